@@ -0,0 +1,182 @@
+//! Address match lists deciding which clients get an answer.
+
+use std::net::IpAddr;
+
+/// One entry of an address match list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    allow: bool,
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+/// Rules like `10.0.0.0/8`, `::1` or `!192.0.2.1`, tried in order with
+/// the first match winning. Clients matching none are refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl {
+    rules: Vec<Rule>,
+}
+
+impl Default for Acl {
+    fn default() -> Acl {
+        Acl::any()
+    }
+}
+
+impl Acl {
+    /// Lets every client through.
+    pub fn any() -> Acl {
+        Acl {
+            rules: everything(true).to_vec(),
+        }
+    }
+
+    /// Reads a list of rules. `any` matches every address, `none` none,
+    /// and a leading `!` turns a match into a refusal.
+    pub fn parse<S: AsRef<str>>(rules: &[S]) -> Result<Acl, String> {
+        let mut parsed = Vec::new();
+        for rule in rules {
+            let rule = rule.as_ref().trim();
+            let (allow, network) = match rule.strip_prefix('!') {
+                Some(network) => (false, network.trim()),
+                None => (true, rule),
+            };
+
+            match network {
+                "any" => parsed.extend(everything(allow)),
+                "none" => {}
+                _ => {
+                    let (addr, prefix_len) = match network.split_once('/') {
+                        Some((addr, len)) => (addr, Some(len)),
+                        None => (network, None),
+                    };
+                    let network: IpAddr = addr.parse().map_err(|_| format!("bad address in `{}`", rule))?;
+                    let max = if network.is_ipv4() { 32 } else { 128 };
+                    let prefix_len = match prefix_len {
+                        None => max,
+                        Some(len) => len
+                            .parse()
+                            .ok()
+                            .filter(|len| *len <= max)
+                            .ok_or_else(|| format!("bad prefix length in `{}`", rule))?,
+                    };
+                    parsed.push(Rule {
+                        allow,
+                        network,
+                        prefix_len,
+                    });
+                }
+            }
+        }
+
+        Ok(Acl { rules: parsed })
+    }
+
+    pub fn allows(&self, client: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack socket show up as mapped addresses.
+        let client = client.to_canonical();
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(client))
+            .is_some_and(|rule| rule.allow)
+    }
+}
+
+/// Rules matching every IPv4 and IPv6 address.
+fn everything(allow: bool) -> [Rule; 2] {
+    [
+        Rule {
+            allow,
+            network: IpAddr::from([0u8; 4]),
+            prefix_len: 0,
+        },
+        Rule {
+            allow,
+            network: IpAddr::from([0u8; 16]),
+            prefix_len: 0,
+        },
+    ]
+}
+
+impl Rule {
+    fn matches(&self, client: IpAddr) -> bool {
+        let (network, client) = match (self.network, client) {
+            (IpAddr::V4(network), IpAddr::V4(client)) => (u32::from(network) as u128, u32::from(client) as u128),
+            (IpAddr::V6(network), IpAddr::V6(client)) => (u128::from(network), u128::from(client)),
+            _ => return false,
+        };
+        let bits = if self.network.is_ipv4() { 32 } else { 128 };
+        let shift = bits - self.prefix_len as u32;
+
+        shift >= bits || network >> shift == client >> shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let acl = Acl::parse(&["!10.1.0.0/16", "10.0.0.0/8", "2001:db8::/32", "192.0.2.1"]).unwrap();
+
+        assert!(acl.allows(ip("10.2.3.4")));
+        assert!(!acl.allows(ip("10.1.3.4")));
+        assert!(acl.allows(ip("192.0.2.1")));
+        assert!(!acl.allows(ip("192.0.2.2")));
+        assert!(acl.allows(ip("2001:db8:1::53")));
+        assert!(!acl.allows(ip("2001:db9::53")));
+        // Clients matching no rule are refused.
+        assert!(!acl.allows(ip("::1")));
+    }
+
+    #[test]
+    fn any_and_none_cover_both_families() {
+        let any = Acl::parse(&["any"]).unwrap();
+        assert_eq!(any, Acl::any());
+        assert_eq!(any, Acl::default());
+        assert!(any.allows(ip("203.0.113.7")) && any.allows(ip("2001:db8::1")));
+
+        let none = Acl::parse(&["none"]).unwrap();
+        assert!(!none.allows(ip("203.0.113.7")) && !none.allows(ip("2001:db8::1")));
+        assert!(!Acl::parse::<&str>(&[]).unwrap().allows(ip("127.0.0.1")));
+
+        let all_but_one = Acl::parse(&["! 192.0.2.1", "any"]).unwrap();
+        assert!(!all_but_one.allows(ip("192.0.2.1")));
+        assert!(all_but_one.allows(ip("192.0.2.2")));
+        assert!(!Acl::parse(&["!any", "192.0.2.1"]).unwrap().allows(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn mapped_clients_match_ipv4_rules() {
+        let acl = Acl::parse(&["127.0.0.0/8"]).unwrap();
+        assert!(acl.allows(ip("::ffff:127.0.0.1")));
+        assert!(!acl.allows(ip("::ffff:192.0.2.1")));
+        // IPv6 rules do not match IPv4 clients, nor the other way round.
+        assert!(!Acl::parse(&["::/1"]).unwrap().allows(ip("10.0.0.1")));
+        assert!(!Acl::parse(&["0.0.0.0/1"]).unwrap().allows(ip("::1")));
+    }
+
+    #[test]
+    fn prefix_lengths_are_checked() {
+        assert!(Acl::parse(&["0.0.0.0/0"]).unwrap().allows(ip("255.255.255.255")));
+        assert!(Acl::parse(&["192.0.2.128/25"]).unwrap().allows(ip("192.0.2.200")));
+        assert!(!Acl::parse(&["192.0.2.128/25"]).unwrap().allows(ip("192.0.2.100")));
+        assert!(Acl::parse(&["2001:db8::1/128"]).unwrap().allows(ip("2001:db8::1")));
+
+        for (rule, error) in [
+            ("10.0.0.0/33", "bad prefix length in `10.0.0.0/33`"),
+            ("::/129", "bad prefix length in `::/129`"),
+            ("10.0.0.0/", "bad prefix length in `10.0.0.0/`"),
+            ("10.0.0.0/x", "bad prefix length in `10.0.0.0/x`"),
+            ("example.com", "bad address in `example.com`"),
+            ("!10.0.0", "bad address in `!10.0.0`"),
+        ] {
+            assert_eq!(Acl::parse(&[rule]).unwrap_err(), error);
+        }
+    }
+}
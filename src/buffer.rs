@@ -0,0 +1,660 @@
+use std::collections::HashMap;
+
+use crate::error::{Result, WireError};
+
+/// Largest message DNS can carry, bounded by the 16 bit TCP length prefix.
+pub const MAX_MESSAGE_SIZE: usize = 65535;
+
+/// Size of a classic UDP message without EDNS.
+pub const UDP_MESSAGE_SIZE: usize = 512;
+
+/// UDP payload size advertised with EDNS, small enough to avoid IP
+/// fragmentation on common paths (DNS flag day 2020).
+pub const EDNS_PAYLOAD_SIZE: u16 = 1232;
+
+/// Byte-level access to a DNS message. Implementors only provide the raw
+/// primitives, the wire encoding helpers are shared.
+pub trait PacketBuffer {
+    fn read(&mut self) -> Result<u8>;
+    fn get(&self, pos: usize) -> Result<u8>;
+    fn get_range(&self, start: usize, len: usize) -> Result<&[u8]>;
+    fn write(&mut self, val: u8) -> Result<()>;
+    fn set(&mut self, pos: usize, val: u8) -> Result<()>;
+    fn pos(&self) -> usize;
+    fn seek(&mut self, pos: usize) -> Result<()>;
+    fn step(&mut self, steps: usize) -> Result<()>;
+
+    /// Discards everything written from `pos` onwards, including any
+    /// compression targets in that range.
+    fn truncate(&mut self, pos: usize) -> Result<()>;
+
+    /// Offset of a name already written to the buffer, if it can be
+    /// pointed to. Buffers that do not track names never compress.
+    fn find_name(&self, _name: &str) -> Option<usize> {
+        None
+    }
+
+    /// Records that `name` was written at `pos`.
+    fn save_name(&mut self, _name: String, _pos: usize) {}
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let byte1 = self.read()? as u16;
+        let byte2 = self.read()? as u16;
+
+        let bytes = byte1 << 8 | byte2;
+
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let byte1 = self.read()? as u32;
+        let byte2 = self.read()? as u32;
+        let byte3 = self.read()? as u32;
+        let byte4 = self.read()? as u32;
+
+        let bytes = byte1 << 24 | byte2 << 16 | byte3 << 8 | byte4;
+
+        Ok(bytes)
+    }
+
+    fn read_qname(&mut self, outstr: &mut String) -> Result<()> {
+        let start = self.pos();
+        let mut pos = start;
+
+        let mut jumped = false;
+        let max_jumps = 5;
+        let mut jumps_performed = 0;
+
+        // Wire length of the name, the terminating root label included.
+        let mut name_len = 1;
+        let mut delim = "";
+
+        loop {
+            if jumps_performed > max_jumps {
+                return Err(WireError::PointerLoop { pos: start });
+            }
+            let len = self.get(pos)?;
+            match len & 0xC0 {
+                0xC0 => {
+                    if !jumped {
+                        self.seek(pos + 2)?;
+                    }
+
+                    let b2 = self.get(pos + 1)? as u16;
+                    let offset = ((((len as u16) ^ 0xC0) << 8) | b2) as usize;
+                    // Pointers may only refer to earlier data (RFC 1035 4.1.4).
+                    if offset >= pos {
+                        return Err(WireError::BadPointer { pos, target: offset });
+                    }
+                    pos = offset;
+
+                    jumped = true;
+                    jumps_performed += 1;
+                    continue;
+                }
+                0x00 => {}
+                _ => return Err(WireError::BadLabelType { pos, byte: len }),
+            }
+
+            pos += 1;
+
+            if len == 0 {
+                break;
+            }
+
+            name_len += len as usize + 1;
+            if name_len > 255 {
+                return Err(WireError::NameTooLong { pos: start, len: name_len });
+            }
+
+            outstr.push_str(delim);
+            let str_buffer = self.get_range(pos, len as usize)?;
+            outstr.push_str(&String::from_utf8_lossy(str_buffer).to_lowercase());
+
+            delim = ".";
+
+            pos += len as usize;
+        }
+
+        if !jumped {
+            self.seek(pos)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_u8(&mut self, val: u8) -> Result<()> {
+        self.write(val)?;
+
+        Ok(())
+    }
+
+    fn write_u16(&mut self, val: u16) -> Result<()> {
+        self.write((val >> 8) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+
+        Ok(())
+    }
+
+    fn write_u32(&mut self, val: u32) -> Result<()> {
+        self.write(((val >> 24) & 0xFF) as u8)?;
+        self.write(((val >> 16) & 0xFF) as u8)?;
+        self.write(((val >> 8) & 0xFF) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+
+        Ok(())
+    }
+
+    /// Writes `qname`, replacing the longest suffix already present in the
+    /// buffer with a compression pointer (RFC 1035 4.1.4).
+    fn write_qname(&mut self, qname: &str) -> Result<()> {
+        let labels: Vec<&str> = qname.split('.').filter(|label| !label.is_empty()).collect();
+        check_name_len(&labels, self.pos())?;
+
+        for i in 0..labels.len() {
+            let suffix = labels[i..].join(".").to_ascii_lowercase();
+            if let Some(offset) = self.find_name(&suffix) {
+                self.write_u16(0xC000 | offset as u16)?;
+                return Ok(());
+            }
+
+            let pos = self.pos();
+            self.write_label(labels[i])?;
+            if pos < 0x4000 {
+                self.save_name(suffix, pos);
+            }
+        }
+
+        self.write_u8(0)?;
+
+        Ok(())
+    }
+
+    /// Writes `qname` in full, for RDATA where compression is not allowed
+    /// (RFC 3597 section 4), such as the SRV target.
+    fn write_uncompressed_qname(&mut self, qname: &str) -> Result<()> {
+        let labels: Vec<&str> = qname.split('.').filter(|label| !label.is_empty()).collect();
+        check_name_len(&labels, self.pos())?;
+
+        for label in labels {
+            self.write_label(label)?;
+        }
+
+        self.write_u8(0)?;
+
+        Ok(())
+    }
+
+    fn write_label(&mut self, label: &str) -> Result<()> {
+        let len = label.len();
+        if len > 0x3f {
+            return Err(WireError::LabelTooLong { pos: self.pos(), len });
+        }
+
+        self.write_u8(len as u8)?;
+        for b in label.as_bytes() {
+            self.write_u8(*b)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
+        self.set(pos, (val >> 8) as u8)?;
+        self.set(pos + 1, (val & 0xFF) as u8)?;
+
+        Ok(())
+    }
+}
+
+/// Fails if `labels` take more than 255 bytes in wire format.
+fn check_name_len(labels: &[&str], pos: usize) -> Result<()> {
+    let len = labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+    if len > 255 {
+        return Err(WireError::NameTooLong { pos, len });
+    }
+
+    Ok(())
+}
+
+/// Fixed capacity buffer, allocated once. Used for UDP where the message
+/// size is bounded by the transport (512 bytes, or the EDNS payload size).
+pub struct BytePacketBuffer {
+    pub buf: Vec<u8>,
+    pub pos: usize,
+    names: HashMap<String, usize>,
+}
+
+impl BytePacketBuffer {
+    pub fn new() -> BytePacketBuffer {
+        BytePacketBuffer::with_capacity(UDP_MESSAGE_SIZE)
+    }
+
+    pub fn with_capacity(capacity: usize) -> BytePacketBuffer {
+        BytePacketBuffer {
+            buf: vec![0; capacity.min(MAX_MESSAGE_SIZE)],
+            pos: 0,
+            names: HashMap::new(),
+        }
+    }
+}
+
+impl Default for BytePacketBuffer {
+    fn default() -> BytePacketBuffer {
+        BytePacketBuffer::new()
+    }
+}
+
+impl PacketBuffer for BytePacketBuffer {
+    fn read(&mut self) -> Result<u8> {
+        let byte = self.get(self.pos)?;
+        self.pos += 1;
+
+        Ok(byte)
+    }
+
+    fn get(&self, pos: usize) -> Result<u8> {
+        self.buf.get(pos).copied().ok_or(WireError::Truncated { pos })
+    }
+
+    fn get_range(&self, start: usize, len: usize) -> Result<&[u8]> {
+        self.buf.get(start..start + len).ok_or(WireError::Truncated { pos: start })
+    }
+
+    fn write(&mut self, val: u8) -> Result<()> {
+        self.set(self.pos, val)?;
+        self.pos += 1;
+
+        Ok(())
+    }
+
+    fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+        let capacity = self.buf.len();
+        *self.buf.get_mut(pos).ok_or(WireError::BufferOverflow { pos, capacity })? = val;
+
+        Ok(())
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        self.pos = pos;
+
+        Ok(())
+    }
+
+    fn step(&mut self, steps: usize) -> Result<()> {
+        self.pos += steps;
+
+        Ok(())
+    }
+
+    fn truncate(&mut self, pos: usize) -> Result<()> {
+        self.names.retain(|_, name_pos| *name_pos < pos);
+        self.pos = pos;
+
+        Ok(())
+    }
+
+    fn find_name(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    fn save_name(&mut self, name: String, pos: usize) {
+        self.names.insert(name, pos);
+    }
+}
+
+/// Heap-backed buffer that grows as it is written to, up to the 65535
+/// bytes a TCP message can hold.
+pub struct VectorPacketBuffer {
+    pub buf: Vec<u8>,
+    pub pos: usize,
+    names: HashMap<String, usize>,
+    compress: bool,
+}
+
+impl VectorPacketBuffer {
+    pub fn new() -> VectorPacketBuffer {
+        VectorPacketBuffer {
+            buf: Vec::new(),
+            pos: 0,
+            names: HashMap::new(),
+            compress: true,
+        }
+    }
+
+    /// A buffer that writes every name in full, as the canonical form
+    /// DNSSEC signs requires (RFC 4034 section 6.2).
+    pub fn uncompressed() -> VectorPacketBuffer {
+        VectorPacketBuffer {
+            compress: false,
+            ..VectorPacketBuffer::new()
+        }
+    }
+}
+
+impl Default for VectorPacketBuffer {
+    fn default() -> VectorPacketBuffer {
+        VectorPacketBuffer::new()
+    }
+}
+
+impl PacketBuffer for VectorPacketBuffer {
+    fn read(&mut self) -> Result<u8> {
+        let byte = self.get(self.pos)?;
+        self.pos += 1;
+
+        Ok(byte)
+    }
+
+    fn get(&self, pos: usize) -> Result<u8> {
+        self.buf.get(pos).copied().ok_or(WireError::Truncated { pos })
+    }
+
+    fn get_range(&self, start: usize, len: usize) -> Result<&[u8]> {
+        self.buf.get(start..start + len).ok_or(WireError::Truncated { pos: start })
+    }
+
+    fn write(&mut self, val: u8) -> Result<()> {
+        if self.pos >= MAX_MESSAGE_SIZE {
+            return Err(WireError::BufferOverflow {
+                pos: self.pos,
+                capacity: MAX_MESSAGE_SIZE,
+            });
+        }
+        if self.pos >= self.buf.len() {
+            self.buf.resize(self.pos + 1, 0);
+        }
+        self.buf[self.pos] = val;
+        self.pos += 1;
+
+        Ok(())
+    }
+
+    fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+        let capacity = self.buf.len();
+        *self.buf.get_mut(pos).ok_or(WireError::BufferOverflow { pos, capacity })? = val;
+
+        Ok(())
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        self.pos = pos;
+
+        Ok(())
+    }
+
+    fn step(&mut self, steps: usize) -> Result<()> {
+        self.pos += steps;
+
+        Ok(())
+    }
+
+    fn truncate(&mut self, pos: usize) -> Result<()> {
+        self.names.retain(|_, name_pos| *name_pos < pos);
+        self.buf.truncate(pos);
+        self.pos = pos;
+
+        Ok(())
+    }
+
+    fn find_name(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    fn save_name(&mut self, name: String, pos: usize) {
+        if self.compress {
+            self.names.insert(name, pos);
+        }
+    }
+}
+
+/// Read-only view over a received message, parsed without copying it.
+pub struct SlicePacketBuffer<'a> {
+    pub buf: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> SlicePacketBuffer<'a> {
+    pub fn new(buf: &'a [u8]) -> SlicePacketBuffer<'a> {
+        SlicePacketBuffer { buf, pos: 0 }
+    }
+}
+
+impl PacketBuffer for SlicePacketBuffer<'_> {
+    fn read(&mut self) -> Result<u8> {
+        let byte = self.get(self.pos)?;
+        self.pos += 1;
+
+        Ok(byte)
+    }
+
+    fn get(&self, pos: usize) -> Result<u8> {
+        self.buf.get(pos).copied().ok_or(WireError::Truncated { pos })
+    }
+
+    fn get_range(&self, start: usize, len: usize) -> Result<&[u8]> {
+        self.buf.get(start..start + len).ok_or(WireError::Truncated { pos: start })
+    }
+
+    fn write(&mut self, _val: u8) -> Result<()> {
+        Err(WireError::ReadOnly)
+    }
+
+    fn set(&mut self, _pos: usize, _val: u8) -> Result<()> {
+        Err(WireError::ReadOnly)
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        self.pos = pos;
+
+        Ok(())
+    }
+
+    fn step(&mut self, steps: usize) -> Result<()> {
+        self.pos += steps;
+
+        Ok(())
+    }
+
+    fn truncate(&mut self, _pos: usize) -> Result<()> {
+        Err(WireError::ReadOnly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet;
+    use crate::query::QueryClass;
+    use crate::record::Record;
+
+    #[test]
+    fn vector_buffer_grows_up_to_the_largest_message() {
+        let mut buffer = VectorPacketBuffer::new();
+        for i in 0..MAX_MESSAGE_SIZE {
+            buffer.write_u8(i as u8).unwrap();
+        }
+        assert_eq!(buffer.buf.len(), MAX_MESSAGE_SIZE);
+        assert_eq!(
+            buffer.write_u8(0),
+            Err(WireError::BufferOverflow {
+                pos: MAX_MESSAGE_SIZE,
+                capacity: MAX_MESSAGE_SIZE
+            })
+        );
+
+        buffer.truncate(600).unwrap();
+        assert_eq!(buffer.buf.len(), 600);
+        assert_eq!(buffer.get(599), Ok((599 % 256) as u8));
+        assert_eq!(buffer.get(600), Err(WireError::Truncated { pos: 600 }));
+    }
+
+    #[test]
+    fn byte_buffer_has_a_fixed_capacity() {
+        let mut buffer = BytePacketBuffer::new();
+        buffer.seek(UDP_MESSAGE_SIZE - 2).unwrap();
+        buffer.write_u16(0xabcd).unwrap();
+        assert_eq!(
+            buffer.write_u8(0),
+            Err(WireError::BufferOverflow {
+                pos: UDP_MESSAGE_SIZE,
+                capacity: UDP_MESSAGE_SIZE
+            })
+        );
+
+        assert_eq!(BytePacketBuffer::with_capacity(1 << 20).buf.len(), MAX_MESSAGE_SIZE);
+    }
+
+    #[test]
+    fn slice_buffer_only_reads() {
+        let data = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        let mut buffer = SlicePacketBuffer::new(&data);
+        assert_eq!(buffer.read_u32(), Ok(0x12345678));
+        assert_eq!(buffer.read_u16(), Err(WireError::Truncated { pos: 5 }));
+        assert_eq!(buffer.get_range(3, 3), Err(WireError::Truncated { pos: 3 }));
+        assert_eq!(buffer.write_u8(0), Err(WireError::ReadOnly));
+        assert_eq!(buffer.truncate(0), Err(WireError::ReadOnly));
+    }
+
+    #[test]
+    fn large_messages_need_a_large_buffer() {
+        let mut packet = Packet::new();
+        for i in 0..200u32 {
+            packet.answers.push(Record::A {
+                domain: format!("host{}.example.com", i),
+                class: QueryClass::IN,
+                addr: [10, 0, (i >> 8) as u8, i as u8].into(),
+                ttl: 300,
+            });
+        }
+
+        let mut buffer = VectorPacketBuffer::new();
+        packet.write(&mut buffer).unwrap();
+        assert!(buffer.buf.len() > UDP_MESSAGE_SIZE);
+        let read = Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf)).unwrap();
+        assert_eq!(read.answers, packet.answers);
+
+        assert!(packet.write(&mut BytePacketBuffer::new()).is_err());
+        packet.write(&mut BytePacketBuffer::with_capacity(buffer.buf.len())).unwrap();
+    }
+
+    #[test]
+    fn names_point_to_the_longest_suffix_written() {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.write_qname("www.example.com").unwrap();
+        buffer.write_qname("WWW.Example.COM").unwrap();
+        buffer.write_qname("mail.example.com").unwrap();
+        buffer.write_qname("com").unwrap();
+
+        let mut expected = b"\x03www\x07example\x03com\x00".to_vec();
+        expected.extend_from_slice(&[0xc0, 0x00]);
+        expected.extend_from_slice(b"\x04mail\xc0\x04");
+        expected.extend_from_slice(&[0xc0, 0x0c]);
+        assert_eq!(buffer.buf, expected);
+
+        let mut reader = SlicePacketBuffer::new(&buffer.buf);
+        for name in ["www.example.com", "www.example.com", "mail.example.com", "com"] {
+            let mut read = String::new();
+            reader.read_qname(&mut read).unwrap();
+            assert_eq!(read, name);
+        }
+        assert_eq!(reader.pos, buffer.buf.len());
+    }
+
+    #[test]
+    fn names_are_written_in_full_where_compression_is_not_allowed() {
+        let mut buffer = VectorPacketBuffer::uncompressed();
+        buffer.write_qname("example.com").unwrap();
+        buffer.write_qname("example.com").unwrap();
+        assert_eq!(buffer.buf.len(), 2 * 13);
+
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.write_qname("example.com").unwrap();
+        buffer.write_uncompressed_qname("example.com").unwrap();
+        assert_eq!(buffer.buf.len(), 2 * 13);
+
+        // Nothing past the 14 bit pointer range is remembered.
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.seek(0x4000).unwrap();
+        buffer.write_qname("example.com").unwrap();
+        buffer.write_qname("example.com").unwrap();
+        assert_eq!(buffer.buf.len(), 0x4000 + 2 * 13);
+    }
+
+    #[test]
+    fn truncating_forgets_the_names_cut_off() {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.write_qname("example.com").unwrap();
+        let end = buffer.pos();
+        buffer.write_qname("www.example.org").unwrap();
+        buffer.truncate(end).unwrap();
+
+        buffer.write_qname("example.org").unwrap();
+        assert_eq!(&buffer.buf[end..], b"\x07example\x03org\x00");
+    }
+
+    fn read_name(data: &[u8], pos: usize) -> Result<String> {
+        let mut buffer = SlicePacketBuffer::new(data);
+        buffer.seek(pos)?;
+        let mut name = String::new();
+        buffer.read_qname(&mut name)?;
+        Ok(name)
+    }
+
+    #[test]
+    fn malformed_names_say_what_is_wrong_and_where() {
+        let mut data = vec![0; 12];
+        data.extend_from_slice(b"\x01a\x00");
+        assert_eq!(read_name(&data, 12), Ok("a".to_string()));
+
+        assert_eq!(read_name(&[0xc0, 0x00], 0), Err(WireError::BadPointer { pos: 0, target: 0 }));
+        assert_eq!(read_name(&[0xc0, 0x02, 0x00], 0), Err(WireError::BadPointer { pos: 0, target: 2 }));
+        assert_eq!(read_name(&[0x41, 0x00], 0), Err(WireError::BadLabelType { pos: 0, byte: 0x41 }));
+        assert_eq!(read_name(&[0x80, 0x00], 0), Err(WireError::BadLabelType { pos: 0, byte: 0x80 }));
+        assert_eq!(read_name(b"\x03ab", 0), Err(WireError::Truncated { pos: 1 }));
+        assert_eq!(read_name(b"\x01a", 0), Err(WireError::Truncated { pos: 2 }));
+
+        let mut long = Vec::new();
+        for _ in 0..5 {
+            long.push(63);
+            long.extend_from_slice(&[b'a'; 63]);
+        }
+        long.push(0);
+        assert_eq!(read_name(&long, 0), Err(WireError::NameTooLong { pos: 0, len: 257 }));
+
+        // Pointers to pointers, each going backwards, are followed five deep.
+        let mut chain = b"\x01a\x00".to_vec();
+        for i in 0..6 {
+            let target = if i == 0 { 0 } else { 3 + 2 * (i - 1) };
+            chain.extend_from_slice(&[0xc0, target]);
+        }
+        assert_eq!(read_name(&chain, 3 + 2 * 4), Ok("a".to_string()));
+        assert_eq!(read_name(&chain, 3 + 2 * 5), Err(WireError::PointerLoop { pos: 13 }));
+    }
+
+    #[test]
+    fn names_that_cannot_be_encoded_are_refused() {
+        let mut buffer = VectorPacketBuffer::new();
+        assert_eq!(
+            buffer.write_qname(&"a".repeat(64)),
+            Err(WireError::LabelTooLong { pos: 0, len: 64 })
+        );
+
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(buffer.write_qname(&long), Err(WireError::NameTooLong { pos: 0, len: 257 }));
+        assert_eq!(buffer.pos(), 0);
+        buffer.write_qname(&long[2..]).unwrap();
+    }
+}
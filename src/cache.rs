@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::dnssec::label_count;
+use crate::query::{QueryClass, QueryType};
+use crate::record::Record;
+use crate::rescode::ResultCode;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub name: String,
+    /// `None` stands for every type of the name, which is how NXDOMAIN
+    /// entries are stored.
+    pub qtype: Option<QueryType>,
+    pub class: QueryClass,
+}
+
+impl CacheKey {
+    pub fn new(name: &str, qtype: Option<QueryType>, class: QueryClass) -> CacheKey {
+        CacheKey {
+            name: name.to_ascii_lowercase(),
+            qtype,
+            class,
+        }
+    }
+}
+
+/// A cached denial. It holds the SOA record, followed by any NSEC or
+/// NSEC3 records and signatures that prove it (RFC 4035 3.1.3).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Negative {
+    /// The name does not exist at all.
+    NxDomain(Vec<Record>),
+    /// The name exists but has no records of the requested type.
+    NoData(Vec<Record>),
+}
+
+impl Negative {
+    pub fn rcode(&self) -> ResultCode {
+        match self {
+            Negative::NxDomain(_) => ResultCode::NXDOMAIN,
+            Negative::NoData(_) => ResultCode::NOERROR,
+        }
+    }
+
+    /// The SOA record to put in the authority section.
+    pub fn soa(&self) -> &Record {
+        &self.records()[0]
+    }
+
+    /// The records to put in the authority section.
+    pub fn records(&self) -> &[Record] {
+        match self {
+            Negative::NxDomain(records) | Negative::NoData(records) => records,
+        }
+    }
+}
+
+enum CacheData {
+    Records(Vec<Record>),
+    Negative(Negative),
+}
+
+struct CacheEntry {
+    data: CacheData,
+    expires: Instant,
+    last_used: u64,
+}
+
+/// RRset cache with TTL expiry and least-recently-used eviction once
+/// `max_entries` RRsets are stored. Negative answers are cached per
+/// RFC 2308 alongside the positive ones and count towards the same limit.
+pub struct Cache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    max_entries: usize,
+}
+
+impl Cache {
+    pub fn new(max_entries: usize) -> Cache {
+        Cache {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            max_entries,
+        }
+    }
+
+    /// Stores every RRset found in `records`, grouped by owner and type.
+    /// The whole set expires with its lowest TTL. Signatures are kept with
+    /// the RRset they cover, and dropped if it is not among `records`.
+    /// RRsets expanded from a wildcard are left out, as the proof that the
+    /// expansion was valid is not kept with them.
+    pub fn insert(&mut self, records: &[Record]) {
+        let mut sets: HashMap<CacheKey, Vec<Record>> = HashMap::new();
+        for record in records.iter().filter(|record| record.qtype() != QueryType::RRSIG) {
+            sets.entry(CacheKey::new(record.domain(), Some(record.qtype()), record.class()))
+                .or_default()
+                .push(record.clone());
+        }
+        for record in records {
+            if let Record::RRSIG { type_covered, .. } = record {
+                let key = CacheKey::new(record.domain(), Some(*type_covered), record.class());
+                if let Some(set) = sets.get_mut(&key) {
+                    set.push(record.clone());
+                }
+            }
+        }
+        sets.retain(|_, set| {
+            !set.iter().any(|record| {
+                matches!(record, Record::RRSIG { labels, .. } if (*labels as usize) < label_count(record.domain()))
+            })
+        });
+
+        for (key, records) in sets {
+            let ttl = records.iter().map(|r| r.ttl()).min().unwrap_or(0);
+
+            // The name evidently exists now.
+            self.remove(&CacheKey::new(&key.name, None, key.class));
+            self.insert_entry(key, CacheData::Records(records), ttl);
+        }
+    }
+
+    /// Caches a negative answer for `qname` in `class`. The SOA record is taken from
+    /// `authorities` and, per RFC 2308, the entry lives for the lesser of
+    /// its TTL and its MINIMUM field. Without a SOA nothing is cached.
+    /// NSEC and NSEC3 records and signatures in `authorities` are kept so
+    /// the denial can still be validated.
+    pub fn insert_negative(
+        &mut self,
+        qname: &str,
+        qtype: QueryType,
+        class: QueryClass,
+        rcode: ResultCode,
+        authorities: &[Record],
+    ) {
+        let soa = authorities.iter().find_map(|record| match record {
+            Record::SOA { minimum, ttl, .. } => Some((record, (*minimum).min(*ttl))),
+            _ => None,
+        });
+        let (mut soa, ttl) = match soa {
+            Some((soa, ttl)) => (soa.clone(), ttl),
+            None => return,
+        };
+        soa.set_ttl(ttl);
+
+        let proof = authorities
+            .iter()
+            .filter(|record| matches!(record, Record::NSEC { .. } | Record::NSEC3 { .. } | Record::RRSIG { .. }));
+        let records: Vec<Record> = std::iter::once(soa).chain(proof.cloned()).collect();
+
+        let (key, negative) = match rcode {
+            ResultCode::NXDOMAIN => (CacheKey::new(qname, None, class), Negative::NxDomain(records)),
+            ResultCode::NOERROR => (CacheKey::new(qname, Some(qtype), class), Negative::NoData(records)),
+            _ => return,
+        };
+
+        self.insert_entry(key, CacheData::Negative(negative), ttl);
+    }
+
+    /// Returns the cached RRset with TTLs rewritten to the time remaining.
+    pub fn lookup(&mut self, name: &str, qtype: QueryType, class: QueryClass) -> Option<Vec<Record>> {
+        match self.get(&CacheKey::new(name, Some(qtype), class))? {
+            (CacheData::Records(records), ttl) => Some(with_ttl(records, ttl)),
+            (CacheData::Negative(_), _) => None,
+        }
+    }
+
+    /// Returns a cached NXDOMAIN or NODATA for the name, with TTLs
+    /// rewritten to the time remaining.
+    pub fn lookup_negative(&mut self, name: &str, qtype: QueryType, class: QueryClass) -> Option<Negative> {
+        let keys = [
+            CacheKey::new(name, None, class),
+            CacheKey::new(name, Some(qtype), class),
+        ];
+
+        for key in keys {
+            if let Some((CacheData::Negative(negative), ttl)) = self.get(&key) {
+                return Some(match negative {
+                    Negative::NxDomain(records) => Negative::NxDomain(with_ttl(records, ttl)),
+                    Negative::NoData(records) => Negative::NoData(with_ttl(records, ttl)),
+                });
+            }
+        }
+
+        None
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<(&CacheData, u32)> {
+        let now = Instant::now();
+
+        let expired = self.entries.get(key)?.expires <= now;
+        if expired {
+            self.remove(key);
+            return None;
+        }
+
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(self.tick, key.clone());
+        entry.last_used = self.tick;
+
+        // Round up so a record is never served with a TTL of 0 before it
+        // actually expires.
+        let remaining = entry.expires.duration_since(now);
+        let ttl = remaining.as_secs() as u32 + (remaining.subsec_nanos() > 0) as u32;
+
+        Some((&entry.data, ttl))
+    }
+
+    fn insert_entry(&mut self, key: CacheKey, data: CacheData, ttl: u32) {
+        self.remove(&key);
+        if ttl == 0 {
+            return;
+        }
+
+        while self.entries.len() >= self.max_entries {
+            if !self.evict_one() {
+                return;
+            }
+        }
+
+        self.tick += 1;
+        self.lru.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                data,
+                expires: Instant::now() + Duration::from_secs(ttl as u64),
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+        }
+    }
+
+    fn evict_one(&mut self) -> bool {
+        match self.lru.pop_first() {
+            Some((_, key)) => {
+                self.entries.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn with_ttl(records: &[Record], ttl: u32) -> Vec<Record> {
+    let mut records = records.to_vec();
+    for record in records.iter_mut() {
+        record.set_ttl(ttl);
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn a(domain: &str, class: QueryClass, ttl: u32) -> Record {
+        Record::A {
+            domain: domain.to_string(),
+            class,
+            addr: "192.0.2.1".parse().unwrap(),
+            ttl,
+        }
+    }
+
+    fn soa(ttl: u32, minimum: u32) -> Record {
+        Record::SOA {
+            domain: "example.com".to_string(),
+            class: QueryClass::IN,
+            mname: "ns.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum,
+            ttl,
+        }
+    }
+
+    #[test]
+    fn ttl_counts_down_until_expiry() {
+        let mut cache = Cache::new(10);
+        cache.insert(&[a("short.example.com", QueryClass::IN, 1), a("long.example.com", QueryClass::IN, 300)]);
+
+        let records = cache.lookup("long.example.com", QueryType::A, QueryClass::IN).unwrap();
+        assert_eq!(records[0].ttl(), 300);
+        assert!(cache.lookup("short.example.com", QueryType::A, QueryClass::IN).is_some());
+
+        thread::sleep(Duration::from_millis(1100));
+        assert!(cache.lookup("short.example.com", QueryType::A, QueryClass::IN).is_none());
+        let records = cache.lookup("long.example.com", QueryType::A, QueryClass::IN).unwrap();
+        assert_eq!(records[0].ttl(), 299);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = Cache::new(2);
+        cache.insert(&[a("one.example.com", QueryClass::IN, 300)]);
+        cache.insert(&[a("two.example.com", QueryClass::IN, 300)]);
+
+        // Reading the first entry makes the second the oldest.
+        assert!(cache.lookup("one.example.com", QueryType::A, QueryClass::IN).is_some());
+        cache.insert(&[a("three.example.com", QueryClass::IN, 300)]);
+
+        assert!(cache.lookup("two.example.com", QueryType::A, QueryClass::IN).is_none());
+        assert!(cache.lookup("one.example.com", QueryType::A, QueryClass::IN).is_some());
+        assert!(cache.lookup("three.example.com", QueryType::A, QueryClass::IN).is_some());
+    }
+
+    #[test]
+    fn names_are_case_insensitive_and_classes_separate() {
+        let mut cache = Cache::new(10);
+        cache.insert(&[a("www.example.com", QueryClass::CH, 300)]);
+
+        assert!(cache.lookup("WWW.Example.COM", QueryType::A, QueryClass::CH).is_some());
+        assert!(cache.lookup("www.example.com", QueryType::A, QueryClass::IN).is_none());
+    }
+
+    #[test]
+    fn negative_answers_live_for_the_soa_minimum() {
+        let mut cache = Cache::new(10);
+        cache.insert_negative("gone.example.com", QueryType::A, QueryClass::IN, ResultCode::NXDOMAIN, &[soa(3600, 60)]);
+        cache.insert_negative("www.example.com", QueryType::MX, QueryClass::IN, ResultCode::NOERROR, &[soa(30, 60)]);
+
+        // NXDOMAIN covers every type of the name.
+        let negative = cache.lookup_negative("gone.example.com", QueryType::AAAA, QueryClass::IN).unwrap();
+        assert_eq!(negative.rcode(), ResultCode::NXDOMAIN);
+        assert_eq!(negative.soa().ttl(), 60);
+        assert!(cache.lookup_negative("gone.example.com", QueryType::A, QueryClass::CH).is_none());
+
+        let negative = cache.lookup_negative("www.example.com", QueryType::MX, QueryClass::IN).unwrap();
+        assert_eq!(negative.rcode(), ResultCode::NOERROR);
+        assert_eq!(negative.soa().ttl(), 30);
+        assert!(cache.lookup_negative("www.example.com", QueryType::A, QueryClass::IN).is_none());
+
+        // Without a SOA there is nothing to bound the entry's life.
+        cache.insert_negative("other.example.com", QueryType::A, QueryClass::IN, ResultCode::NXDOMAIN, &[]);
+        assert!(cache.lookup_negative("other.example.com", QueryType::A, QueryClass::IN).is_none());
+    }
+
+    #[test]
+    fn records_replace_a_cached_nxdomain() {
+        let mut cache = Cache::new(10);
+        cache.insert_negative("new.example.com", QueryType::A, QueryClass::IN, ResultCode::NXDOMAIN, &[soa(300, 300)]);
+        cache.insert(&[a("new.example.com", QueryClass::IN, 300)]);
+
+        assert!(cache.lookup_negative("new.example.com", QueryType::A, QueryClass::IN).is_none());
+        assert!(cache.lookup("new.example.com", QueryType::A, QueryClass::IN).is_some());
+    }
+}
@@ -0,0 +1,72 @@
+use std::env;
+use std::fs;
+
+use crate::query::{QueryClass, QueryType};
+use crate::question::Question;
+use crate::record::Record;
+
+/// Answers the CHAOS class names servers conventionally use to identify
+/// themselves. Returns `None` for any other name.
+pub fn lookup(question: &Question) -> Option<Vec<Record>> {
+    let text = match question.name.to_ascii_lowercase().as_str() {
+        "version.bind" | "version.server" => {
+            format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        }
+        "hostname.bind" | "id.server" => hostname(),
+        _ => return None,
+    };
+
+    if question.qtype != QueryType::TXT {
+        return Some(Vec::new());
+    }
+
+    Some(vec![Record::TXT {
+        domain: question.name.clone(),
+        class: QueryClass::CH,
+        data: vec![text.into_bytes()],
+        ttl: 0,
+    }])
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(name: &str, qtype: QueryType) -> Question {
+        Question {
+            qclass: QueryClass::CH,
+            ..Question::new(name.to_string(), qtype)
+        }
+    }
+
+    #[test]
+    fn identifies_the_server() {
+        let answers = lookup(&question("VERSION.bind", QueryType::TXT)).unwrap();
+        match &answers[..] {
+            [Record::TXT { domain, class, data, .. }] => {
+                assert_eq!(domain, "VERSION.bind");
+                assert_eq!(*class, QueryClass::CH);
+                assert_eq!(data, &vec![format!("my_dns {}", env!("CARGO_PKG_VERSION")).into_bytes()]);
+            }
+            other => panic!("expected one TXT record, found {:?}", other),
+        }
+
+        let answers = lookup(&question("id.server", QueryType::TXT)).unwrap();
+        assert!(matches!(&answers[..], [Record::TXT { data, .. }] if !data[0].is_empty()));
+    }
+
+    #[test]
+    fn other_types_and_names() {
+        assert_eq!(lookup(&question("version.bind", QueryType::A)), Some(Vec::new()));
+        assert_eq!(lookup(&question("authors.bind", QueryType::TXT)), None);
+    }
+}
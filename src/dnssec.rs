@@ -0,0 +1,939 @@
+//! DNSSEC building blocks: key tags, DS digests, the canonical form that
+//! signatures cover (RFC 4034 section 6), signature verification and the
+//! NSEC and NSEC3 proofs of nonexistence.
+
+use std::cmp::Ordering;
+
+use ring::digest;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+
+use crate::buffer::VectorPacketBuffer;
+use crate::encoding::{base32hex_decode, hex_decode};
+use crate::error::Result;
+use crate::packet::is_subdomain;
+use crate::query::{QueryClass, QueryType};
+use crate::record::Record;
+
+/// DNSKEY flag marking a key that signs zone data (RFC 4034 2.1.1).
+pub const ZONE_KEY: u16 = 0x0100;
+/// DNSKEY flag conventionally marking a key signing key.
+pub const SECURE_ENTRY_POINT: u16 = 0x0001;
+/// The only valid DNSKEY protocol value.
+pub const PROTOCOL: u8 = 3;
+/// NSEC3 flag for spans that may hide unsigned delegations (RFC 5155 3.1.2.1).
+pub const OPT_OUT: u8 = 0x01;
+
+pub const RSASHA256: u8 = 8;
+pub const ECDSAP256SHA256: u8 = 13;
+pub const ECDSAP384SHA384: u8 = 14;
+pub const ED25519: u8 = 15;
+
+pub const DIGEST_SHA1: u8 = 1;
+pub const DIGEST_SHA256: u8 = 2;
+pub const DIGEST_SHA384: u8 = 4;
+
+/// NSEC3 records hashed with more iterations are not trusted, as RFC 9276
+/// section 3.2 allows, so they cannot be used to cheaply load the resolver.
+pub const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// The IANA root zone key signing keys, KSK-2017 and KSK-2024, as DS records.
+pub fn root_trust_anchors() -> Vec<Record> {
+    [
+        (20326, "E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D"),
+        (38696, "683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16"),
+    ]
+    .iter()
+    .map(|&(key_tag, digest)| Record::DS {
+        domain: String::new(),
+        class: QueryClass::IN,
+        key_tag,
+        algorithm: RSASHA256,
+        digest_type: DIGEST_SHA256,
+        digest: hex_decode(digest).expect("valid hex"),
+        ttl: 0,
+    })
+    .collect()
+}
+
+pub fn supports_algorithm(algorithm: u8) -> bool {
+    matches!(algorithm, RSASHA256 | ECDSAP256SHA256 | ECDSAP384SHA384 | ED25519)
+}
+
+pub fn supports_digest(digest_type: u8) -> bool {
+    matches!(digest_type, DIGEST_SHA1 | DIGEST_SHA256 | DIGEST_SHA384)
+}
+
+/// The key tag RRSIG and DS records use to refer to a DNSKEY
+/// (RFC 4034 appendix B).
+pub fn key_tag(dnskey: &Record) -> Option<u16> {
+    let rdata = dnskey_rdata(dnskey)?;
+    let mut acc: u32 = 0;
+    for (i, &b) in rdata.iter().enumerate() {
+        acc += if i % 2 == 0 { (b as u32) << 8 } else { b as u32 };
+    }
+    acc += acc >> 16;
+
+    Some(acc as u16)
+}
+
+fn dnskey_rdata(dnskey: &Record) -> Option<Vec<u8>> {
+    match dnskey {
+        Record::DNSKEY {
+            flags,
+            protocol,
+            algorithm,
+            public_key,
+            ..
+        } => {
+            let mut rdata = Vec::with_capacity(public_key.len() + 4);
+            rdata.extend_from_slice(&flags.to_be_bytes());
+            rdata.push(*protocol);
+            rdata.push(*algorithm);
+            rdata.extend_from_slice(public_key);
+            Some(rdata)
+        }
+        _ => None,
+    }
+}
+
+/// The digest a DS record of `digest_type` holds for `dnskey`
+/// (RFC 4034 5.1.4).
+pub fn ds_digest(dnskey: &Record, digest_type: u8) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        DIGEST_SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        DIGEST_SHA256 => &digest::SHA256,
+        DIGEST_SHA384 => &digest::SHA384,
+        _ => return None,
+    };
+
+    let mut context = digest::Context::new(algorithm);
+    context.update(&canonical_name(dnskey.domain()));
+    context.update(&dnskey_rdata(dnskey)?);
+
+    Some(context.finish().as_ref().to_vec())
+}
+
+/// Whether `ds` refers to `dnskey`.
+pub fn ds_matches(ds: &Record, dnskey: &Record) -> bool {
+    let Record::DS {
+        key_tag: tag,
+        algorithm,
+        digest_type,
+        digest,
+        ..
+    } = ds
+    else {
+        return false;
+    };
+
+    matches!(dnskey, Record::DNSKEY { algorithm: key_algorithm, .. } if key_algorithm == algorithm)
+        && dnskey.domain().eq_ignore_ascii_case(ds.domain())
+        && key_tag(dnskey) == Some(*tag)
+        && ds_digest(dnskey, *digest_type).as_ref() == Some(digest)
+}
+
+/// `name` in wire format, lowercase and uncompressed.
+pub fn canonical_name(name: &str) -> Vec<u8> {
+    let mut wire = Vec::with_capacity(name.len() + 2);
+    for label in labels(name) {
+        wire.push(label.len() as u8);
+        wire.extend(label.bytes().map(|b| b.to_ascii_lowercase()));
+    }
+    wire.push(0);
+
+    wire
+}
+
+/// Canonical DNS name order (RFC 4034 6.1): label by label from the root,
+/// each compared as lowercase bytes, with a name sorting before its
+/// subdomains.
+pub fn canonical_cmp(a: &str, b: &str) -> Ordering {
+    let a = labels(a).rev().map(|label| label.to_ascii_lowercase());
+    let b = labels(b).rev().map(|label| label.to_ascii_lowercase());
+
+    a.map(String::into_bytes).cmp(b.map(String::into_bytes))
+}
+
+/// Canonical order of records (RFC 4034 6.3): by owner, then type, then
+/// RDATA in canonical form. Signatures follow the RRset they cover, as
+/// in signed zone files.
+pub fn canonical_order(a: &Record, b: &Record) -> Ordering {
+    let rank = |record: &Record| match *record {
+        Record::RRSIG { type_covered, .. } => (type_covered.to_num(), true),
+        _ => (record.qtype().to_num(), false),
+    };
+    // Written under the root, the RDATA starts after 11 bytes.
+    let rdata = |record: &Record| canonical_record(record, "", 0).map(|wire| wire[11..].to_vec()).unwrap_or_default();
+
+    canonical_cmp(a.domain(), b.domain())
+        .then_with(|| rank(a).cmp(&rank(b)))
+        .then_with(|| rdata(a).cmp(&rdata(b)))
+}
+
+fn labels(name: &str) -> impl DoubleEndedIterator<Item = &str> {
+    name.split('.').filter(|label| !label.is_empty())
+}
+
+/// Labels in `name` as the RRSIG labels field counts them, without the
+/// root or a leading wildcard (RFC 4034 3.1.3).
+pub fn label_count(name: &str) -> usize {
+    let count = labels(name).count();
+    if name == "*" || name.starts_with("*.") {
+        count - 1
+    } else {
+        count
+    }
+}
+
+/// `name` without its leftmost label, or `None` for the root.
+pub fn parent(name: &str) -> Option<&str> {
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(name.split_once('.').map_or("", |(_, parent)| parent))
+}
+
+/// The rightmost `count` labels of `name`.
+fn suffix(name: &str, count: usize) -> String {
+    let all: Vec<&str> = labels(name).collect();
+    all[all.len().saturating_sub(count)..].join(".")
+}
+
+/// The wildcard name directly below `name`.
+pub fn wildcard_of(name: &str) -> String {
+    if name.is_empty() {
+        "*".to_string()
+    } else {
+        format!("*.{}", name)
+    }
+}
+
+/// A record in the canonical form of RFC 4034 6.2: owner and embedded
+/// names in lowercase, nothing compressed, and the TTL given.
+pub fn canonical_record(record: &Record, owner: &str, ttl: u32) -> Result<Vec<u8>> {
+    let mut record = record.clone();
+    record.set_domain(&owner.to_ascii_lowercase());
+    record.set_ttl(ttl);
+
+    match record {
+        Record::NS { ref mut host, .. }
+        | Record::CNAME { ref mut host, .. }
+        | Record::PTR { ref mut host, .. }
+        | Record::MX { ref mut host, .. }
+        | Record::SRV { ref mut host, .. } => host.make_ascii_lowercase(),
+        Record::SOA {
+            ref mut mname,
+            ref mut rname,
+            ..
+        } => {
+            mname.make_ascii_lowercase();
+            rname.make_ascii_lowercase();
+        }
+        Record::RRSIG { ref mut signer, .. } => signer.make_ascii_lowercase(),
+        _ => {}
+    }
+
+    let mut buffer = VectorPacketBuffer::uncompressed();
+    record.write(&mut buffer)?;
+
+    Ok(buffer.buf)
+}
+
+/// The data an RRSIG signs: its own RDATA up to the signature, then the
+/// RRset in canonical form and order (RFC 4034 3.1.8.1). The RRset must
+/// share owner, class and type.
+pub fn signed_data(rrsig: &Record, rrset: &[Record]) -> Result<Vec<u8>> {
+    let Record::RRSIG {
+        type_covered,
+        algorithm,
+        labels,
+        original_ttl,
+        expiration,
+        inception,
+        key_tag,
+        signer,
+        ..
+    } = rrsig
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&type_covered.to_num().to_be_bytes());
+    data.push(*algorithm);
+    data.push(*labels);
+    data.extend_from_slice(&original_ttl.to_be_bytes());
+    data.extend_from_slice(&expiration.to_be_bytes());
+    data.extend_from_slice(&inception.to_be_bytes());
+    data.extend_from_slice(&key_tag.to_be_bytes());
+    data.extend_from_slice(&canonical_name(signer));
+
+    // Records synthesised from a wildcard are signed under the wildcard.
+    let owner = rrset.first().map_or("", |record| record.domain());
+    let owner = if label_count(owner) > *labels as usize {
+        wildcard_of(&suffix(owner, *labels as usize))
+    } else {
+        owner.to_string()
+    };
+
+    let header_len = canonical_name(&owner).len() + 10;
+    let mut records = rrset
+        .iter()
+        .map(|record| canonical_record(record, &owner, *original_ttl))
+        .collect::<Result<Vec<_>>>()?;
+    records.sort_by(|a, b| a[header_len..].cmp(&b[header_len..]));
+    records.dedup();
+
+    for record in records {
+        data.extend(record);
+    }
+
+    Ok(data)
+}
+
+/// Whether `now` falls inside the validity period of `rrsig`, using serial
+/// number arithmetic so that the times can wrap (RFC 4034 3.1.5).
+pub fn signature_current(rrsig: &Record, now: u32) -> bool {
+    match *rrsig {
+        Record::RRSIG {
+            expiration,
+            inception,
+            ..
+        } => (now.wrapping_sub(inception) as i32) >= 0 && (expiration.wrapping_sub(now) as i32) >= 0,
+        _ => false,
+    }
+}
+
+/// Checks that `rrsig` is a valid signature by `dnskey` over `rrset`
+/// (RFC 4035 5.3.1), apart from its validity period.
+pub fn verify(rrsig: &Record, dnskey: &Record, rrset: &[Record]) -> bool {
+    let (
+        Record::RRSIG {
+            type_covered,
+            algorithm,
+            labels,
+            key_tag: tag,
+            signer,
+            signature,
+            ..
+        },
+        Record::DNSKEY {
+            flags,
+            protocol,
+            algorithm: key_algorithm,
+            public_key,
+            ..
+        },
+    ) = (rrsig, dnskey)
+    else {
+        return false;
+    };
+
+    let owner = match rrset.first() {
+        Some(record) => record.domain(),
+        None => return false,
+    };
+    let consistent = rrset.iter().all(|record| {
+        record.qtype() == *type_covered
+            && record.class() == rrsig.class()
+            && record.domain().eq_ignore_ascii_case(owner)
+    });
+    if !consistent
+        || algorithm != key_algorithm
+        || *protocol != PROTOCOL
+        || flags & ZONE_KEY == 0
+        || key_tag(dnskey) != Some(*tag)
+        || !dnskey.domain().eq_ignore_ascii_case(signer)
+        || !is_subdomain(owner, signer)
+        || *labels as usize > label_count(owner)
+    {
+        return false;
+    }
+
+    let data = match signed_data(rrsig, rrset) {
+        Ok(data) => data,
+        Err(_) => return false,
+    };
+
+    verify_signature(*algorithm, public_key, &data, signature)
+}
+
+fn verify_signature(algorithm: u8, public_key: &[u8], data: &[u8], sig: &[u8]) -> bool {
+    match algorithm {
+        RSASHA256 => {
+            // Exponent length, exponent and modulus (RFC 3110 section 2).
+            let (e_len, rest) = match public_key {
+                [0, hi, lo, rest @ ..] => (u16::from_be_bytes([*hi, *lo]) as usize, rest),
+                [len, rest @ ..] => (*len as usize, rest),
+                [] => return false,
+            };
+            if e_len == 0 || rest.len() <= e_len {
+                return false;
+            }
+            let (e, n) = rest.split_at(e_len);
+            RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY, data, sig)
+                .is_ok()
+        }
+        ECDSAP256SHA256 | ECDSAP384SHA384 => {
+            let algorithm = if algorithm == ECDSAP256SHA256 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            // DNSSEC leaves out the uncompressed point marker.
+            let mut point = Vec::with_capacity(public_key.len() + 1);
+            point.push(4);
+            point.extend_from_slice(public_key);
+            UnparsedPublicKey::new(algorithm, point).verify(data, sig).is_ok()
+        }
+        ED25519 => UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(data, sig)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// The NSEC3 hash of `name` (RFC 5155 section 5).
+pub fn nsec3_hash(name: &str, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut hash = canonical_name(name);
+    for _ in 0..=iterations {
+        let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        context.update(&hash);
+        context.update(salt);
+        hash = context.finish().as_ref().to_vec();
+    }
+
+    hash
+}
+
+/// How far a set of authenticated NSEC or NSEC3 records goes towards
+/// proving a denial.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proof {
+    Proven,
+    /// Proven only by an NSEC3 opt-out span, which may hide unsigned
+    /// delegations, so the answer cannot be called secure (RFC 5155 9.2).
+    OptOut,
+    Missing,
+}
+
+impl Proof {
+    fn or(self, other: Proof) -> Proof {
+        match (self, other) {
+            (Proof::Proven, _) | (_, Proof::Proven) => Proof::Proven,
+            (Proof::OptOut, _) | (_, Proof::OptOut) => Proof::OptOut,
+            _ => Proof::Missing,
+        }
+    }
+}
+
+/// Proof that `qname` does not exist, nor a wildcard that would match it.
+pub fn prove_nxdomain(denial: &[Record], qname: &str) -> Proof {
+    let nsec = {
+        let covering = nsecs(denial).find(|nsec| nsec_covers(nsec, qname));
+        match covering {
+            Some(covering) => {
+                let encloser = nsec_closest_encloser(covering, qname);
+                nsec_proof(nsecs(denial).any(|nsec| nsec_covers(nsec, &wildcard_of(&encloser))))
+            }
+            None => Proof::Missing,
+        }
+    };
+
+    let nsec3 = match nsec3_closest_encloser(denial, qname) {
+        Some((encloser, proof)) if encloser != qname => {
+            let wildcard = wildcard_of(&encloser);
+            match nsec3s(denial).any(|nsec3| nsec3_covers(nsec3, &wildcard)) {
+                true => proof,
+                false => Proof::Missing,
+            }
+        }
+        _ => Proof::Missing,
+    };
+
+    nsec.or(nsec3)
+}
+
+/// Proof that `qname` exists but has no `qtype` records, directly, as an
+/// empty non-terminal or through a wildcard.
+pub fn prove_nodata(denial: &[Record], qname: &str, qtype: QueryType) -> Proof {
+    let nsec = nsecs(denial).any(|nsec| {
+        if nsec.domain().eq_ignore_ascii_case(qname) {
+            return types_lack(nsec, qname, qtype);
+        }
+
+        // An empty non-terminal sorts right before its first descendant.
+        let next = match nsec {
+            Record::NSEC { next, .. } => next,
+            _ => return false,
+        };
+        if nsec_covers(nsec, qname) && is_subdomain(next, qname) {
+            return true;
+        }
+
+        nsec_covers(nsec, qname) && {
+            let wildcard = wildcard_of(&nsec_closest_encloser(nsec, qname));
+            nsecs(denial).any(|nsec| nsec.domain().eq_ignore_ascii_case(&wildcard) && types_lack(nsec, qname, qtype))
+        }
+    });
+
+    let matching = nsec3s(denial).any(|nsec3| nsec3_matches(nsec3, qname) && types_lack(nsec3, qname, qtype));
+    let nsec3 = if matching {
+        Proof::Proven
+    } else {
+        match nsec3_closest_encloser(denial, qname) {
+            // A DS query for a name in an opt-out span (RFC 5155 8.6).
+            Some((encloser, Proof::OptOut)) if qtype == QueryType::DS && encloser != qname => Proof::OptOut,
+            Some((encloser, proof)) if encloser != qname => {
+                let wildcard = wildcard_of(&encloser);
+                let lacking =
+                    nsec3s(denial).any(|nsec3| nsec3_matches(nsec3, &wildcard) && types_lack(nsec3, qname, qtype));
+                if lacking {
+                    proof
+                } else {
+                    Proof::Missing
+                }
+            }
+            _ => Proof::Missing,
+        }
+    };
+
+    nsec_proof(nsec).or(nsec3)
+}
+
+/// Proof that no name closer to `qname` than the wildcard an answer was
+/// expanded from exists, `labels` being the RRSIG's label count.
+pub fn prove_wildcard_expansion(denial: &[Record], qname: &str, labels: usize) -> Proof {
+    let nsec = nsecs(denial).any(|nsec| nsec_covers(nsec, qname));
+
+    let next_closer = suffix(qname, labels + 1);
+    let nsec3 = nsec3s(denial)
+        .find(|nsec3| nsec3_covers(nsec3, &next_closer))
+        .map_or(Proof::Missing, nsec3_span);
+
+    nsec_proof(nsec).or(nsec3)
+}
+
+/// Proof that `name` is a delegation without a DS record, so the zone
+/// below it is unsigned.
+pub fn proves_insecure_delegation(denial: &[Record], name: &str) -> bool {
+    let unsigned = |record: &Record| {
+        let types = types(record);
+        types.contains(&QueryType::NS) && !types.contains(&QueryType::DS) && !types.contains(&QueryType::SOA)
+    };
+
+    nsecs(denial).any(|nsec| nsec.domain().eq_ignore_ascii_case(name) && unsigned(nsec))
+        || nsec3s(denial).any(|nsec3| nsec3_matches(nsec3, name) && unsigned(nsec3))
+        || matches!(nsec3_closest_encloser(denial, name), Some((encloser, Proof::OptOut)) if encloser != name)
+}
+
+fn nsec_proof(proven: bool) -> Proof {
+    if proven {
+        Proof::Proven
+    } else {
+        Proof::Missing
+    }
+}
+
+fn nsecs(denial: &[Record]) -> impl Iterator<Item = &Record> {
+    denial.iter().filter(|record| matches!(record, Record::NSEC { .. }))
+}
+
+fn nsec3s(denial: &[Record]) -> impl Iterator<Item = &Record> {
+    denial.iter().filter(|record| {
+        matches!(record, Record::NSEC3 { hash_algorithm: 1, iterations, .. } if *iterations <= MAX_NSEC3_ITERATIONS)
+    })
+}
+
+fn types(record: &Record) -> &[QueryType] {
+    match record {
+        Record::NSEC { types, .. } | Record::NSEC3 { types, .. } => types,
+        _ => &[],
+    }
+}
+
+/// Whether the NSEC or NSEC3 record for `qname` shows it has no `qtype`
+/// data. The parent side of a delegation only speaks for the DS record.
+fn types_lack(record: &Record, qname: &str, qtype: QueryType) -> bool {
+    let types = types(record);
+    let apex = types.contains(&QueryType::SOA);
+    let delegation = types.contains(&QueryType::NS) && !apex;
+    // A DS record lives in the parent, so the child's apex cannot deny it.
+    if qtype == QueryType::DS && apex && !qname.is_empty() {
+        return false;
+    }
+    if qtype != QueryType::DS && delegation {
+        return false;
+    }
+
+    !types.contains(&qtype) && !types.contains(&QueryType::CNAME)
+}
+
+/// Whether `name` falls strictly between the owner and next name of
+/// `nsec`. The last NSEC of a zone points back to the apex.
+fn nsec_covers(nsec: &Record, name: &str) -> bool {
+    let Record::NSEC { domain, next, .. } = nsec else {
+        return false;
+    };
+
+    let after_owner = canonical_cmp(domain, name) == Ordering::Less;
+    if canonical_cmp(domain, next) == Ordering::Less {
+        after_owner && canonical_cmp(name, next) == Ordering::Less
+    } else {
+        after_owner
+    }
+}
+
+/// The closest existing ancestor of `qname`, judging by the names on
+/// either side of it in the zone.
+fn nsec_closest_encloser(nsec: &Record, qname: &str) -> String {
+    let Record::NSEC { domain, next, .. } = nsec else {
+        return String::new();
+    };
+
+    let mut encloser = qname;
+    while let Some(parent) = parent(encloser) {
+        encloser = parent;
+        if is_subdomain(domain, encloser) || is_subdomain(next, encloser) {
+            break;
+        }
+    }
+
+    encloser.to_ascii_lowercase()
+}
+
+/// The hash in an NSEC3 record's owner name and the zone it belongs to.
+fn nsec3_owner(nsec3: &Record) -> Option<(Vec<u8>, &str)> {
+    let (hash, zone) = nsec3.domain().split_once('.').unwrap_or((nsec3.domain(), ""));
+
+    Some((base32hex_decode(hash)?, zone))
+}
+
+fn nsec3_hash_of(nsec3: &Record, name: &str) -> Option<Vec<u8>> {
+    let Record::NSEC3 { salt, iterations, .. } = nsec3 else {
+        return None;
+    };
+
+    Some(nsec3_hash(name, salt, *iterations))
+}
+
+fn nsec3_matches(nsec3: &Record, name: &str) -> bool {
+    match (nsec3_owner(nsec3), nsec3_hash_of(nsec3, name)) {
+        (Some((owner, zone)), Some(hash)) => is_subdomain(name, zone) && owner == hash,
+        _ => false,
+    }
+}
+
+fn nsec3_covers(nsec3: &Record, name: &str) -> bool {
+    let (Some((owner, zone)), Some(hash), Record::NSEC3 { next_hashed, .. }) =
+        (nsec3_owner(nsec3), nsec3_hash_of(nsec3, name), nsec3)
+    else {
+        return false;
+    };
+    if !is_subdomain(name, zone) {
+        return false;
+    }
+
+    // The hash space is circular, the last record wraps around.
+    if owner < *next_hashed {
+        owner < hash && hash < *next_hashed
+    } else {
+        owner < hash || hash < *next_hashed
+    }
+}
+
+fn nsec3_span(nsec3: &Record) -> Proof {
+    match nsec3 {
+        Record::NSEC3 { flags, .. } if flags & OPT_OUT != 0 => Proof::OptOut,
+        _ => Proof::Proven,
+    }
+}
+
+/// The closest encloser proof of RFC 5155 8.3: the longest ancestor of
+/// `qname` with a matching NSEC3, and whether the name one label closer
+/// to `qname` is covered by a plain span or an opt-out one. When `qname`
+/// itself matches, it is returned with `Proof::Proven`.
+fn nsec3_closest_encloser(denial: &[Record], qname: &str) -> Option<(String, Proof)> {
+    let mut next_closer = None;
+    let mut candidate = Some(qname);
+
+    while let Some(name) = candidate {
+        let matching = nsec3s(denial).find(|nsec3| nsec3_matches(nsec3, name));
+        if let Some(matching) = matching {
+            // The parent side of a delegation does not prove anything
+            // about the names below it.
+            let types = types(matching);
+            if types.contains(&QueryType::NS) && !types.contains(&QueryType::SOA) && name != qname {
+                return None;
+            }
+
+            return match next_closer {
+                None => Some((name.to_ascii_lowercase(), Proof::Proven)),
+                Some(next_closer) => nsec3s(denial)
+                    .find(|nsec3| nsec3_covers(nsec3, next_closer))
+                    .map(|covering| (name.to_ascii_lowercase(), nsec3_span(covering))),
+            };
+        }
+
+        next_closer = Some(name);
+        candidate = parent(name);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{base32hex_encode, base64_decode};
+
+    fn dnskey(domain: &str, flags: u16, algorithm: u8, public_key: &str) -> Record {
+        Record::DNSKEY {
+            domain: domain.to_string(),
+            class: QueryClass::IN,
+            flags,
+            protocol: PROTOCOL,
+            algorithm,
+            public_key: base64_decode(public_key).unwrap(),
+            ttl: 86400,
+        }
+    }
+
+    fn nsec(domain: &str, next: &str, types: &[QueryType]) -> Record {
+        Record::NSEC {
+            domain: domain.to_string(),
+            class: QueryClass::IN,
+            next: next.to_string(),
+            types: types.to_vec(),
+            ttl: 3600,
+        }
+    }
+
+    /// The only NSEC3 record of a zone holding nothing but its apex, so
+    /// it matches the apex and covers every other name.
+    fn lone_nsec3(zone: &str, flags: u8, iterations: u16) -> Record {
+        let salt = vec![0xAA, 0xBB, 0xCC, 0xDD];
+        let hash = nsec3_hash(zone, &salt, iterations);
+        Record::NSEC3 {
+            domain: format!("{}.{}", base32hex_encode(&hash), zone),
+            class: QueryClass::IN,
+            hash_algorithm: 1,
+            flags,
+            iterations,
+            salt,
+            next_hashed: hash,
+            types: vec![QueryType::NS, QueryType::SOA, QueryType::RRSIG, QueryType::DNSKEY, QueryType::NSEC3PARAM],
+            ttl: 3600,
+        }
+    }
+
+    #[test]
+    fn root_ksk_matches_its_trust_anchor() {
+        let ksk = dnskey(
+            "",
+            ZONE_KEY | SECURE_ENTRY_POINT,
+            RSASHA256,
+            "AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQl\
+             NVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58D\
+             a+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9\
+             555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=",
+        );
+
+        assert_eq!(key_tag(&ksk), Some(20326));
+        let anchors = root_trust_anchors();
+        assert!(ds_matches(&anchors[0], &ksk));
+        assert!(!ds_matches(&anchors[1], &ksk));
+
+        // The owner name is part of the digest.
+        let mut elsewhere = ksk.clone();
+        elsewhere.set_domain("com");
+        let mut ds = anchors[0].clone();
+        ds.set_domain("com");
+        assert!(!ds_matches(&ds, &elsewhere));
+    }
+
+    #[test]
+    fn key_tags_and_digests_follow_rfc_4034() {
+        // The DNSKEY and DS examples of RFC 4034 sections 2.3 and 5.4.
+        let key = dnskey(
+            "example.com",
+            ZONE_KEY,
+            5,
+            "AQPSKmynfzW4kyBv015MUG2DeIQ3Cbl+BBZH4b/0PY1kxkmvHjcZc8nokfzj31GajIQKY+5CptLr3buXA10hWqTkF7H6RfoRqXQeogmMHfpf\
+             tf6zMv1LyBUgia7za6ZEzOJBOztyvhjL742iU/TpPSEDhm2SNKLijfUppn1UaNvv4w==",
+        );
+        assert_eq!(key_tag(&key), Some(2642));
+
+        let dskey = dnskey(
+            "dskey.example.com",
+            ZONE_KEY,
+            5,
+            "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7Or\
+             zKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbtG9DMBmADjFDc2w/rljwvFw==",
+        );
+        assert_eq!(key_tag(&dskey), Some(60485));
+        let digest = hex_decode("2BB183AF5F22588179A53B0A98631FAD1A292118").unwrap();
+        assert_eq!(ds_digest(&dskey, DIGEST_SHA1), Some(digest.clone()));
+        assert_eq!(ds_digest(&dskey, 3), None);
+
+        let ds = Record::DS {
+            domain: "DSKEY.example.com".to_string(),
+            class: QueryClass::IN,
+            key_tag: 60485,
+            algorithm: 5,
+            digest_type: DIGEST_SHA1,
+            digest,
+            ttl: 86400,
+        };
+        assert!(ds_matches(&ds, &dskey));
+        assert!(!ds_matches(&ds, &key));
+    }
+
+    #[test]
+    fn nsec3_hashes_follow_rfc_5155() {
+        // Appendix A: salt aabbccdd and 12 extra iterations.
+        let salt = [0xAA, 0xBB, 0xCC, 0xDD];
+        let hash = |name| base32hex_encode(&nsec3_hash(name, &salt, 12)).to_ascii_lowercase();
+
+        assert_eq!(hash("example"), "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom");
+        assert_eq!(hash("a.example"), "35mthgpgcu1qg68fab165klnsnk3dpvl");
+        assert_eq!(hash("A.EXAMPLE"), "35mthgpgcu1qg68fab165klnsnk3dpvl");
+        assert_eq!(hash("*.w.example"), "r53bq7cc2uvmubfu5ocmm6pers9tk9en");
+    }
+
+    #[test]
+    fn names_sort_in_canonical_order() {
+        // The example of RFC 4034 section 6.1, shuffled.
+        let mut names = vec![
+            "z.example",
+            "zABC.a.EXAMPLE",
+            "*.z.example",
+            "a.example",
+            "\u{1}.z.example",
+            "yljkjljk.a.example",
+            "example",
+            "Z.a.example",
+        ];
+        names.sort_by(|a, b| canonical_cmp(a, b));
+
+        assert_eq!(
+            names,
+            [
+                "example",
+                "a.example",
+                "yljkjljk.a.example",
+                "Z.a.example",
+                "zABC.a.EXAMPLE",
+                "z.example",
+                "\u{1}.z.example",
+                "*.z.example",
+            ]
+        );
+        assert_eq!(canonical_cmp("Example.", "example"), Ordering::Equal);
+        assert_eq!(canonical_name("WWW.Example"), b"\x03www\x07example\x00");
+        assert_eq!(canonical_name(""), b"\x00");
+    }
+
+    #[test]
+    fn names_break_into_labels() {
+        assert_eq!(label_count("www.example.com"), 3);
+        assert_eq!(label_count("*.example.com"), 2);
+        assert_eq!(label_count("*"), 0);
+        assert_eq!(label_count(""), 0);
+
+        assert_eq!(parent("www.example"), Some("example"));
+        assert_eq!(parent("example"), Some(""));
+        assert_eq!(parent(""), None);
+
+        assert_eq!(suffix("a.b.example.com", 2), "example.com");
+        assert_eq!(suffix("example.com", 5), "example.com");
+        assert_eq!(wildcard_of("example"), "*.example");
+        assert_eq!(wildcard_of(""), "*");
+    }
+
+    #[test]
+    fn validity_periods_wrap_around() {
+        let rrsig = |inception, expiration| Record::RRSIG {
+            domain: "example".to_string(),
+            class: QueryClass::IN,
+            type_covered: QueryType::A,
+            algorithm: ED25519,
+            labels: 1,
+            original_ttl: 3600,
+            expiration,
+            inception,
+            key_tag: 1,
+            signer: "example".to_string(),
+            signature: vec![0; 64],
+            ttl: 3600,
+        };
+
+        assert!(signature_current(&rrsig(100, 200), 100));
+        assert!(signature_current(&rrsig(100, 200), 200));
+        assert!(!signature_current(&rrsig(100, 200), 99));
+        assert!(!signature_current(&rrsig(100, 200), 201));
+
+        // Signed shortly before the 32 bit clock wraps, valid after it.
+        let wrapping = rrsig(u32::MAX - 10, 10);
+        assert!(signature_current(&wrapping, u32::MAX));
+        assert!(signature_current(&wrapping, 5));
+        assert!(!signature_current(&wrapping, 20));
+        assert!(!signature_current(&wrapping, u32::MAX - 20));
+    }
+
+    #[test]
+    fn nsec_proves_names_and_types_missing() {
+        use QueryType::*;
+        let zone = [
+            nsec("example", "a.example", &[NS, SOA, RRSIG, NSEC, DNSKEY]),
+            nsec("a.example", "x.c.example", &[A, RRSIG, NSEC]),
+            nsec("x.c.example", "sub.example", &[A, RRSIG, NSEC]),
+            nsec("sub.example", "example", &[NS, NSEC, RRSIG]),
+        ];
+
+        // b.example falls between a.example and x.c.example, and
+        // *.example between the apex and a.example.
+        assert_eq!(prove_nxdomain(&zone, "b.example"), Proof::Proven);
+        assert_eq!(prove_nxdomain(&zone[1..], "b.example"), Proof::Missing);
+        assert_eq!(prove_nxdomain(&zone, "a.example"), Proof::Missing);
+
+        assert_eq!(prove_nodata(&zone, "a.example", MX), Proof::Proven);
+        assert_eq!(prove_nodata(&zone, "A.EXAMPLE", MX), Proof::Proven);
+        assert_eq!(prove_nodata(&zone, "a.example", A), Proof::Missing);
+        // c.example only exists as the parent of x.c.example.
+        assert_eq!(prove_nodata(&zone, "c.example", A), Proof::Proven);
+
+        // The parent side of a delegation only speaks for the DS record.
+        assert_eq!(prove_nodata(&zone, "sub.example", DS), Proof::Proven);
+        assert_eq!(prove_nodata(&zone, "sub.example", A), Proof::Missing);
+        assert!(proves_insecure_delegation(&zone, "sub.example"));
+        assert!(!proves_insecure_delegation(&zone, "a.example"));
+        // Nor does the child's apex.
+        assert_eq!(prove_nodata(&zone, "example", DS), Proof::Missing);
+
+        assert_eq!(prove_wildcard_expansion(&zone, "b.example", 1), Proof::Proven);
+        assert_eq!(prove_wildcard_expansion(&zone, "a.example", 1), Proof::Missing);
+    }
+
+    #[test]
+    fn nsec3_proves_names_missing() {
+        let zone = [lone_nsec3("example", 0, 0)];
+        assert_eq!(prove_nxdomain(&zone, "nope.example"), Proof::Proven);
+        assert_eq!(prove_nxdomain(&zone, "example"), Proof::Missing);
+        assert_eq!(prove_nodata(&zone, "example", QueryType::A), Proof::Proven);
+        assert_eq!(prove_nodata(&zone, "example", QueryType::SOA), Proof::Missing);
+        assert_eq!(prove_wildcard_expansion(&zone, "host.example", 1), Proof::Proven);
+
+        let opt_out = [lone_nsec3("example", OPT_OUT, 0)];
+        assert_eq!(prove_nxdomain(&opt_out, "nope.example"), Proof::OptOut);
+        assert_eq!(prove_nodata(&opt_out, "sub.example", QueryType::DS), Proof::OptOut);
+        assert!(proves_insecure_delegation(&opt_out, "sub.example"));
+
+        // Too many iterations to be worth hashing.
+        let costly = [lone_nsec3("example", 0, MAX_NSEC3_ITERATIONS + 1)];
+        assert_eq!(prove_nxdomain(&costly, "nope.example"), Proof::Missing);
+        assert_eq!(prove_nodata(&costly, "example", QueryType::A), Proof::Missing);
+    }
+}
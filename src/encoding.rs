@@ -0,0 +1,199 @@
+//! Text encodings used by the presentation format of DNSSEC records.
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE32HEX: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// Standard base64 with padding (RFC 4648 section 4).
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    for (n, chunk) in text.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && n + 1 != text.len() / 4) {
+            return None;
+        }
+
+        let mut bits = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let value = BASE64.iter().position(|&b| b == c)? as u32;
+            bits = bits << 6 | value;
+        }
+        bits <<= 6 * padding;
+
+        out.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+
+    Some(out)
+}
+
+/// Base32 with the extended hex alphabet and no padding, as NSEC3 uses
+/// for hashed owner names (RFC 5155 section 3.3).
+pub fn base32hex_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    for chunk in data.chunks(5) {
+        let bits = chunk.iter().enumerate().fold(0u64, |bits, (i, &b)| bits | (b as u64) << (32 - 8 * i));
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            out.push(BASE32HEX[(bits >> (35 - 5 * i) & 0x1F) as usize] as char);
+        }
+    }
+
+    out
+}
+
+pub fn base32hex_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes() {
+        let value = BASE32HEX.iter().position(|&b| b == c.to_ascii_uppercase())? as u32;
+        bits = bits << 5 | value;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+
+    // Leftover bits are padding and must be zero.
+    (bits == 0 && count < 5).then_some(out)
+}
+
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+pub fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Formats seconds since the epoch as `YYYYMMDDHHmmSS` in UTC, the form
+/// RRSIG validity times are written in (RFC 4034 section 3.2).
+pub fn format_timestamp(time: u32) -> String {
+    let days = (time / 86400) as i64;
+    let secs = time % 86400;
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parses a `YYYYMMDDHHmmSS` timestamp, or a plain count of seconds.
+pub fn parse_timestamp(text: &str) -> Option<u32> {
+    if text.len() != 14 {
+        return text.parse().ok();
+    }
+    if !text.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let field = |range: std::ops::Range<usize>| text[range].parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Days since 1970-01-01 from a civil date, the inverse of the above.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    u32::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_follows_rfc_4648() {
+        for (data, text) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64_encode(data.as_bytes()), text);
+            assert_eq!(base64_decode(text), Some(data.as_bytes().to_vec()));
+        }
+        assert_eq!(base64_encode(&[0xfb, 0xff]), "+/8=");
+        assert_eq!(base64_decode("Zm9v!"), None);
+        assert_eq!(base64_decode("Zg="), None);
+    }
+
+    #[test]
+    fn base32hex_follows_rfc_4648() {
+        for (data, text) in [("", ""), ("f", "CO"), ("fo", "CPNG"), ("foo", "CPNMU"), ("foobar", "CPNMUOJ1E8")] {
+            assert_eq!(base32hex_encode(data.as_bytes()), text);
+            assert_eq!(base32hex_decode(text), Some(data.as_bytes().to_vec()));
+        }
+        // NSEC3 owner names are lowercase.
+        assert_eq!(base32hex_decode("cpnmuoj1e8"), Some(b"foobar".to_vec()));
+        assert_eq!(base32hex_decode("CPNMW"), None);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(hex_encode(&[0x00, 0xab, 0xcd, 0xef]), "00ABCDEF");
+        assert_eq!(hex_decode("00abCDef"), Some(vec![0x00, 0xab, 0xcd, 0xef]));
+        assert_eq!(hex_decode("abc"), None);
+        assert_eq!(hex_decode("zz"), None);
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(format_timestamp(0), "19700101000000");
+        // RFC 4034 section 3.3.
+        assert_eq!(parse_timestamp("20030322173103"), Some(1048354263));
+        assert_eq!(format_timestamp(1048354263), "20030322173103");
+        assert_eq!(format_timestamp(u32::MAX), "21060207062815");
+        assert_eq!(parse_timestamp("1048354263"), Some(1048354263));
+        assert_eq!(parse_timestamp("20031322173103"), None);
+        assert_eq!(parse_timestamp("2003032217310x"), None);
+    }
+}
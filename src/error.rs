@@ -0,0 +1,119 @@
+use std::fmt;
+use std::io;
+
+/// Why a message could not be encoded or decoded. Offsets are relative to
+/// the start of the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    /// The message ended before the data expected at `pos`.
+    Truncated { pos: usize },
+    /// Writing at `pos` would go past the buffer's `capacity`.
+    BufferOverflow { pos: usize, capacity: usize },
+    /// The compression pointer at `pos` does not point backwards.
+    BadPointer { pos: usize, target: usize },
+    /// The name starting at `pos` chains too many compression pointers.
+    PointerLoop { pos: usize },
+    /// The label at `pos` uses the reserved 01 or 10 type bits.
+    BadLabelType { pos: usize, byte: u8 },
+    /// A label longer than 63 bytes at `pos`.
+    LabelTooLong { pos: usize, len: usize },
+    /// A name at `pos` longer than 255 bytes in wire format.
+    NameTooLong { pos: usize, len: usize },
+    /// The RDATA starting at `pos` did not take up RDLENGTH bytes.
+    RdataLengthMismatch { pos: usize, expected: usize, actual: usize },
+    /// RDATA at `pos` that is well framed but cannot be encoded.
+    InvalidRdata { pos: usize, reason: &'static str },
+    /// The buffer only supports reading.
+    ReadOnly,
+}
+
+pub type Result<T> = std::result::Result<T, WireError>;
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            WireError::Truncated { pos } => write!(f, "Message truncated at offset {}", pos),
+            WireError::BufferOverflow { pos, capacity } => {
+                write!(f, "Write at offset {} exceeds buffer capacity {}", pos, capacity)
+            }
+            WireError::BadPointer { pos, target } => {
+                write!(f, "Compression pointer at offset {} points forward to {}", pos, target)
+            }
+            WireError::PointerLoop { pos } => {
+                write!(f, "Too many compression pointers in name at offset {}", pos)
+            }
+            WireError::BadLabelType { pos, byte } => {
+                write!(f, "Unsupported label type {:#04x} at offset {}", byte, pos)
+            }
+            WireError::LabelTooLong { pos, len } => {
+                write!(f, "Label of {} bytes at offset {} exceeds 63 bytes", len, pos)
+            }
+            WireError::NameTooLong { pos, len } => {
+                write!(f, "Name of {} bytes at offset {} exceeds 255 bytes", len, pos)
+            }
+            WireError::RdataLengthMismatch {
+                pos,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "RDATA at offset {} is {} bytes but RDLENGTH says {}",
+                pos, actual, expected
+            ),
+            WireError::InvalidRdata { pos, reason } => {
+                write!(f, "Invalid RDATA at offset {}: {}", pos, reason)
+            }
+            WireError::ReadOnly => write!(f, "Buffer is read-only"),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+impl From<WireError> for io::Error {
+    fn from(e: WireError) -> io::Error {
+        let kind = match e {
+            WireError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            WireError::ReadOnly => io::ErrorKind::PermissionDenied,
+            _ => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_give_the_offset() {
+        assert_eq!(
+            WireError::BadPointer { pos: 12, target: 40 }.to_string(),
+            "Compression pointer at offset 12 points forward to 40"
+        );
+        assert_eq!(
+            WireError::RdataLengthMismatch {
+                pos: 30,
+                expected: 5,
+                actual: 4
+            }
+            .to_string(),
+            "RDATA at offset 30 is 4 bytes but RDLENGTH says 5"
+        );
+    }
+
+    #[test]
+    fn io_errors_keep_the_cause() {
+        let e = io::Error::from(WireError::Truncated { pos: 3 });
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(e.to_string(), "Message truncated at offset 3");
+
+        assert_eq!(io::Error::from(WireError::ReadOnly).kind(), io::ErrorKind::PermissionDenied);
+
+        let e = io::Error::from(WireError::PointerLoop { pos: 12 });
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let cause = e.get_ref().and_then(|cause| cause.downcast_ref::<WireError>());
+        assert_eq!(cause, Some(&WireError::PointerLoop { pos: 12 }));
+    }
+}
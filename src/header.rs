@@ -1,7 +1,6 @@
-use std::io::Result;
-
 use crate::rescode::ResultCode;
-use crate::packet::BytePacketBuffer;
+use crate::buffer::PacketBuffer;
+use crate::error::Result;
 
 #[derive(Clone, Debug)]
 pub struct Header {
@@ -14,7 +13,11 @@ pub struct Header {
     pub rd: bool,
 
     pub ra: bool,
-    pub z: u8,
+    pub z: bool,
+    /// Authentic data: every record in the answer was validated.
+    pub ad: bool,
+    /// Checking disabled: the client does its own DNSSEC validation.
+    pub cd: bool,
     pub rcode: ResultCode,
 
     pub qdcount: u16,
@@ -35,7 +38,9 @@ impl Header {
             rd: false,
 
             ra: false,
-            z: 0,
+            z: false,
+            ad: false,
+            cd: false,
             rcode: ResultCode::NOERROR,
 
             qdcount: 0,
@@ -45,7 +50,7 @@ impl Header {
         }
     }
 
-    pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn read<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
         self.id = buffer.read_u16()?;
 
         let flags = buffer.read_u16()?;
@@ -57,8 +62,10 @@ impl Header {
         self.rd = (flags & 0x100) == 0x100;                                     // 0x100    = 0000 0001 0000 0000
 
         self.ra = (flags & 0x80) == 0x80;                                       // 0x80     = 0000 0000 1000 0000
-        self.z = ((flags & 0x70) >> 4) as u8;                                   // 0x70     = 0000 0000 0111 0000
-        self.rcode = ResultCode::from_num((flags & 0xf) as u8);                 // 0xf      = 0000 0000 0000 1111
+        self.z = (flags & 0x40) == 0x40;                                        // 0x40     = 0000 0000 0100 0000
+        self.ad = (flags & 0x20) == 0x20;                                       // 0x20     = 0000 0000 0010 0000
+        self.cd = (flags & 0x10) == 0x10;                                       // 0x10     = 0000 0000 0001 0000
+        self.rcode = ResultCode::from_num(flags & 0xf);                         // 0xf      = 0000 0000 0000 1111
 
         self.qdcount = buffer.read_u16()?;
         self.ancount = buffer.read_u16()?;
@@ -68,11 +75,11 @@ impl Header {
         Ok(())
     }
 
-    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<()> {
         buffer.write_u16(self.id)?;
 
         buffer.write_u8(
-            ((self.qr as u8) << 7) as u8        // x000 0000
+            ((self.qr as u8) << 7)       // x000 0000
                 | (self.opcode << 3)                // 0xxx x000
                 | ((self.aa as u8) << 2)            // 0000 0x00
                 | ((self.tc as u8) << 1)            // 0000 00x0
@@ -82,7 +89,10 @@ impl Header {
         buffer.write_u8(
             ((self.ra as u8) << 7)
                 | ((self.z as u8) << 6)
-                | (self.rcode as u8),
+                | ((self.ad as u8) << 5)
+                | ((self.cd as u8) << 4)
+                // Anything above 15 goes in the OPT record.
+                | (self.rcode.to_num() & 0xF) as u8,
         )?;
 
         buffer.write_u16(self.qdcount)?;
@@ -92,4 +102,60 @@ impl Header {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl Default for Header {
+    fn default() -> Header {
+        Header::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{BytePacketBuffer, SlicePacketBuffer};
+
+    fn round_trip(header: &Header) -> Header {
+        let mut buffer = BytePacketBuffer::new();
+        header.write(&mut buffer).unwrap();
+        assert_eq!(buffer.pos, 12);
+
+        let mut read = Header::new();
+        read.read(&mut SlicePacketBuffer::new(&buffer.buf[..12])).unwrap();
+        read
+    }
+
+    #[test]
+    fn flags_round_trip() {
+        let header = Header {
+            id: 0xbeef,
+            qr: true,
+            opcode: 5,
+            aa: true,
+            tc: false,
+            rd: true,
+            ra: false,
+            z: true,
+            ad: true,
+            cd: false,
+            rcode: ResultCode::NOTZONE,
+            qdcount: 1,
+            ancount: 2,
+            nscount: 3,
+            arcount: 4,
+        };
+        let read = round_trip(&header);
+        assert_eq!(format!("{:?}", read), format!("{:?}", header));
+    }
+
+    #[test]
+    fn unassigned_and_extended_codes_do_not_panic() {
+        let mut header = Header::new();
+        header.rcode = ResultCode::UNKNOWN(15);
+        assert_eq!(round_trip(&header).rcode, ResultCode::UNKNOWN(15));
+
+        // Without the OPT record only the low four bits of BADVERS remain.
+        header.rcode = ResultCode::BADVERS;
+        assert_eq!(round_trip(&header).rcode, ResultCode::NOERROR);
+    }
+}
@@ -0,0 +1,33 @@
+//! DNS message codec, caching resolver and the pieces of the server built
+//! on them.
+
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod acl;
+pub mod buffer;
+pub mod cache;
+pub mod config;
+pub mod dnssec;
+pub mod encoding;
+pub mod error;
+pub mod header;
+pub mod packet;
+pub mod pool;
+pub mod query;
+pub mod question;
+pub mod record;
+pub mod rescode;
+pub mod resolver;
+pub mod server;
+pub mod signer;
+pub mod tcp;
+pub mod upstream;
+pub mod validator;
+pub mod zone;
+pub mod zonefile;
+
+mod chaos;
+mod random;
+#[cfg(test)]
+mod testing;
+mod toml;
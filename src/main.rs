@@ -1,101 +1,173 @@
-use std::io::Result;
-use std::net::UdpSocket;
+use std::fs;
+use std::io::{self, Error, ErrorKind, Result, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use my_dns::config::Config;
+use my_dns::dnssec;
+use my_dns::encoding::{hex_decode, parse_timestamp};
+use my_dns::pool::ThreadPool;
+use my_dns::server::{self, ServerContext};
+use my_dns::signer::SigningKey;
+use my_dns::zone::{Nsec3Params, Zone};
+use my_dns::zonefile;
 
-mod packet;
-mod header;
-mod query;
-mod record;
-mod question;
-mod rescode;
-
-use packet::{BytePacketBuffer, Packet};
-use question::Question;
-use rescode::ResultCode;
-
-use crate::query::QueryType;
-
-fn lookup(qname: &str, qtype: QueryType) -> Result<Packet> {
-    let server = ("8.8.8.8", 53);
-
-    let socket = UdpSocket::bind(("0.0.0.0", 43210))?;
-
-    let mut packet = Packet::new();
-
-    packet.header.id = 6666;
-    packet.header.qdcount = 1;
-    packet.header.rd = true;
-    packet
-        .questions
-        .push(Question::new(qname.to_string(), qtype));
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "sign-zone") {
+        return sign_zone(args.skip(1));
+    }
 
-    let mut req_buffer = BytePacketBuffer::new();
-    packet.write(&mut req_buffer)?;
-    socket.send_to(&req_buffer.buf[0..req_buffer.pos], server)?;
+    // The server is set up from `--config FILE`. Without one it listens
+    // on port 2053 and resolves recursively for anyone.
+    let config = match args.next() {
+        None => Config::default(),
+        Some(arg) if arg == "--config" => {
+            let path = args
+                .next()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "--config expects a file"))?;
+            Config::load(Path::new(&path))?
+        }
+        Some(arg) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unexpected argument {}, expected --config FILE or sign-zone", arg),
+            ))
+        }
+    };
+    if let Some(arg) = args.next() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("unexpected argument {}", arg)));
+    }
 
-    let mut res_buffer = BytePacketBuffer::new();
-    socket.recv_from(&mut res_buffer.buf)?;
+    let context = ServerContext::from_config(&config)?;
+    // The parent zone has to publish these for the signatures to be trusted.
+    for zone in context.authority.zones() {
+        for ds in zone.ds_records() {
+            println!("{}", ds);
+        }
+    }
 
-    Packet::from_buffer(&mut res_buffer)
+    let pool = Arc::new(ThreadPool::new(config.workers, config.max_queued));
+    server::serve(&config.listeners, Arc::new(context), pool)
 }
 
-fn handle_query(socket: &UdpSocket) -> Result<()> {
-    let mut req_buffer = BytePacketBuffer::new();
-
-    let (_, src) = socket.recv_from(&mut req_buffer.buf)?;
-
-    let mut request = Packet::from_buffer(&mut req_buffer)?;
-
-    let mut packet = Packet::new();
-    packet.header.id = request.header.id;
-    packet.header.rd = true;
-    packet.header.ra = true;
-    packet.header.qr = true;
-
-    if let Some(question) = request.questions.pop() {
-        println!("Received query: {:?}", question);
-
-        if let Ok(result) = lookup(&question.name, question.qtype) {
-            packet.questions.push(question);
-            packet.header.rcode = result.header.rcode;
+/// `sign-zone <origin> <file>` signs a master file and writes the signed
+/// zone to standard output, or to `--output FILE`, with the SOA serial
+/// bumped. Keys are given as `--ksk FILE` and `--zsk FILE` and generated
+/// into the files, using `--algorithm` (Ed25519 by default), if missing.
+/// Denial uses NSEC3 with `--iterations` and `--salt`, defaulting to the
+/// zone's NSEC3PARAM record or to none. Giving `--inception` and
+/// `--expiration` makes the output reproducible, except for ECDSA keys,
+/// whose signatures are randomised. DS records for the parent zone are
+/// printed to standard error.
+fn sign_zone(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+
+    let mut files = Vec::new();
+    let mut key_files = Vec::new();
+    let mut algorithm = dnssec::ED25519;
+    let mut iterations = None;
+    let mut salt = None;
+    let mut inception = None;
+    let mut expiration = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            files.push(arg);
+            continue;
+        }
 
-            for rec in result.answers {
-                println!("Answer: {:?}", rec);
-                packet.answers.push(rec);
+        let value = args.next().ok_or_else(|| invalid(format!("{} expects a value", arg)))?;
+        match arg.as_str() {
+            "--ksk" | "--zsk" => key_files.push((value, arg == "--ksk")),
+            "--algorithm" => {
+                algorithm = match value.to_ascii_lowercase().as_str() {
+                    "ed25519" => dnssec::ED25519,
+                    "ecdsap256sha256" => dnssec::ECDSAP256SHA256,
+                    "ecdsap384sha384" => dnssec::ECDSAP384SHA384,
+                    _ => return Err(invalid(format!("unknown algorithm {}", value))),
+                }
             }
-            for rec in result.authorities {
-                println!("Authority: {:?}", rec);
-                packet.authorities.push(rec);
+            "--iterations" => match value.parse() {
+                Ok(n) if n <= dnssec::MAX_NSEC3_ITERATIONS => iterations = Some(n),
+                _ => return Err(invalid(format!("bad NSEC3 iterations {}", value))),
+            },
+            "--salt" => {
+                let bytes = if value == "-" { Some(Vec::new()) } else { hex_decode(&value) };
+                salt = Some(bytes.ok_or_else(|| invalid(format!("bad NSEC3 salt {}", value)))?);
             }
-            for rec in result.resources {
-                println!("Resource: {:?}", rec);
-                packet.resources.push(rec);
+            "--inception" | "--expiration" => {
+                let time = parse_timestamp(&value).ok_or_else(|| invalid(format!("bad time {}", value)))?;
+                if arg == "--inception" {
+                    inception = Some(time);
+                } else {
+                    expiration = Some(time);
+                }
             }
-        } else {
-            packet.header.rcode = ResultCode::SERVFAIL;
+            "--output" => output = Some(value),
+            _ => return Err(invalid(format!("unknown option {}", arg))),
         }
     }
-    else {
-        packet.header.rcode = ResultCode::FORMERR;
+    let [origin, path] = <[String; 2]>::try_from(files)
+        .map_err(|_| invalid("sign-zone expects <origin> <file>".to_string()))?;
+    if key_files.is_empty() {
+        return Err(invalid("sign-zone needs a --ksk or --zsk key".to_string()));
     }
 
-    let mut res_buffer = BytePacketBuffer::new();
-    packet.write(&mut res_buffer)?;
+    let records = zonefile::load(Path::new(&path), &origin)?;
+    let mut nsec3 = Nsec3Params::of(&records);
+    nsec3.iterations = iterations.unwrap_or(nsec3.iterations);
+    nsec3.salt = salt.unwrap_or(nsec3.salt);
+
+    let mut keys = Vec::new();
+    for (key_file, ksk) in key_files {
+        let key_path = Path::new(&key_file);
+        let key = if key_path.exists() {
+            SigningKey::load(&origin, key_path, ksk)?
+        } else {
+            eprintln!("Generating {}", key_file);
+            SigningKey::generate(&origin, key_path, algorithm, ksk)?
+        };
+        keys.push(key);
+    }
 
-    let len = res_buffer.pos();
-    let data = res_buffer.get_range(0, len)?;
+    let (signed, ds_records) = Zone::resign(&origin, records, keys, nsec3, inception, expiration)?;
+    let mut text = String::new();
+    for record in signed {
+        text.push_str(&format!("{}\n", record));
+    }
 
-    socket.send_to(data, src)?;
+    match output {
+        Some(output) => fs::write(&output, text)?,
+        None => io::stdout().write_all(text.as_bytes())?,
+    }
+    for ds in ds_records {
+        eprintln!("{}", ds);
+    }
 
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let socket = UdpSocket::bind(("0.0.0.0", 2053))?;
-
-    loop {
-        match handle_query(&socket) {
-            Ok(_) => {},
-            Err(e) => eprintln!("An error occurred: {}", e),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_arguments_are_refused() {
+        // Each is refused before any file is read or written.
+        let cases: [(&[&str], &str); 6] = [
+            (&["example", "example.zone"], "needs a --ksk or --zsk"),
+            (&["example", "--zsk", "zsk.pem"], "expects <origin> <file>"),
+            (&["example", "example.zone", "--zsk"], "--zsk expects a value"),
+            (&["example", "example.zone", "--zsk", "zsk.pem", "--iterations", "151"], "bad NSEC3 iterations"),
+            (&["example", "example.zone", "--zsk", "zsk.pem", "--algorithm", "rsasha1"], "unknown algorithm"),
+            (&["example", "example.zone", "--zsk", "zsk.pem", "--colour", "red"], "unknown option"),
+        ];
+        for (args, needle) in cases {
+            let e = sign_zone(args.iter().map(|arg| arg.to_string())).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            assert!(e.to_string().contains(needle), "{}", e);
         }
+        assert!(!Path::new("zsk.pem").exists());
     }
-}
\ No newline at end of file
+}
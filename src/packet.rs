@@ -1,198 +1,12 @@
-use std::io::{Error, ErrorKind, Result};
+use std::net::Ipv4Addr;
 
+use crate::buffer::PacketBuffer;
+use crate::error::{Result, WireError};
 use crate::header::Header;
 use crate::query::QueryType;
 use crate::question::Question;
 use crate::record::Record;
-
-pub struct BytePacketBuffer {
-    pub buf: [u8; 512],
-    pub pos: usize,
-}
-
-impl BytePacketBuffer {
-
-    pub fn new() -> BytePacketBuffer {
-        BytePacketBuffer {
-            buf: [0; 512],
-            pos: 0,
-        }
-    }
-
-    pub fn pos(&self) -> usize {
-        self.pos
-    }
-
-    pub fn step(&mut self, steps: usize) -> Result<()> {
-        self.pos += steps;
-
-        Ok(())
-    }
-
-    fn seek(&mut self, pos: usize) -> Result<()> {
-        self.pos = pos;
-
-        Ok(())
-    }
-
-    pub fn read(&mut self) -> Result<u8> {
-        if self.pos >= self.buf.len() {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "EOF"));
-        }
-        
-        let byte = self.buf[self.pos];
-        self.pos += 1;
-
-        Ok(byte)
-    }
-
-    pub fn get(&mut self, pos: usize) -> Result<u8> {
-        if pos >= self.buf.len() {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "EOF"));
-        }
-        
-        Ok(self.buf[pos])
-    }
-
-    pub fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]> {
-        if start + len >= self.buf.len() {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "EOF"));
-        }
-        
-        Ok(&self.buf[start..start+len])
-    }
-
-    pub fn read_u16(&mut self) -> Result<u16> {
-        let byte1 = self.read()? as u16;
-        let byte2 = self.read()? as u16;
-
-        let bytes = byte1 << 8 | byte2;
-
-        Ok(bytes)
-    }
-
-    pub fn read_u32(&mut self) -> Result<u32> {
-        let byte1 = self.read()? as u32;
-        let byte2 = self.read()? as u32;
-        let byte3 = self.read()? as u32;
-        let byte4 = self.read()? as u32;
-
-        let bytes = byte1 << 24 | byte2 << 16 | byte3 << 8 | byte4;
-
-        Ok(bytes)
-    }
-
-    pub fn read_qname(&mut self, outstr: &mut String) -> Result<()> {
-        let mut pos = self.pos;
-
-        let mut jumped = false;
-        let max_jumps = 5;
-        let mut jumps_performed = 0;
-
-        let mut delim = "";
-
-        loop {
-            if jumps_performed > max_jumps {
-                return Err(Error::new(ErrorKind::InvalidData, "Too many jumps"));
-            }
-            let len = self.get(pos)?;
-            if (len & 0xC0) == 0xC0 {
-                if !jumped {
-                    self.seek(pos + 2)?;
-                }
-                    
-                let b2 = self.get(pos + 1)? as u16;
-                let offset = (((len as u16) ^ 0xC0) << 8) | b2;
-                pos = offset as usize;
-
-                jumped = true;
-                jumps_performed += 1;
-                continue;
-            } else {
-                pos += 1;
-
-                if len == 0 {
-                    break;
-                }
-                outstr.push_str(delim);
-                let str_buffer = self.get_range(pos, len as usize)?;
-                outstr.push_str(&String::from_utf8_lossy(str_buffer).to_lowercase());
-
-                delim = ".";
-
-                pos += len as usize;
-            }
-        }
-
-        if !jumped {
-            self.seek(pos)?;
-        }
-
-        Ok(())
-    }
-
-    fn write(&mut self, val: u8) -> Result<()> {
-        if self.pos >= self.buf.len() {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "EOF"));
-        }
-        self.buf[self.pos] = val;
-        self.pos += 1;
-        Ok(())
-    }
-
-    pub fn write_u8(&mut self, val: u8) -> Result<()> {
-        self.write(val)?;
-
-        Ok(())
-    }
-
-    pub fn write_u16(&mut self, val: u16) -> Result<()> {
-        self.write((val >> 8) as u8)?;
-        self.write((val & 0xFF) as u8)?;
-
-        Ok(())
-    }
-
-    pub fn write_u32(&mut self, val: u32) -> Result<()> {
-        self.write(((val >> 24) & 0xFF) as u8)?;
-        self.write(((val >> 16) & 0xFF) as u8)?;
-        self.write(((val >> 8) & 0xFF) as u8)?;
-        self.write(((val >> 0) & 0xFF) as u8)?;
-
-        Ok(())
-    }
-
-    pub fn write_qname(&mut self, qname: &str) -> Result<()> {
-        for label in qname.split('.') {
-            let len = label.len();
-            if len > 0x3f {
-                return Err(Error::new(ErrorKind::InvalidData, "Label exceeds 63 bytes"));
-            }
-
-            self.write_u8(len as u8)?;
-            for b in label.as_bytes() {
-                self.write_u8(*b)?;
-            }
-        }
-
-        self.write_u8(0)?;
-
-        Ok(())
-    }
-
-    pub fn set(&mut self, pos: usize, val: u8) -> Result<()> {
-        self.buf[pos] = val;
-
-        Ok(())
-    }
-
-    pub fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
-        self.set(pos, (val >> 8) as u8)?;
-        self.set(pos + 1, (val & 0xFF) as u8)?;
-
-        Ok(())
-    }
-}
+use crate::rescode::ResultCode;
 
 #[derive(Clone, Debug)]
 pub struct Packet {
@@ -214,7 +28,7 @@ impl Packet {
         }
     }
 
-    pub fn from_buffer(buffer: &mut BytePacketBuffer) -> Result<Packet> {
+    pub fn from_buffer<T: PacketBuffer>(buffer: &mut T) -> Result<Packet> {
         let mut result = Packet::new();
         result.header.read(buffer)?;
         
@@ -237,14 +51,20 @@ impl Packet {
             result.resources.push(rec);
         }
 
+        if let Some(Record::OPT { ext_rcode, .. }) = result.edns() {
+            let rcode = ((*ext_rcode as u16) << 4) | result.header.rcode.to_num();
+            result.header.rcode = ResultCode::from_num(rcode);
+        }
+
         Ok(result)
     }
 
-    pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn write<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
         self.header.qdcount = self.questions.len() as u16;
         self.header.ancount = self.answers.len() as u16;
         self.header.nscount = self.authorities.len() as u16;
         self.header.arcount = self.resources.len() as u16;
+        self.set_ext_rcode();
 
         self.header.write(buffer)?;
 
@@ -263,4 +83,286 @@ impl Packet {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Writes as much of the packet as fits in `buffer`, for UDP. Records
+    /// are dropped from the end, so additional records go first, then the
+    /// authority and answer sections. Losing anything but additional
+    /// records sets the TC flag so the client retries over TCP. An OPT
+    /// record is always kept, as RFC 6891 requires.
+    pub fn write_truncated<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
+        self.set_ext_rcode();
+        let opt = self
+            .resources
+            .iter()
+            .position(|rec| matches!(rec, Record::OPT { .. }))
+            .map(|i| self.resources.remove(i));
+
+        let start = buffer.pos();
+        self.header.write(buffer)?;
+
+        for question in &self.questions {
+            question.write(buffer)?;
+        }
+
+        // Start offset and section of every record written so far.
+        let mut marks: Vec<(usize, usize)> = Vec::new();
+        let sections = [&self.answers, &self.authorities, &self.resources];
+        'sections: for (i, section) in sections.into_iter().enumerate() {
+            for rec in section {
+                let pos = buffer.pos();
+                match rec.write(buffer) {
+                    Ok(_) => marks.push((pos, i)),
+                    Err(WireError::BufferOverflow { .. }) => {
+                        buffer.truncate(pos)?;
+                        break 'sections;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        if let Some(ref opt) = opt {
+            loop {
+                let pos = buffer.pos();
+                let err = match opt.write(buffer) {
+                    Ok(_) => break,
+                    Err(e @ WireError::BufferOverflow { .. }) => e,
+                    Err(e) => return Err(e),
+                };
+                buffer.truncate(pos)?;
+
+                match marks.pop() {
+                    Some((pos, _)) => buffer.truncate(pos)?,
+                    None => return Err(err),
+                }
+            }
+        }
+
+        let mut written = [0; 3];
+        for (_, i) in marks {
+            written[i] += 1;
+        }
+
+        if written[0] < self.answers.len() || written[1] < self.authorities.len() {
+            self.header.tc = true;
+        }
+        self.answers.truncate(written[0]);
+        self.authorities.truncate(written[1]);
+        self.resources.truncate(written[2]);
+        self.resources.extend(opt);
+
+        self.header.qdcount = self.questions.len() as u16;
+        self.header.ancount = self.answers.len() as u16;
+        self.header.nscount = self.authorities.len() as u16;
+        self.header.arcount = self.resources.len() as u16;
+
+        let end = buffer.pos();
+        buffer.seek(start)?;
+        self.header.write(buffer)?;
+        buffer.seek(end)?;
+
+        Ok(())
+    }
+
+    /// Copies the upper bits of the header RCODE into the OPT record, which
+    /// is where extended codes such as BADVERS live on the wire.
+    fn set_ext_rcode(&mut self) {
+        let bits = self.header.rcode.ext_bits();
+        for rec in &mut self.resources {
+            if let Record::OPT { ext_rcode, .. } = rec {
+                *ext_rcode = bits;
+            }
+        }
+    }
+
+    /// The EDNS OPT pseudo-record, if the packet carries one.
+    pub fn edns(&self) -> Option<&Record> {
+        self.resources
+            .iter()
+            .find(|rec| matches!(rec, Record::OPT { .. }))
+    }
+
+    /// A records for `host`, anywhere in the answer or additional section.
+    pub fn get_a(&self, host: &str) -> Vec<Ipv4Addr> {
+        self.answers
+            .iter()
+            .chain(self.resources.iter())
+            .filter_map(|record| match record {
+                Record::A { domain, addr, .. } if domain.eq_ignore_ascii_case(host) => Some(*addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// NS records in the authority section for zones enclosing `qname`,
+    /// as `(zone, host)` pairs.
+    pub fn get_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.authorities
+            .iter()
+            .filter_map(|record| match record {
+                Record::NS { domain, host, .. } => Some((domain.as_str(), host.as_str())),
+                _ => None,
+            })
+            .filter(move |(domain, _)| is_subdomain(qname, domain))
+    }
+
+    /// Addresses of the referred name servers that came with glue records.
+    pub fn get_resolved_ns(&self, qname: &str) -> Vec<Ipv4Addr> {
+        self.get_ns(qname)
+            .flat_map(|(_, host)| {
+                self.resources.iter().filter_map(move |record| match record {
+                    Record::A { domain, addr, .. } if domain.eq_ignore_ascii_case(host) => Some(*addr),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Names of the referred name servers, for when no glue was supplied.
+    pub fn get_unresolved_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = &'a str> {
+        self.get_ns(qname).map(|(_, host)| host)
+    }
+}
+
+/// Whether `name` is equal to or below `zone`. The root zone is `""`.
+pub fn is_subdomain(name: &str, zone: &str) -> bool {
+    if zone.is_empty() {
+        return true;
+    }
+    if name.len() < zone.len() {
+        return false;
+    }
+
+    let (head, tail) = name.as_bytes().split_at(name.len() - zone.len());
+    tail.eq_ignore_ascii_case(zone.as_bytes()) && (head.is_empty() || head.ends_with(b"."))
+}
+
+impl Default for Packet {
+    fn default() -> Packet {
+        Packet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{BytePacketBuffer, SlicePacketBuffer, UDP_MESSAGE_SIZE};
+    use crate::query::QueryClass;
+    use crate::record::EdnsOption;
+
+    fn a(domain: &str, i: u8) -> Record {
+        Record::A {
+            domain: domain.to_string(),
+            class: QueryClass::IN,
+            addr: Ipv4Addr::new(192, 0, 2, i),
+            ttl: 300,
+        }
+    }
+
+    fn query() -> Packet {
+        let mut packet = Packet::new();
+        packet.questions.push(Question::new("www.example.com".to_string(), QueryType::A));
+        packet
+    }
+
+    fn opt(packet_len: u16) -> Record {
+        Record::OPT {
+            packet_len,
+            ext_rcode: 0,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![EdnsOption {
+                code: 10,
+                data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            }],
+        }
+    }
+
+    /// Writes `packet` truncated to a classic UDP message and reads it back.
+    fn truncated(packet: &mut Packet) -> Packet {
+        let mut buffer = BytePacketBuffer::new();
+        packet.write_truncated(&mut buffer).unwrap();
+        assert!(buffer.pos <= UDP_MESSAGE_SIZE);
+        Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf[..buffer.pos])).unwrap()
+    }
+
+    #[test]
+    fn answers_that_do_not_fit_set_tc() {
+        let mut packet = query();
+        packet.answers = (0..100).map(|i| a("www.example.com", i)).collect();
+        packet.resources.push(a("ns.example.com", 0));
+
+        let read = truncated(&mut packet);
+        assert!(read.header.tc);
+        assert!(read.answers.len() > 10 && read.answers.len() < 100);
+        assert_eq!(read.answers, packet.answers);
+        // Records are dropped from the end.
+        assert_eq!(read.answers.last(), Some(&a("www.example.com", read.answers.len() as u8 - 1)));
+        assert!(read.resources.is_empty());
+        assert_eq!(read.questions, query().questions);
+    }
+
+    #[test]
+    fn dropping_additional_records_does_not_set_tc() {
+        let mut packet = query();
+        packet.answers.push(a("www.example.com", 1));
+        packet.resources = (0..100).map(|i| a(&format!("host{}.example.org", i), i)).collect();
+
+        let read = truncated(&mut packet);
+        assert!(!read.header.tc);
+        assert_eq!(read.answers.len(), 1);
+        assert!(!read.resources.is_empty() && read.resources.len() < 100);
+    }
+
+    #[test]
+    fn packets_that_fit_are_written_whole() {
+        let mut packet = query();
+        packet.answers = (0..3).map(|i| a("www.example.com", i)).collect();
+
+        let read = truncated(&mut packet);
+        assert!(!read.header.tc);
+        assert_eq!(read.answers.len(), 3);
+    }
+
+    #[test]
+    fn extended_rcodes_travel_in_the_opt_record() {
+        let mut packet = query();
+        packet.header.rcode = ResultCode::BADVERS;
+        packet.resources.push(opt(4096));
+
+        let mut buffer = BytePacketBuffer::new();
+        packet.write(&mut buffer).unwrap();
+        // Only the low four bits fit in the header.
+        assert_eq!(buffer.buf[3] & 0x0f, 0);
+
+        let read = Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf[..buffer.pos])).unwrap();
+        assert_eq!(read.header.rcode, ResultCode::BADVERS);
+        match read.edns() {
+            Some(Record::OPT {
+                packet_len,
+                ext_rcode,
+                dnssec_ok,
+                options,
+                ..
+            }) => {
+                assert_eq!(*packet_len, 4096);
+                assert_eq!(*ext_rcode, 1);
+                assert!(*dnssec_ok);
+                assert_eq!(options[0].data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+            }
+            other => panic!("expected an OPT record, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncation_keeps_the_opt_record() {
+        let mut packet = query();
+        packet.answers = (0..100).map(|i| a("www.example.com", i)).collect();
+        packet.resources.push(a("ns.example.com", 0));
+        packet.resources.push(opt(512));
+
+        let read = truncated(&mut packet);
+        assert!(read.header.tc);
+        assert_eq!(read.resources, vec![opt(512)]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Fixed set of worker threads fed through a bounded queue. At most
+/// `workers + queue_len` jobs are in flight; beyond that new jobs are
+/// refused rather than piling up.
+pub struct ThreadPool {
+    sender: SyncSender<Job>,
+}
+
+impl ThreadPool {
+    pub fn new(workers: usize, queue_len: usize) -> ThreadPool {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue_len);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..workers.max(1) {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || worker(receiver));
+        }
+
+        ThreadPool { sender }
+    }
+
+    /// Queues `job`, returning `false` if the pool is saturated.
+    pub fn try_execute<F>(&self, job: F) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self.sender.try_send(Box::new(job)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+fn worker(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = {
+            let receiver = receiver.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            receiver.recv()
+        };
+
+        match job {
+            // A panicking query must not take the worker down with it.
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn jobs_run_side_by_side() {
+        let pool = ThreadPool::new(4, 4);
+        // Only passed once all four jobs are running at the same time.
+        let barrier = Arc::new(Barrier::new(5));
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            assert!(pool.try_execute(move || {
+                barrier.wait();
+            }));
+        }
+        barrier.wait();
+    }
+
+    #[test]
+    fn saturated_pools_refuse_jobs() {
+        let pool = ThreadPool::new(1, 1);
+        let (started, is_started) = mpsc::channel();
+        let (release, is_released) = mpsc::channel::<()>();
+        assert!(pool.try_execute(move || {
+            started.send(()).unwrap();
+            let _ = is_released.recv();
+        }));
+        is_started.recv_timeout(WAIT).unwrap();
+
+        let (done, is_done) = mpsc::channel();
+        let queued = done.clone();
+        assert!(pool.try_execute(move || queued.send(1).unwrap()));
+        assert!(!pool.try_execute(move || done.send(2).unwrap()));
+
+        drop(release);
+        assert_eq!(is_done.recv_timeout(WAIT), Ok(1));
+        assert!(is_done.recv_timeout(WAIT).is_err());
+    }
+
+    #[test]
+    fn workers_survive_panicking_jobs() {
+        let pool = ThreadPool::new(1, 4);
+        assert!(pool.try_execute(|| panic!("bad query")));
+
+        let (done, is_done) = mpsc::channel();
+        assert!(pool.try_execute(move || done.send(()).unwrap()));
+        is_done.recv_timeout(WAIT).unwrap();
+    }
+}
@@ -1,22 +1,48 @@
-#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy)]
+use std::fmt;
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy, PartialOrd, Ord)]
 pub enum QueryType {
     UNKNOWN(u16),
     A,
     NS,
     CNAME,
+    SOA,
+    PTR,
     MX,
+    TXT,
     AAAA,
+    SRV,
+    OPT,
+    DS,
+    RRSIG,
+    NSEC,
+    DNSKEY,
+    NSEC3,
+    NSEC3PARAM,
+    CAA,
 }
 
 impl QueryType {
-    pub fn to_num(&self) -> u16 {
-        match *self {
+    pub fn to_num(self) -> u16 {
+        match self {
             QueryType::UNKNOWN(num) => num,
             QueryType::A => 1,
             QueryType::NS => 2,
             QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
             QueryType::MX => 15,
+            QueryType::TXT => 16,
             QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::OPT => 41,
+            QueryType::DS => 43,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::NSEC3PARAM => 51,
+            QueryType::CAA => 257,
         }
     }
     
@@ -25,9 +51,152 @@ impl QueryType {
             1 => QueryType::A,
             2 => QueryType::NS,
             5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
             15 => QueryType::MX,
+            16 => QueryType::TXT,
             28 => QueryType::AAAA,
+            33 => QueryType::SRV,
+            41 => QueryType::OPT,
+            43 => QueryType::DS,
+            46 => QueryType::RRSIG,
+            47 => QueryType::NSEC,
+            48 => QueryType::DNSKEY,
+            50 => QueryType::NSEC3,
+            51 => QueryType::NSEC3PARAM,
+            257 => QueryType::CAA,
             _ => QueryType::UNKNOWN(num),
         }
     }
-}
\ No newline at end of file
+
+    /// Parses a mnemonic such as `MX`, or the generic `TYPE15` form.
+    pub fn from_name(name: &str) -> Option<QueryType> {
+        let name = name.to_ascii_uppercase();
+        let qtype = match name.as_str() {
+            "A" => QueryType::A,
+            "NS" => QueryType::NS,
+            "CNAME" => QueryType::CNAME,
+            "SOA" => QueryType::SOA,
+            "PTR" => QueryType::PTR,
+            "MX" => QueryType::MX,
+            "TXT" => QueryType::TXT,
+            "AAAA" => QueryType::AAAA,
+            "SRV" => QueryType::SRV,
+            "OPT" => QueryType::OPT,
+            "DS" => QueryType::DS,
+            "RRSIG" => QueryType::RRSIG,
+            "NSEC" => QueryType::NSEC,
+            "DNSKEY" => QueryType::DNSKEY,
+            "NSEC3" => QueryType::NSEC3,
+            "NSEC3PARAM" => QueryType::NSEC3PARAM,
+            "CAA" => QueryType::CAA,
+            _ => QueryType::from_num(name.strip_prefix("TYPE")?.parse().ok()?),
+        };
+
+        Some(qtype)
+    }
+}
+
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            QueryType::UNKNOWN(num) => write!(f, "TYPE{}", num),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy, PartialOrd, Ord)]
+pub enum QueryClass {
+    UNKNOWN(u16),
+    IN,
+    CH,
+    HS,
+    /// Used by dynamic updates to delete RRsets (RFC 2136).
+    NONE,
+    ANY,
+}
+
+impl QueryClass {
+    pub fn to_num(self) -> u16 {
+        match self {
+            QueryClass::UNKNOWN(num) => num,
+            QueryClass::IN => 1,
+            QueryClass::CH => 3,
+            QueryClass::HS => 4,
+            QueryClass::NONE => 254,
+            QueryClass::ANY => 255,
+        }
+    }
+
+    pub fn from_num(num: u16) -> QueryClass {
+        match num {
+            1 => QueryClass::IN,
+            3 => QueryClass::CH,
+            4 => QueryClass::HS,
+            254 => QueryClass::NONE,
+            255 => QueryClass::ANY,
+            _ => QueryClass::UNKNOWN(num),
+        }
+    }
+
+    /// Parses a mnemonic such as `IN`, or the generic `CLASS1` form.
+    pub fn from_name(name: &str) -> Option<QueryClass> {
+        let name = name.to_ascii_uppercase();
+        let class = match name.as_str() {
+            "IN" => QueryClass::IN,
+            "CH" => QueryClass::CH,
+            "HS" => QueryClass::HS,
+            "NONE" => QueryClass::NONE,
+            "ANY" => QueryClass::ANY,
+            _ => QueryClass::from_num(name.strip_prefix("CLASS")?.parse().ok()?),
+        };
+
+        Some(class)
+    }
+}
+
+impl fmt::Display for QueryClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            QueryClass::UNKNOWN(num) => write!(f, "CLASS{}", num),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_convert_both_ways() {
+        for class in [QueryClass::IN, QueryClass::CH, QueryClass::HS, QueryClass::NONE, QueryClass::ANY] {
+            assert_eq!(QueryClass::from_num(class.to_num()), class);
+            assert_eq!(QueryClass::from_name(&class.to_string()), Some(class));
+        }
+
+        assert_eq!(QueryClass::from_name("ch"), Some(QueryClass::CH));
+        assert_eq!(QueryClass::from_name("CLASS3"), Some(QueryClass::CH));
+        assert_eq!(QueryClass::from_num(42), QueryClass::UNKNOWN(42));
+        assert_eq!(QueryClass::UNKNOWN(42).to_string(), "CLASS42");
+        assert_eq!(QueryClass::from_name("CLASS42"), Some(QueryClass::UNKNOWN(42)));
+        assert_eq!(QueryClass::from_name("CLASS"), None);
+        assert_eq!(QueryClass::from_name("XX"), None);
+    }
+
+    #[test]
+    fn types_convert_both_ways() {
+        for num in 0..=300 {
+            let qtype = QueryType::from_num(num);
+            assert_eq!(qtype.to_num(), num);
+            assert_eq!(QueryType::from_name(&qtype.to_string()), Some(qtype));
+        }
+
+        assert_eq!(QueryType::from_name("nsec3param"), Some(QueryType::NSEC3PARAM));
+        assert_eq!(QueryType::from_name("TYPE15"), Some(QueryType::MX));
+        assert_eq!(QueryType::UNKNOWN(731).to_string(), "TYPE731");
+        assert_eq!(QueryType::from_name("TYPE65536"), None);
+        assert_eq!(QueryType::from_name("MAILX"), None);
+    }
+}
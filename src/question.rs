@@ -1,12 +1,12 @@
-use std::io::Result;
-
-use crate::query::QueryType;
-use crate::packet::BytePacketBuffer;
+use crate::query::{QueryClass, QueryType};
+use crate::buffer::PacketBuffer;
+use crate::error::Result;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Question {
     pub name: String,
     pub qtype: QueryType,
+    pub qclass: QueryClass,
 }
 
 impl Question {
@@ -14,23 +14,24 @@ impl Question {
         Question {
             name,
             qtype,
+            qclass: QueryClass::IN,
         }
     }
 
-    pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn read<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
         buffer.read_qname(&mut self.name)?;
         self.qtype = QueryType::from_num(buffer.read_u16()?);
-        let _ = buffer.read_u16()?; // qclass
+        self.qclass = QueryClass::from_num(buffer.read_u16()?);
 
         Ok(())
     }
 
-    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<()> {
         buffer.write_qname(&self.name)?;
 
         let typenum = self.qtype.to_num();
         buffer.write_u16(typenum)?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(self.qclass.to_num())?;
 
         Ok(())
     }
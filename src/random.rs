@@ -0,0 +1,51 @@
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// 64 random bits from the operating system's CSPRNG (`getrandom` on
+/// Linux), which an off-path attacker cannot predict from earlier output.
+pub fn random_u64() -> u64 {
+    let mut bytes = [0; 8];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("the system random number generator failed");
+
+    u64::from_ne_bytes(bytes)
+}
+
+pub fn random_u16() -> u16 {
+    random_u64() as u16
+}
+
+/// Uniformly distributed value in `0..bound`.
+pub fn random_below(bound: u64) -> u64 {
+    // Rejection sampling keeps small biases out of port and ID selection.
+    let zone = u64::MAX - (u64::MAX % bound);
+    loop {
+        let value = random_u64();
+        if value < zone {
+            return value % bound;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_do_not_repeat() {
+        let values: std::collections::HashSet<u64> = (0..1000).map(|_| random_u64()).collect();
+        assert_eq!(values.len(), 1000);
+    }
+
+    #[test]
+    fn bounded_values_cover_the_range() {
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            seen[random_below(7) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+
+        assert_eq!(random_below(1), 0);
+        assert!((0..1000).all(|_| random_below(u64::MAX) < u64::MAX));
+    }
+}
@@ -1,74 +1,350 @@
+use std::fmt;
 use std::net::{Ipv4Addr, Ipv6Addr};
-use std::io::Result;
 
-use crate::packet::BytePacketBuffer;
-use crate::query::QueryType;
+use crate::buffer::PacketBuffer;
+use crate::encoding::{base32hex_encode, base64_encode, format_timestamp, hex_encode};
+use crate::error::{Result, WireError};
+use crate::query::{QueryClass, QueryType};
+
+/// An EDNS option carried in the OPT pseudo-record (RFC 6891 6.1.2).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-#[allow(dead_code)]
 pub enum Record {
+    /// A type we do not model, kept as opaque RDATA so it can be passed on
+    /// unchanged (RFC 3597).
     UNKNOWN {
         domain: String,
+        class: QueryClass,
         qtype: u16,
-        data_len: u16,
+        data: Vec<u8>,
         ttl: u32,
     },
     A {
         domain: String,
+        class: QueryClass,
         addr: Ipv4Addr,
         ttl: u32,
     },
     NS {
         domain: String,
+        class: QueryClass,
         host: String,
         ttl: u32,
     },
     CNAME {
         domain: String,
+        class: QueryClass,
+        host: String,
+        ttl: u32,
+    },
+    SOA {
+        domain: String,
+        class: QueryClass,
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+        ttl: u32,
+    },
+    PTR {
+        domain: String,
+        class: QueryClass,
         host: String,
         ttl: u32,
     },
     MX {
         domain: String,
+        class: QueryClass,
         priority: u16,
         host: String,
         ttl: u32,
     },
+    /// One or more character-strings of up to 255 bytes each, kept as raw
+    /// bytes since nothing requires them to be text.
+    TXT {
+        domain: String,
+        class: QueryClass,
+        data: Vec<Vec<u8>>,
+        ttl: u32,
+    },
     AAAA {
         domain: String,
+        class: QueryClass,
         addr: Ipv6Addr,
         ttl: u32,
     },
+    SRV {
+        domain: String,
+        class: QueryClass,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        host: String,
+        ttl: u32,
+    },
+    /// EDNS(0) pseudo-record. It always belongs to the root and has no
+    /// TTL, its CLASS and TTL fields are reused for the values below.
+    OPT {
+        packet_len: u16,
+        ext_rcode: u8,
+        version: u8,
+        dnssec_ok: bool,
+        options: Vec<EdnsOption>,
+    },
+    CAA {
+        domain: String,
+        class: QueryClass,
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+        ttl: u32,
+    },
+    /// Delegation signer, the digest of a child zone's key (RFC 4034 5).
+    DS {
+        domain: String,
+        class: QueryClass,
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        digest: Vec<u8>,
+        ttl: u32,
+    },
+    /// A signature over the RRset of `type_covered` (RFC 4034 3). The
+    /// validity times are seconds since the epoch.
+    RRSIG {
+        domain: String,
+        class: QueryClass,
+        type_covered: QueryType,
+        algorithm: u8,
+        labels: u8,
+        original_ttl: u32,
+        expiration: u32,
+        inception: u32,
+        key_tag: u16,
+        signer: String,
+        signature: Vec<u8>,
+        ttl: u32,
+    },
+    /// The next name in the zone and the types present at this one
+    /// (RFC 4034 4).
+    NSEC {
+        domain: String,
+        class: QueryClass,
+        next: String,
+        types: Vec<QueryType>,
+        ttl: u32,
+    },
+    DNSKEY {
+        domain: String,
+        class: QueryClass,
+        flags: u16,
+        protocol: u8,
+        algorithm: u8,
+        public_key: Vec<u8>,
+        ttl: u32,
+    },
+    /// Hashed denial of existence (RFC 5155 3). `next_hashed` is the raw
+    /// hash, not its base32 form.
+    NSEC3 {
+        domain: String,
+        class: QueryClass,
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+        next_hashed: Vec<u8>,
+        types: Vec<QueryType>,
+        ttl: u32,
+    },
+    NSEC3PARAM {
+        domain: String,
+        class: QueryClass,
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+        ttl: u32,
+    },
 }
 
 impl Record {
-    pub fn read(buffer: &mut BytePacketBuffer) -> Result<Record> {
+    pub fn domain(&self) -> &str {
+        match self {
+            Record::UNKNOWN { domain, .. }
+            | Record::A { domain, .. }
+            | Record::NS { domain, .. }
+            | Record::CNAME { domain, .. }
+            | Record::SOA { domain, .. }
+            | Record::PTR { domain, .. }
+            | Record::MX { domain, .. }
+            | Record::TXT { domain, .. }
+            | Record::AAAA { domain, .. }
+            | Record::SRV { domain, .. }
+            | Record::CAA { domain, .. }
+            | Record::DS { domain, .. }
+            | Record::RRSIG { domain, .. }
+            | Record::NSEC { domain, .. }
+            | Record::DNSKEY { domain, .. }
+            | Record::NSEC3 { domain, .. }
+            | Record::NSEC3PARAM { domain, .. } => domain,
+            Record::OPT { .. } => "",
+        }
+    }
+
+    pub fn set_domain(&mut self, new_domain: &str) {
+        match self {
+            Record::UNKNOWN { domain, .. }
+            | Record::A { domain, .. }
+            | Record::NS { domain, .. }
+            | Record::CNAME { domain, .. }
+            | Record::SOA { domain, .. }
+            | Record::PTR { domain, .. }
+            | Record::MX { domain, .. }
+            | Record::TXT { domain, .. }
+            | Record::AAAA { domain, .. }
+            | Record::SRV { domain, .. }
+            | Record::CAA { domain, .. }
+            | Record::DS { domain, .. }
+            | Record::RRSIG { domain, .. }
+            | Record::NSEC { domain, .. }
+            | Record::DNSKEY { domain, .. }
+            | Record::NSEC3 { domain, .. }
+            | Record::NSEC3PARAM { domain, .. } => *domain = new_domain.to_string(),
+            Record::OPT { .. } => {}
+        }
+    }
+
+    pub fn class(&self) -> QueryClass {
+        match self {
+            Record::UNKNOWN { class, .. }
+            | Record::A { class, .. }
+            | Record::NS { class, .. }
+            | Record::CNAME { class, .. }
+            | Record::SOA { class, .. }
+            | Record::PTR { class, .. }
+            | Record::MX { class, .. }
+            | Record::TXT { class, .. }
+            | Record::AAAA { class, .. }
+            | Record::SRV { class, .. }
+            | Record::CAA { class, .. }
+            | Record::DS { class, .. }
+            | Record::RRSIG { class, .. }
+            | Record::NSEC { class, .. }
+            | Record::DNSKEY { class, .. }
+            | Record::NSEC3 { class, .. }
+            | Record::NSEC3PARAM { class, .. } => *class,
+            // OPT reuses the field for its payload size.
+            Record::OPT { packet_len, .. } => QueryClass::from_num(*packet_len),
+        }
+    }
+
+    pub fn ttl(&self) -> u32 {
+        match self {
+            Record::UNKNOWN { ttl, .. }
+            | Record::A { ttl, .. }
+            | Record::NS { ttl, .. }
+            | Record::CNAME { ttl, .. }
+            | Record::SOA { ttl, .. }
+            | Record::PTR { ttl, .. }
+            | Record::MX { ttl, .. }
+            | Record::TXT { ttl, .. }
+            | Record::AAAA { ttl, .. }
+            | Record::SRV { ttl, .. }
+            | Record::CAA { ttl, .. }
+            | Record::DS { ttl, .. }
+            | Record::RRSIG { ttl, .. }
+            | Record::NSEC { ttl, .. }
+            | Record::DNSKEY { ttl, .. }
+            | Record::NSEC3 { ttl, .. }
+            | Record::NSEC3PARAM { ttl, .. } => *ttl,
+            Record::OPT { .. } => 0,
+        }
+    }
+
+    pub fn set_ttl(&mut self, new_ttl: u32) {
+        match self {
+            Record::UNKNOWN { ttl, .. }
+            | Record::A { ttl, .. }
+            | Record::NS { ttl, .. }
+            | Record::CNAME { ttl, .. }
+            | Record::SOA { ttl, .. }
+            | Record::PTR { ttl, .. }
+            | Record::MX { ttl, .. }
+            | Record::TXT { ttl, .. }
+            | Record::AAAA { ttl, .. }
+            | Record::SRV { ttl, .. }
+            | Record::CAA { ttl, .. }
+            | Record::DS { ttl, .. }
+            | Record::RRSIG { ttl, .. }
+            | Record::NSEC { ttl, .. }
+            | Record::DNSKEY { ttl, .. }
+            | Record::NSEC3 { ttl, .. }
+            | Record::NSEC3PARAM { ttl, .. } => *ttl = new_ttl,
+            Record::OPT { .. } => {}
+        }
+    }
+
+    pub fn qtype(&self) -> QueryType {
+        match self {
+            Record::UNKNOWN { qtype, .. } => QueryType::UNKNOWN(*qtype),
+            Record::A { .. } => QueryType::A,
+            Record::NS { .. } => QueryType::NS,
+            Record::CNAME { .. } => QueryType::CNAME,
+            Record::SOA { .. } => QueryType::SOA,
+            Record::PTR { .. } => QueryType::PTR,
+            Record::MX { .. } => QueryType::MX,
+            Record::TXT { .. } => QueryType::TXT,
+            Record::AAAA { .. } => QueryType::AAAA,
+            Record::SRV { .. } => QueryType::SRV,
+            Record::OPT { .. } => QueryType::OPT,
+            Record::CAA { .. } => QueryType::CAA,
+            Record::DS { .. } => QueryType::DS,
+            Record::RRSIG { .. } => QueryType::RRSIG,
+            Record::NSEC { .. } => QueryType::NSEC,
+            Record::DNSKEY { .. } => QueryType::DNSKEY,
+            Record::NSEC3 { .. } => QueryType::NSEC3,
+            Record::NSEC3PARAM { .. } => QueryType::NSEC3PARAM,
+        }
+    }
+
+    pub fn read<T: PacketBuffer>(buffer: &mut T) -> Result<Record> {
         let mut domain = String::new();
         buffer.read_qname(&mut domain)?;
 
         let qtype_num = buffer.read_u16()?;
         let qtype = QueryType::from_num(qtype_num);
 
-        let _ = buffer.read_u16()?; // qclass
+        let class_num = buffer.read_u16()?;
+        let class = QueryClass::from_num(class_num);
 
         let ttl = buffer.read_u32()?;
 
         let data_len = buffer.read_u16()?;
+        let data_start = buffer.pos();
 
-        match qtype {
+        let record: Result<Record> = match qtype {
             QueryType::A => {
                 let raw_addr = buffer.read_u32()?;
                 let addr = Ipv4Addr::new(
                     ((raw_addr >> 24) & 0xFF) as u8,
                     ((raw_addr >> 16) & 0xFF) as u8,
                     ((raw_addr >> 8) & 0xFF) as u8,
-                    ((raw_addr >> 0) & 0xFF) as u8,
+                    ((raw_addr) & 0xFF) as u8,
                 );
 
                 Ok(Record::A {
-                    domain: domain,
-                    addr: addr,
-                    ttl: ttl,
+                    domain,
+                    class,
+                    addr,
+                    ttl,
                 })
             }
             QueryType::AAAA => {
@@ -78,19 +354,20 @@ impl Record {
                 let raw_addr4 = buffer.read_u32()?;
                 let addr = Ipv6Addr::new(
                     ((raw_addr1 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr1 >> 0) & 0xFFFF) as u16,
+                    ((raw_addr1) & 0xFFFF) as u16,
                     ((raw_addr2 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr2 >> 0) & 0xFFFF) as u16,
+                    ((raw_addr2) & 0xFFFF) as u16,
                     ((raw_addr3 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr3 >> 0) & 0xFFFF) as u16,
+                    ((raw_addr3) & 0xFFFF) as u16,
                     ((raw_addr4 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr4 >> 0) & 0xFFFF) as u16,
+                    ((raw_addr4) & 0xFFFF) as u16,
                 );
 
                 Ok(Record::AAAA {
-                    domain: domain,
-                    addr: addr,
-                    ttl: ttl,
+                    domain,
+                    class,
+                    addr,
+                    ttl,
                 })
             }
             QueryType::NS => {
@@ -98,9 +375,10 @@ impl Record {
                 buffer.read_qname(&mut ns)?;
 
                 Ok(Record::NS {
-                    domain: domain,
+                    domain,
+                    class,
                     host: ns,
-                    ttl: ttl,
+                    ttl,
                 })
             }
             QueryType::CNAME => {
@@ -108,9 +386,35 @@ impl Record {
                 buffer.read_qname(&mut cname)?;
 
                 Ok(Record::CNAME {
-                    domain: domain,
+                    domain,
+                    class,
                     host: cname,
-                    ttl: ttl,
+                    ttl,
+                })
+            }
+            QueryType::SOA => {
+                let mut mname = String::new();
+                buffer.read_qname(&mut mname)?;
+                let mut rname = String::new();
+                buffer.read_qname(&mut rname)?;
+
+                let serial = buffer.read_u32()?;
+                let refresh = buffer.read_u32()?;
+                let retry = buffer.read_u32()?;
+                let expire = buffer.read_u32()?;
+                let minimum = buffer.read_u32()?;
+
+                Ok(Record::SOA {
+                    domain,
+                    class,
+                    mname,
+                    rname,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                    ttl,
                 })
             }
             QueryType::MX => {
@@ -119,37 +423,251 @@ impl Record {
                 buffer.read_qname(&mut mx)?;
 
                 Ok(Record::MX {
-                    domain: domain,
-                    priority: priority,
+                    domain,
+                    class,
+                    priority,
                     host: mx,
-                    ttl: ttl,
+                    ttl,
+                })
+            }
+            QueryType::PTR => {
+                let mut ptr = String::new();
+                buffer.read_qname(&mut ptr)?;
+
+                Ok(Record::PTR {
+                    domain,
+                    class,
+                    host: ptr,
+                    ttl,
+                })
+            }
+            QueryType::TXT => {
+                let mut data = Vec::new();
+                let end = data_start + data_len as usize;
+                while buffer.pos() < end {
+                    let len = buffer.read()? as usize;
+                    data.push(buffer.get_range(buffer.pos(), len)?.to_vec());
+                    buffer.step(len)?;
+                }
+
+                Ok(Record::TXT {
+                    domain,
+                    class,
+                    data,
+                    ttl,
+                })
+            }
+            QueryType::SRV => {
+                let priority = buffer.read_u16()?;
+                let weight = buffer.read_u16()?;
+                let port = buffer.read_u16()?;
+                let mut srv = String::new();
+                buffer.read_qname(&mut srv)?;
+
+                Ok(Record::SRV {
+                    domain,
+                    class,
+                    priority,
+                    weight,
+                    port,
+                    host: srv,
+                    ttl,
+                })
+            }
+            QueryType::CAA => {
+                let flags = buffer.read()?;
+                let tag_len = buffer.read()? as usize;
+                let tag = buffer.get_range(buffer.pos(), tag_len)?;
+                let tag = String::from_utf8_lossy(tag).into_owned();
+                buffer.step(tag_len)?;
+
+                // The value runs to the end of the RDATA.
+                let value = read_rest(buffer, data_start, data_len)?;
+
+                Ok(Record::CAA {
+                    domain,
+                    class,
+                    flags,
+                    tag,
+                    value,
+                    ttl,
+                })
+            }
+            QueryType::DS => {
+                let key_tag = buffer.read_u16()?;
+                let algorithm = buffer.read()?;
+                let digest_type = buffer.read()?;
+                let digest = read_rest(buffer, data_start, data_len)?;
+
+                Ok(Record::DS {
+                    domain,
+                    class,
+                    key_tag,
+                    algorithm,
+                    digest_type,
+                    digest,
+                    ttl,
+                })
+            }
+            QueryType::RRSIG => {
+                let type_covered = QueryType::from_num(buffer.read_u16()?);
+                let algorithm = buffer.read()?;
+                let labels = buffer.read()?;
+                let original_ttl = buffer.read_u32()?;
+                let expiration = buffer.read_u32()?;
+                let inception = buffer.read_u32()?;
+                let key_tag = buffer.read_u16()?;
+                let mut signer = String::new();
+                buffer.read_qname(&mut signer)?;
+                let signature = read_rest(buffer, data_start, data_len)?;
+
+                Ok(Record::RRSIG {
+                    domain,
+                    class,
+                    type_covered,
+                    algorithm,
+                    labels,
+                    original_ttl,
+                    expiration,
+                    inception,
+                    key_tag,
+                    signer,
+                    signature,
+                    ttl,
+                })
+            }
+            QueryType::NSEC => {
+                let mut next = String::new();
+                buffer.read_qname(&mut next)?;
+                let types = read_type_bitmap(buffer, data_start + data_len as usize)?;
+
+                Ok(Record::NSEC {
+                    domain,
+                    class,
+                    next,
+                    types,
+                    ttl,
+                })
+            }
+            QueryType::DNSKEY => {
+                let flags = buffer.read_u16()?;
+                let protocol = buffer.read()?;
+                let algorithm = buffer.read()?;
+                let public_key = read_rest(buffer, data_start, data_len)?;
+
+                Ok(Record::DNSKEY {
+                    domain,
+                    class,
+                    flags,
+                    protocol,
+                    algorithm,
+                    public_key,
+                    ttl,
+                })
+            }
+            QueryType::NSEC3 => {
+                let hash_algorithm = buffer.read()?;
+                let flags = buffer.read()?;
+                let iterations = buffer.read_u16()?;
+                let salt_len = buffer.read()? as usize;
+                let salt = buffer.get_range(buffer.pos(), salt_len)?.to_vec();
+                buffer.step(salt_len)?;
+                let hash_len = buffer.read()? as usize;
+                let next_hashed = buffer.get_range(buffer.pos(), hash_len)?.to_vec();
+                buffer.step(hash_len)?;
+                let types = read_type_bitmap(buffer, data_start + data_len as usize)?;
+
+                Ok(Record::NSEC3 {
+                    domain,
+                    class,
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed,
+                    types,
+                    ttl,
+                })
+            }
+            QueryType::NSEC3PARAM => {
+                let hash_algorithm = buffer.read()?;
+                let flags = buffer.read()?;
+                let iterations = buffer.read_u16()?;
+                let salt_len = buffer.read()? as usize;
+                let salt = buffer.get_range(buffer.pos(), salt_len)?.to_vec();
+                buffer.step(salt_len)?;
+
+                Ok(Record::NSEC3PARAM {
+                    domain,
+                    class,
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    ttl,
+                })
+            }
+            QueryType::OPT => {
+                let mut options = Vec::new();
+                let end = buffer.pos() + data_len as usize;
+                while buffer.pos() < end {
+                    let code = buffer.read_u16()?;
+                    let len = buffer.read_u16()? as usize;
+                    let data = buffer.get_range(buffer.pos(), len)?.to_vec();
+                    buffer.step(len)?;
+
+                    options.push(EdnsOption { code, data });
+                }
+
+                Ok(Record::OPT {
+                    packet_len: class_num,
+                    ext_rcode: (ttl >> 24) as u8,
+                    version: ((ttl >> 16) & 0xFF) as u8,
+                    dnssec_ok: (ttl & 0x8000) == 0x8000,
+                    options,
                 })
             }
             QueryType::UNKNOWN(_) => {
+                let data = buffer.get_range(data_start, data_len as usize)?.to_vec();
                 buffer.step(data_len as usize)?;
 
                 Ok(Record::UNKNOWN {
-                    domain: domain,
+                    domain,
+                    class,
                     qtype: qtype_num,
-                    data_len: data_len,
-                    ttl: ttl,
+                    data,
+                    ttl,
                 })
             }
+        };
+        let record = record?;
+
+        // Names in RDATA (NS, CNAME, SOA, PTR, MX) may be compressed, so the
+        // bytes consumed are only known after parsing them.
+        if buffer.pos() != data_start + data_len as usize {
+            return Err(WireError::RdataLengthMismatch {
+                pos: data_start,
+                expected: data_len as usize,
+                actual: buffer.pos() - data_start,
+            });
         }
+
+        Ok(record)
     }
 
-    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<usize> {
+    pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<usize> {
         let start_pos = buffer.pos();
 
         match *self {
             Record::A {
                 ref domain,
+                class,
                 ref addr,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::A.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(4)?;
 
@@ -161,12 +679,13 @@ impl Record {
             }
             Record::NS {
                 ref domain,
+                class,
                 ref host,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::NS.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
@@ -179,12 +698,13 @@ impl Record {
             }
             Record::CNAME {
                 ref domain,
+                class,
                 ref host,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::CNAME.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
@@ -195,15 +715,47 @@ impl Record {
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
+            Record::SOA {
+                ref domain,
+                class,
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SOA.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_qname(mname)?;
+                buffer.write_qname(rname)?;
+                buffer.write_u32(serial)?;
+                buffer.write_u32(refresh)?;
+                buffer.write_u32(retry)?;
+                buffer.write_u32(expire)?;
+                buffer.write_u32(minimum)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
             Record::MX {
                 ref domain,
+                class,
                 priority,
                 ref host,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::MX.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
@@ -215,14 +767,122 @@ impl Record {
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
+            Record::PTR {
+                ref domain,
+                class,
+                ref host,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::PTR.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_qname(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::TXT {
+                ref domain,
+                class,
+                ref data,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::TXT.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                for string in data {
+                    if string.len() > 0xFF {
+                        return Err(WireError::InvalidRdata {
+                            pos: buffer.pos(),
+                            reason: "character-string exceeds 255 bytes",
+                        });
+                    }
+                    buffer.write_u8(string.len() as u8)?;
+                    for b in string {
+                        buffer.write_u8(*b)?;
+                    }
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::SRV {
+                ref domain,
+                class,
+                priority,
+                weight,
+                port,
+                ref host,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SRV.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(priority)?;
+                buffer.write_u16(weight)?;
+                buffer.write_u16(port)?;
+                // RFC 2782 forbids compressing the target.
+                buffer.write_uncompressed_qname(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::CAA {
+                ref domain,
+                class,
+                flags,
+                ref tag,
+                ref value,
+                ttl,
+            } => {
+                if tag.is_empty() || tag.len() > 0xFF {
+                    return Err(WireError::InvalidRdata {
+                        pos: buffer.pos(),
+                        reason: "CAA tag must be 1 to 255 bytes",
+                    });
+                }
+
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::CAA.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(flags)?;
+                buffer.write_u8(tag.len() as u8)?;
+                for b in tag.as_bytes().iter().chain(value) {
+                    buffer.write_u8(*b)?;
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
             Record::AAAA {
                 ref domain,
+                class,
                 ref addr,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::AAAA.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(16)?;
 
@@ -230,10 +890,883 @@ impl Record {
                     buffer.write_u16(*octet)?;
                 }
             }
-            Record::UNKNOWN { .. } => {
-                println!("Skipping record: {:?}", self);
+            Record::DS {
+                ref domain,
+                class,
+                key_tag,
+                algorithm,
+                digest_type,
+                ref digest,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::DS.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(key_tag)?;
+                buffer.write_u8(algorithm)?;
+                buffer.write_u8(digest_type)?;
+                write_bytes(buffer, digest)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::RRSIG {
+                ref domain,
+                class,
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                ref signer,
+                ref signature,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::RRSIG.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(type_covered.to_num())?;
+                buffer.write_u8(algorithm)?;
+                buffer.write_u8(labels)?;
+                buffer.write_u32(original_ttl)?;
+                buffer.write_u32(expiration)?;
+                buffer.write_u32(inception)?;
+                buffer.write_u16(key_tag)?;
+                // RFC 4034 3.1.7 forbids compressing the signer's name.
+                buffer.write_uncompressed_qname(signer)?;
+                write_bytes(buffer, signature)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::NSEC {
+                ref domain,
+                class,
+                ref next,
+                ref types,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::NSEC.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                // RFC 4034 4.1.1 forbids compressing the next name.
+                buffer.write_uncompressed_qname(next)?;
+                write_type_bitmap(buffer, types)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::DNSKEY {
+                ref domain,
+                class,
+                flags,
+                protocol,
+                algorithm,
+                ref public_key,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::DNSKEY.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(flags)?;
+                buffer.write_u8(protocol)?;
+                buffer.write_u8(algorithm)?;
+                write_bytes(buffer, public_key)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::NSEC3 {
+                ref domain,
+                class,
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ref next_hashed,
+                ref types,
+                ttl,
+            } => {
+                if salt.len() > 0xFF || next_hashed.is_empty() || next_hashed.len() > 0xFF {
+                    return Err(WireError::InvalidRdata {
+                        pos: buffer.pos(),
+                        reason: "NSEC3 salt or hash length out of range",
+                    });
+                }
+
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::NSEC3.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(hash_algorithm)?;
+                buffer.write_u8(flags)?;
+                buffer.write_u16(iterations)?;
+                buffer.write_u8(salt.len() as u8)?;
+                write_bytes(buffer, salt)?;
+                buffer.write_u8(next_hashed.len() as u8)?;
+                write_bytes(buffer, next_hashed)?;
+                write_type_bitmap(buffer, types)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::NSEC3PARAM {
+                ref domain,
+                class,
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ttl,
+            } => {
+                if salt.len() > 0xFF {
+                    return Err(WireError::InvalidRdata {
+                        pos: buffer.pos(),
+                        reason: "NSEC3PARAM salt exceeds 255 bytes",
+                    });
+                }
+
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::NSEC3PARAM.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(hash_algorithm)?;
+                buffer.write_u8(flags)?;
+                buffer.write_u16(iterations)?;
+                buffer.write_u8(salt.len() as u8)?;
+                write_bytes(buffer, salt)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::OPT {
+                packet_len,
+                ext_rcode,
+                version,
+                dnssec_ok,
+                ref options,
+            } => {
+                buffer.write_u8(0)?;
+                buffer.write_u16(QueryType::OPT.to_num())?;
+                buffer.write_u16(packet_len)?;
+                buffer.write_u32(
+                    ((ext_rcode as u32) << 24)
+                        | ((version as u32) << 16)
+                        | ((dnssec_ok as u32) << 15),
+                )?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                for option in options {
+                    buffer.write_u16(option.code)?;
+                    buffer.write_u16(option.data.len() as u16)?;
+                    for b in &option.data {
+                        buffer.write_u8(*b)?;
+                    }
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            Record::UNKNOWN {
+                ref domain,
+                class,
+                qtype,
+                ref data,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(qtype)?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+                buffer.write_u16(data.len() as u16)?;
+
+                // Names inside unknown RDATA are never compressed, so the
+                // bytes are valid wherever they end up.
+                for b in data {
+                    buffer.write_u8(*b)?;
+                }
             }
         }
         Ok(buffer.pos() - start_pos)
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for Record {
+    /// Master file presentation format, e.g. `example.com. 300 IN A 1.2.3.4`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Record::OPT {
+            packet_len,
+            ext_rcode,
+            version,
+            dnssec_ok,
+            ref options,
+        } = *self
+        {
+            return write!(
+                f,
+                "; EDNS: version {}, ext rcode {}, udp {}, do {}, {} options",
+                version,
+                ext_rcode,
+                packet_len,
+                dnssec_ok,
+                options.len()
+            );
+        }
+
+        write!(
+            f,
+            "{} {} {} {} ",
+            Fqdn(self.domain()),
+            self.ttl(),
+            self.class(),
+            self.qtype()
+        )?;
+
+        match *self {
+            Record::A { ref addr, .. } => write!(f, "{}", addr),
+            Record::AAAA { ref addr, .. } => write!(f, "{}", addr),
+            Record::NS { ref host, .. }
+            | Record::CNAME { ref host, .. }
+            | Record::PTR { ref host, .. } => write!(f, "{}", Fqdn(host)),
+            Record::SOA {
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                Fqdn(mname),
+                Fqdn(rname),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            ),
+            Record::MX {
+                priority, ref host, ..
+            } => write!(f, "{} {}", priority, Fqdn(host)),
+            Record::TXT { ref data, .. } => {
+                for (i, string) in data.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_character_string(f, string)?;
+                }
+                Ok(())
+            }
+            Record::SRV {
+                priority,
+                weight,
+                port,
+                ref host,
+                ..
+            } => write!(f, "{} {} {} {}", priority, weight, port, Fqdn(host)),
+            Record::CAA {
+                flags,
+                ref tag,
+                ref value,
+                ..
+            } => {
+                write!(f, "{} {} ", flags, tag)?;
+                write_character_string(f, value)
+            }
+            Record::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                ref digest,
+                ..
+            } => write!(f, "{} {} {} {}", key_tag, algorithm, digest_type, hex_encode(digest)),
+            Record::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                ref signer,
+                ref signature,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {} {} {}",
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                format_timestamp(expiration),
+                format_timestamp(inception),
+                key_tag,
+                Fqdn(signer),
+                base64_encode(signature)
+            ),
+            Record::NSEC {
+                ref next,
+                ref types,
+                ..
+            } => {
+                write!(f, "{}", Fqdn(next))?;
+                write_types(f, types)
+            }
+            Record::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                ref public_key,
+                ..
+            } => write!(f, "{} {} {} {}", flags, protocol, algorithm, base64_encode(public_key)),
+            Record::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ref next_hashed,
+                ref types,
+                ..
+            } => {
+                write!(f, "{} {} {} ", hash_algorithm, flags, iterations)?;
+                write_salt(f, salt)?;
+                write!(f, " {}", base32hex_encode(next_hashed))?;
+                write_types(f, types)
+            }
+            Record::NSEC3PARAM {
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ..
+            } => {
+                write!(f, "{} {} {} ", hash_algorithm, flags, iterations)?;
+                write_salt(f, salt)
+            }
+            Record::UNKNOWN { ref data, .. } => write_generic_rdata(f, data),
+            Record::OPT { .. } => Ok(()),
+        }
+    }
+}
+
+/// Reads the bytes from the current position to the end of the RDATA.
+fn read_rest<T: PacketBuffer>(buffer: &mut T, data_start: usize, data_len: u16) -> Result<Vec<u8>> {
+    let len = (data_start + data_len as usize)
+        .checked_sub(buffer.pos())
+        .ok_or(WireError::RdataLengthMismatch {
+            pos: data_start,
+            expected: data_len as usize,
+            actual: buffer.pos() - data_start,
+        })?;
+    let data = buffer.get_range(buffer.pos(), len)?.to_vec();
+    buffer.step(len)?;
+
+    Ok(data)
+}
+
+fn write_bytes<T: PacketBuffer>(buffer: &mut T, data: &[u8]) -> Result<()> {
+    for b in data {
+        buffer.write_u8(*b)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the type bitmap that closes NSEC and NSEC3 RDATA (RFC 4034
+/// 4.1.2). Only the canonical encoding is accepted, so that writing the
+/// types back reproduces the same bytes.
+fn read_type_bitmap<T: PacketBuffer>(buffer: &mut T, end: usize) -> Result<Vec<QueryType>> {
+    let mut types = Vec::new();
+    let mut last_window = None;
+
+    while buffer.pos() < end {
+        let pos = buffer.pos();
+        let window = buffer.read()?;
+        let len = buffer.read()? as usize;
+        let bitmap = buffer.get_range(buffer.pos(), len)?;
+        if len == 0 || len > 32 || bitmap[len - 1] == 0 || last_window.is_some_and(|last| window <= last) {
+            return Err(WireError::InvalidRdata {
+                pos,
+                reason: "malformed type bitmap",
+            });
+        }
+
+        for (i, byte) in bitmap.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push(QueryType::from_num((window as u16) << 8 | (i * 8 + bit) as u16));
+                }
+            }
+        }
+
+        last_window = Some(window);
+        buffer.step(len)?;
+    }
+
+    Ok(types)
+}
+
+fn write_type_bitmap<T: PacketBuffer>(buffer: &mut T, types: &[QueryType]) -> Result<()> {
+    let mut nums: Vec<u16> = types.iter().map(|qtype| qtype.to_num()).collect();
+    nums.sort_unstable();
+    nums.dedup();
+
+    for block in nums.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bitmap = [0u8; 32];
+        for num in block {
+            let low = (num & 0xFF) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+        }
+        let len = (block[block.len() - 1] & 0xFF) as usize / 8 + 1;
+
+        buffer.write_u8((block[0] >> 8) as u8)?;
+        buffer.write_u8(len as u8)?;
+        write_bytes(buffer, &bitmap[..len])?;
+    }
+
+    Ok(())
+}
+
+/// Generic RDATA presentation from RFC 3597 section 5, e.g. `\# 3 abcdef`.
+fn write_generic_rdata(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    write!(f, "\\# {}", data.len())?;
+
+    if !data.is_empty() {
+        write!(f, " ")?;
+        for b in data {
+            write!(f, "{:02x}", b)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The types of an NSEC or NSEC3 bitmap, each preceded by a space.
+fn write_types(f: &mut fmt::Formatter<'_>, types: &[QueryType]) -> fmt::Result {
+    for qtype in types {
+        write!(f, " {}", qtype)?;
+    }
+
+    Ok(())
+}
+
+/// An NSEC3 salt in hex, or `-` when there is none (RFC 5155 3.3).
+fn write_salt(f: &mut fmt::Formatter<'_>, salt: &[u8]) -> fmt::Result {
+    if salt.is_empty() {
+        write!(f, "-")
+    } else {
+        write!(f, "{}", hex_encode(salt))
+    }
+}
+
+/// Displays a domain name with its trailing dot. Backslashes are escaped
+/// and bytes that are not printable written as `\DDD` (RFC 1035 section
+/// 5.1).
+struct Fqdn<'a>(&'a str);
+
+impl fmt::Display for Fqdn<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &b in self.0.trim_end_matches('.').as_bytes() {
+            match b {
+                b'\\' => write!(f, "\\\\")?,
+                0x21..=0x7E => write!(f, "{}", b as char)?,
+                _ => write!(f, "\\{:03}", b)?,
+            }
+        }
+        write!(f, ".")
+    }
+}
+
+/// Quotes `data`, escaping quotes and backslashes and writing anything not
+/// printable as `\DDD` (RFC 1035 section 5.1).
+fn write_character_string(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for &b in data {
+        match b {
+            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+            0x20..=0x7E => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{:03}", b)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{BytePacketBuffer, SlicePacketBuffer, MAX_MESSAGE_SIZE};
+
+    /// Writes `records` one after the other and returns the message bytes.
+    fn encode(records: &[Record]) -> Vec<u8> {
+        let mut buffer = BytePacketBuffer::with_capacity(MAX_MESSAGE_SIZE);
+        for record in records {
+            record.write(&mut buffer).unwrap();
+        }
+        buffer.buf[..buffer.pos].to_vec()
+    }
+
+    fn round_trip(record: Record) -> Record {
+        let data = encode(std::slice::from_ref(&record));
+        let mut buffer = SlicePacketBuffer::new(&data);
+        let read = Record::read(&mut buffer).unwrap();
+        assert_eq!(buffer.pos(), data.len());
+        assert_eq!(read, record);
+        read
+    }
+
+    fn txt(data: Vec<Vec<u8>>) -> Record {
+        Record::TXT {
+            domain: "example.com".to_string(),
+            class: QueryClass::IN,
+            data,
+            ttl: 300,
+        }
+    }
+
+    fn caa(tag: &str, value: &[u8]) -> Record {
+        Record::CAA {
+            domain: "example.com".to_string(),
+            class: QueryClass::IN,
+            flags: 128,
+            tag: tag.to_string(),
+            value: value.to_vec(),
+            ttl: 300,
+        }
+    }
+
+    fn srv(host: &str) -> Record {
+        Record::SRV {
+            domain: "_sip._tcp.example.com".to_string(),
+            class: QueryClass::IN,
+            priority: 10,
+            weight: 5,
+            port: 5060,
+            host: host.to_string(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn txt_round_trips() {
+        let record = round_trip(txt(vec![b"v=spf1 -all".to_vec(), b"a\"b\\c\x01".to_vec(), Vec::new()]));
+        assert_eq!(record.to_string(), r#"example.com. 300 IN TXT "v=spf1 -all" "a\"b\\c\001" """#);
+
+        round_trip(txt(vec![vec![b'x'; 255], vec![b'y'; 255]]));
+    }
+
+    #[test]
+    fn txt_strings_are_limited_to_255_bytes() {
+        let mut buffer = BytePacketBuffer::new();
+        let result = txt(vec![vec![b'x'; 256]]).write(&mut buffer);
+        assert!(matches!(result, Err(WireError::InvalidRdata { .. })));
+    }
+
+    #[test]
+    fn records_keep_their_class() {
+        let record = round_trip(Record::TXT {
+            domain: "version.bind".to_string(),
+            class: QueryClass::CH,
+            data: vec![b"my_dns".to_vec()],
+            ttl: 0,
+        });
+        assert_eq!(record.to_string(), r#"version.bind. 0 CH TXT "my_dns""#);
+    }
+
+    #[test]
+    fn ptr_round_trips() {
+        let record = round_trip(Record::PTR {
+            domain: "1.2.0.192.in-addr.arpa".to_string(),
+            class: QueryClass::IN,
+            host: "host.example.com".to_string(),
+            ttl: 300,
+        });
+        assert_eq!(record.to_string(), "1.2.0.192.in-addr.arpa. 300 IN PTR host.example.com.");
+    }
+
+    #[test]
+    fn srv_round_trips_with_an_uncompressed_target() {
+        let record = round_trip(srv("sip.example.com"));
+        assert_eq!(record.to_string(), "_sip._tcp.example.com. 300 IN SRV 10 5 5060 sip.example.com.");
+
+        // The owner has already written `example.com`, yet the target
+        // spells it out again (RFC 2782).
+        let data = encode(&[srv("sip.example.com")]);
+        let target = b"\x03sip\x07example\x03com\x00";
+        assert!(data.ends_with(target));
+    }
+
+    #[test]
+    fn names_escape_unprintable_bytes() {
+        let record = Record::NSEC {
+            domain: "host.example".to_string(),
+            class: QueryClass::IN,
+            next: "\0.host.example".to_string(),
+            types: vec![QueryType::A, QueryType::RRSIG, QueryType::NSEC],
+            ttl: 300,
+        };
+        assert_eq!(record.to_string(), r"host.example. 300 IN NSEC \000.host.example. A RRSIG NSEC");
+
+        let record = Record::PTR {
+            domain: String::new(),
+            class: QueryClass::IN,
+            host: "odd name\\.example".to_string(),
+            ttl: 300,
+        };
+        assert_eq!(record.to_string(), r". 300 IN PTR odd\032name\\.example.");
+    }
+
+    #[test]
+    fn caa_round_trips() {
+        let record = round_trip(caa("issue", b"letsencrypt.org"));
+        assert_eq!(record.to_string(), r#"example.com. 300 IN CAA 128 issue "letsencrypt.org""#);
+
+        round_trip(caa("iodef", b""));
+        round_trip(caa(&"t".repeat(255), b"value"));
+    }
+
+    #[test]
+    fn caa_tags_are_1_to_255_bytes() {
+        for tag in [String::new(), "t".repeat(256)] {
+            let mut buffer = BytePacketBuffer::new();
+            let result = caa(&tag, b"value").write(&mut buffer);
+            assert!(matches!(result, Err(WireError::InvalidRdata { .. })), "tag of {} bytes", tag.len());
+        }
+    }
+
+    #[test]
+    fn caa_tag_longer_than_rdata_is_rejected() {
+        let mut data = encode(&[caa("issue", b"ca.example")]);
+        // The tag length byte follows the flags, right after RDLENGTH.
+        let tag_len = data.len() - b"issueca.example".len() - 1;
+        data[tag_len] = 200;
+        // Padding so the oversized tag can be read at all.
+        data.extend_from_slice(&[0; 256]);
+
+        let result = Record::read(&mut SlicePacketBuffer::new(&data));
+        assert!(matches!(result, Err(WireError::RdataLengthMismatch { .. })));
+    }
+
+    #[test]
+    fn soa_round_trips_with_compressed_names() {
+        let soa = Record::SOA {
+            domain: "example.com".to_string(),
+            class: QueryClass::IN,
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+            ttl: 3600,
+        };
+        let record = round_trip(soa.clone());
+        assert_eq!(
+            record.to_string(),
+            "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300"
+        );
+
+        // Both names point back at the owner's `example.com`.
+        let data = encode(&[soa]);
+        let rdata = &data[13 + 10..];
+        // Each name is its first label and a pointer, then five counters.
+        assert_eq!(rdata.len(), (4 + 2) + (11 + 2) + 5 * 4);
+        assert_eq!(&rdata[..6], b"\x03ns1\xc0\x00");
+    }
+
+    #[test]
+    fn soa_rdlength_must_match_its_fields() {
+        let mut data = encode(&[Record::SOA {
+            domain: "example.com".to_string(),
+            class: QueryClass::IN,
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 1,
+            refresh: 2,
+            retry: 3,
+            expire: 4,
+            minimum: 5,
+            ttl: 3600,
+        }]);
+        // RDLENGTH sits right before the RDATA, after the 13 byte owner.
+        data[13 + 9] += 1;
+        data.push(0);
+
+        let result = Record::read(&mut SlicePacketBuffer::new(&data));
+        assert!(matches!(result, Err(WireError::RdataLengthMismatch { .. })));
+    }
+
+    fn unknown(domain: &str, class: QueryClass, qtype: u16, data: &[u8]) -> Record {
+        Record::UNKNOWN {
+            domain: domain.to_string(),
+            class,
+            qtype,
+            data: data.to_vec(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn unknown_types_round_trip_opaquely() {
+        let record = round_trip(unknown("example.com", QueryClass::IN, 731, &[0xab, 0xcd, 0xef]));
+        assert_eq!(record.to_string(), r"example.com. 300 IN TYPE731 \# 3 abcdef");
+
+        let record = round_trip(unknown("example.com", QueryClass::UNKNOWN(42), 65280, &[]));
+        assert_eq!(record.to_string(), r"example.com. 300 CLASS42 TYPE65280 \# 0");
+    }
+
+    #[test]
+    fn unknown_rdata_is_never_rewritten() {
+        // Bytes that would read as a compression pointer stay as they are,
+        // and names in RDATA are never pointed to later.
+        let records = [
+            unknown("example.org", QueryClass::IN, 731, &[0xc0, 0x00, 0x07]),
+            unknown("example.net", QueryClass::IN, 732, b"\x07example\x03com\x00"),
+            unknown("example.com", QueryClass::IN, 733, &[]),
+        ];
+        let data = encode(&records);
+
+        let mut buffer = SlicePacketBuffer::new(&data);
+        for record in records {
+            assert_eq!(Record::read(&mut buffer).unwrap(), record);
+        }
+        let owner = b"\x07example\x03com\x00";
+        assert_eq!(&data[data.len() - 10 - owner.len()..data.len() - 10], owner);
+    }
+
+    fn nsec(types: Vec<QueryType>) -> Record {
+        Record::NSEC {
+            domain: "alfa.example.com".to_string(),
+            class: QueryClass::IN,
+            next: "host.example.com".to_string(),
+            types,
+            ttl: 86400,
+        }
+    }
+
+    #[test]
+    fn dnssec_records_round_trip() {
+        let records = [
+            Record::DNSKEY {
+                domain: "example.com".to_string(),
+                class: QueryClass::IN,
+                flags: 257,
+                protocol: 3,
+                algorithm: 15,
+                public_key: vec![7; 32],
+                ttl: 3600,
+            },
+            Record::DS {
+                domain: "example.com".to_string(),
+                class: QueryClass::IN,
+                key_tag: 60485,
+                algorithm: 5,
+                digest_type: 1,
+                digest: vec![0x2b; 20],
+                ttl: 3600,
+            },
+            Record::RRSIG {
+                domain: "host.example.com".to_string(),
+                class: QueryClass::IN,
+                type_covered: QueryType::A,
+                algorithm: 5,
+                labels: 3,
+                original_ttl: 86400,
+                expiration: 1048354263,
+                inception: 1045762263,
+                key_tag: 2642,
+                signer: "example.com".to_string(),
+                signature: vec![0xa0; 128],
+                ttl: 86400,
+            },
+            nsec(vec![QueryType::A, QueryType::MX, QueryType::RRSIG, QueryType::NSEC, QueryType::UNKNOWN(1234)]),
+            Record::NSEC3 {
+                domain: "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example.com".to_string(),
+                class: QueryClass::IN,
+                hash_algorithm: 1,
+                flags: 1,
+                iterations: 12,
+                salt: vec![0xaa, 0xbb, 0xcc, 0xdd],
+                next_hashed: vec![0x17; 20],
+                types: vec![QueryType::NS, QueryType::SOA, QueryType::CAA],
+                ttl: 86400,
+            },
+            Record::NSEC3PARAM {
+                domain: "example.com".to_string(),
+                class: QueryClass::IN,
+                hash_algorithm: 1,
+                flags: 0,
+                iterations: 0,
+                salt: Vec::new(),
+                ttl: 0,
+            },
+        ];
+        for record in records {
+            round_trip(record);
+        }
+    }
+
+    #[test]
+    fn dnssec_rdata_names_are_not_compressed() {
+        // RFC 4034 section 4.3, with the owner already written.
+        let data = encode(&[nsec(vec![QueryType::A, QueryType::MX, QueryType::RRSIG, QueryType::NSEC, QueryType::UNKNOWN(1234)])]);
+        let mut rdata = b"\x04host\x07example\x03com\x00".to_vec();
+        rdata.extend_from_slice(&[0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03, 0x04, 0x1b]);
+        rdata.extend_from_slice(&[0; 26]);
+        rdata.push(0x20);
+        assert!(data.ends_with(&rdata));
+    }
+
+    #[test]
+    fn nsec_bitmaps_are_checked() {
+        let mut data = encode(&[nsec(vec![QueryType::A])]);
+        // A trailing zero byte in the last window is not allowed.
+        *data.last_mut().unwrap() = 0;
+        assert!(Record::read(&mut SlicePacketBuffer::new(&data)).is_err());
+
+        // Nor is a window of more than 32 bytes.
+        let mut data = encode(&[nsec(Vec::new())]);
+        let next_len = b"\x04host\x07example\x03com\x00".len();
+        let rdlength = data.len() - next_len - 2;
+        data.extend_from_slice(&[0, 33]);
+        data.extend_from_slice(&[1; 33]);
+        let len = (data.len() - rdlength - 2) as u16;
+        data[rdlength..rdlength + 2].copy_from_slice(&len.to_be_bytes());
+        assert!(Record::read(&mut SlicePacketBuffer::new(&data)).is_err());
+    }
+}
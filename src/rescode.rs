@@ -1,23 +1,109 @@
+/// Response codes from the IANA registry. Codes above 15 only exist in
+/// combination with the upper 8 bits carried in the EDNS OPT record.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ResultCode {
-    NOERROR = 0,
-    FORMERR = 1,
-    SERVFAIL = 2,
-    NXDOMAIN = 3,
-    NOTIMP = 4,
-    REFUSED = 5,
+    NOERROR,
+    FORMERR,
+    SERVFAIL,
+    NXDOMAIN,
+    NOTIMP,
+    REFUSED,
+    YXDOMAIN,
+    YXRRSET,
+    NXRRSET,
+    NOTAUTH,
+    NOTZONE,
+    DSOTYPENI,
+    /// Also BADSIG when it appears in a TSIG record.
+    BADVERS,
+    BADKEY,
+    BADTIME,
+    BADMODE,
+    BADNAME,
+    BADALG,
+    BADTRUNC,
+    BADCOOKIE,
+    UNKNOWN(u16),
 }
 
 impl ResultCode {
-    pub fn from_num(num: u8) -> ResultCode {
-        match num.into() {
-            0_u8 => ResultCode::NOERROR,
-            1_u8 => ResultCode::FORMERR,
-            2_u8 => ResultCode::SERVFAIL,
-            3_u8 => ResultCode::NXDOMAIN,
-            4_u8 => ResultCode::NOTIMP,
-            5_u8 => ResultCode::REFUSED,
-            _ => panic!("Invalid ResultCode: {}", num),
+    pub fn to_num(self) -> u16 {
+        match self {
+            ResultCode::UNKNOWN(num) => num,
+            ResultCode::NOERROR => 0,
+            ResultCode::FORMERR => 1,
+            ResultCode::SERVFAIL => 2,
+            ResultCode::NXDOMAIN => 3,
+            ResultCode::NOTIMP => 4,
+            ResultCode::REFUSED => 5,
+            ResultCode::YXDOMAIN => 6,
+            ResultCode::YXRRSET => 7,
+            ResultCode::NXRRSET => 8,
+            ResultCode::NOTAUTH => 9,
+            ResultCode::NOTZONE => 10,
+            ResultCode::DSOTYPENI => 11,
+            ResultCode::BADVERS => 16,
+            ResultCode::BADKEY => 17,
+            ResultCode::BADTIME => 18,
+            ResultCode::BADMODE => 19,
+            ResultCode::BADNAME => 20,
+            ResultCode::BADALG => 21,
+            ResultCode::BADTRUNC => 22,
+            ResultCode::BADCOOKIE => 23,
         }
     }
-}
\ No newline at end of file
+
+    pub fn from_num(num: u16) -> ResultCode {
+        match num {
+            0 => ResultCode::NOERROR,
+            1 => ResultCode::FORMERR,
+            2 => ResultCode::SERVFAIL,
+            3 => ResultCode::NXDOMAIN,
+            4 => ResultCode::NOTIMP,
+            5 => ResultCode::REFUSED,
+            6 => ResultCode::YXDOMAIN,
+            7 => ResultCode::YXRRSET,
+            8 => ResultCode::NXRRSET,
+            9 => ResultCode::NOTAUTH,
+            10 => ResultCode::NOTZONE,
+            11 => ResultCode::DSOTYPENI,
+            16 => ResultCode::BADVERS,
+            17 => ResultCode::BADKEY,
+            18 => ResultCode::BADTIME,
+            19 => ResultCode::BADMODE,
+            20 => ResultCode::BADNAME,
+            21 => ResultCode::BADALG,
+            22 => ResultCode::BADTRUNC,
+            23 => ResultCode::BADCOOKIE,
+            _ => ResultCode::UNKNOWN(num),
+        }
+    }
+
+    /// The 8 bits carried in the OPT record's extended RCODE field.
+    pub fn ext_bits(self) -> u8 {
+        (self.to_num() >> 4) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_code_converts_both_ways() {
+        for num in 0..4096 {
+            assert_eq!(ResultCode::from_num(num).to_num(), num);
+        }
+
+        assert_eq!(ResultCode::from_num(12), ResultCode::UNKNOWN(12));
+        assert_eq!(ResultCode::from_num(3841), ResultCode::UNKNOWN(3841));
+    }
+
+    #[test]
+    fn extended_bits_are_the_upper_eight() {
+        assert_eq!(ResultCode::NXDOMAIN.ext_bits(), 0);
+        assert_eq!(ResultCode::BADVERS.ext_bits(), 1);
+        assert_eq!(ResultCode::BADCOOKIE.ext_bits(), 1);
+        assert_eq!(ResultCode::UNKNOWN(4095).ext_bits(), 0xff);
+    }
+}
@@ -0,0 +1,850 @@
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::cache::Cache;
+use crate::buffer::{PacketBuffer, VectorPacketBuffer, EDNS_PAYLOAD_SIZE};
+use crate::dnssec;
+use crate::packet::{is_subdomain, Packet};
+use crate::query::{QueryClass, QueryType};
+use crate::question::Question;
+use crate::random;
+use crate::record::Record;
+use crate::rescode::ResultCode;
+use crate::tcp;
+use crate::upstream::{Selection, UdpClient, Upstreams};
+use crate::validator::{KeyCache, Security, Validator};
+
+/// IANA root hints (IPv4 only), as published in `named.root`.
+pub const ROOT_HINTS: [(&str, Ipv4Addr); 13] = [
+    ("a.root-servers.net", Ipv4Addr::new(198, 41, 0, 4)),
+    ("b.root-servers.net", Ipv4Addr::new(170, 247, 170, 2)),
+    ("c.root-servers.net", Ipv4Addr::new(192, 33, 4, 12)),
+    ("d.root-servers.net", Ipv4Addr::new(199, 7, 91, 13)),
+    ("e.root-servers.net", Ipv4Addr::new(192, 203, 230, 10)),
+    ("f.root-servers.net", Ipv4Addr::new(192, 5, 5, 241)),
+    ("g.root-servers.net", Ipv4Addr::new(192, 112, 36, 4)),
+    ("h.root-servers.net", Ipv4Addr::new(198, 97, 190, 53)),
+    ("i.root-servers.net", Ipv4Addr::new(192, 36, 148, 17)),
+    ("j.root-servers.net", Ipv4Addr::new(192, 58, 128, 30)),
+    ("k.root-servers.net", Ipv4Addr::new(193, 0, 14, 129)),
+    ("l.root-servers.net", Ipv4Addr::new(199, 7, 83, 42)),
+    ("m.root-servers.net", Ipv4Addr::new(202, 12, 27, 33)),
+];
+
+/// Maximum number of referrals followed for a single name.
+const MAX_REFERRALS: usize = 16;
+/// Maximum nesting of lookups (NS name resolution, CNAME chasing).
+const MAX_DEPTH: usize = 8;
+/// Default number of RRsets kept in the cache.
+pub const CACHE_SIZE: usize = 10_000;
+/// Default time to wait on a single upstream attempt.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+/// Default number of passes over the forwarder's upstream list.
+pub const ATTEMPTS: usize = 2;
+
+pub enum ResolveMode {
+    /// Forward every query with RD set to a list of upstreams, failing
+    /// over between them.
+    Forward(Upstreams),
+    /// Iterate from the root hints, following referrals.
+    Recursive,
+}
+
+pub struct Resolver {
+    pub mode: ResolveMode,
+    pub root_hints: Vec<IpAddr>,
+    /// Port authoritative servers are contacted on. Always 53 outside tests.
+    pub port: u16,
+    pub cache: Mutex<Cache>,
+    /// How long a single query to one server may take.
+    pub timeout: Duration,
+    /// How many times the forwarder goes through its upstream list before
+    /// giving up.
+    pub attempts: usize,
+    /// DS or DNSKEY records answers are validated against. Validation is
+    /// off while there are none.
+    pub trust_anchors: Vec<Record>,
+    /// DNSKEY RRsets authenticated while validating earlier answers.
+    pub keys: KeyCache,
+    /// Zones whose names are forwarded to upstreams of their own whatever
+    /// the mode, the longest match winning.
+    pub forward_zones: Vec<(String, Upstreams)>,
+    udp: UdpClient,
+}
+
+impl Resolver {
+    pub fn new() -> Result<Resolver> {
+        Ok(Resolver {
+            mode: ResolveMode::Recursive,
+            root_hints: ROOT_HINTS.iter().map(|(_, ip)| IpAddr::V4(*ip)).collect(),
+            port: 53,
+            cache: Mutex::new(Cache::new(CACHE_SIZE)),
+            timeout: QUERY_TIMEOUT,
+            attempts: ATTEMPTS,
+            trust_anchors: Vec::new(),
+            keys: KeyCache::new(),
+            forward_zones: Vec::new(),
+            udp: UdpClient::new(),
+        })
+    }
+
+    pub fn forwarding(servers: Vec<SocketAddr>, selection: Selection) -> Result<Resolver> {
+        Ok(Resolver {
+            mode: ResolveMode::Forward(Upstreams::new(servers, selection)),
+            ..Resolver::new()?
+        })
+    }
+
+    pub fn resolve(&self, qname: &str, qtype: QueryType) -> Result<Packet> {
+        let forward_zone = self
+            .forward_zones
+            .iter()
+            .filter(|(zone, _)| is_subdomain(qname, zone))
+            .max_by_key(|(zone, _)| zone.len());
+
+        match (forward_zone, &self.mode) {
+            (Some((_, upstreams)), _) | (None, ResolveMode::Forward(upstreams)) => {
+                if let Some(packet) = self.cached_answer(qname, qtype) {
+                    return Ok(packet);
+                }
+
+                let packet = self.forward(upstreams, qname, qtype)?;
+                if packet.header.rcode == ResultCode::NOERROR && !packet.answers.is_empty() {
+                    self.cache().insert(&packet.answers);
+                } else {
+                    self.cache_negative(qname, qtype, &packet);
+                }
+
+                Ok(packet)
+            }
+            (None, ResolveMode::Recursive) => self.recursive_lookup(qname, qtype, 0),
+        }
+    }
+
+    /// How many upstream responses were discarded for not matching a query.
+    pub fn mismatched_responses(&self) -> u64 {
+        self.udp.mismatched()
+    }
+
+    /// Checks the DNSSEC signatures on a response to `qname` and `qtype`
+    /// from `resolve`, from the trust anchors down.
+    pub fn validate(&self, qname: &str, qtype: QueryType, response: &Packet) -> Security {
+        if self.trust_anchors.is_empty() {
+            return Security::Insecure;
+        }
+
+        Validator::new(self, &self.trust_anchors).validate(qname, qtype, response)
+    }
+
+    /// Sends the query to each upstream in turn until one gives a usable
+    /// answer. SERVFAIL and REFUSED count as failures, and are only passed
+    /// on once every server has been tried `attempts` times.
+    fn forward(&self, upstreams: &Upstreams, qname: &str, qtype: QueryType) -> Result<Packet> {
+        let mut last = Err(Error::other("No upstream servers"));
+
+        for _ in 0..self.attempts {
+            for server in upstreams.order() {
+                let start = Instant::now();
+                match self.lookup(qname, qtype, server, true) {
+                    Ok(packet) if matches!(packet.header.rcode, ResultCode::SERVFAIL | ResultCode::REFUSED) => {
+                        upstreams.record_failure(server, self.timeout);
+                        last = Ok(packet);
+                    }
+                    Ok(packet) => {
+                        upstreams.record_rtt(server, start.elapsed());
+                        return Ok(packet);
+                    }
+                    Err(e) => {
+                        upstreams.record_failure(server, self.timeout);
+                        last = Err(e);
+                    }
+                }
+            }
+        }
+
+        last
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Builds an answer from the cache, following cached CNAME chains.
+    fn cached_answer(&self, qname: &str, qtype: QueryType) -> Option<Packet> {
+        let mut cache = self.cache();
+        let mut packet = Packet::new();
+        let mut name = qname.to_string();
+
+        for _ in 0..MAX_DEPTH {
+            if let Some(negative) = cache.lookup_negative(&name, qtype, QueryClass::IN) {
+                packet.header.rcode = negative.rcode();
+                packet.authorities.extend_from_slice(negative.records());
+                return Some(packet);
+            }
+            if let Some(records) = cache.lookup(&name, qtype, QueryClass::IN) {
+                packet.answers.extend(records);
+                return Some(packet);
+            }
+            if qtype == QueryType::CNAME {
+                return None;
+            }
+
+            let records = cache.lookup(&name, QueryType::CNAME, QueryClass::IN)?;
+            name = records.iter().find_map(|record| match record {
+                Record::CNAME { host, .. } => Some(host.clone()),
+                _ => None,
+            })?;
+            packet.answers.extend(records);
+        }
+
+        None
+    }
+
+    fn cache_negative(&self, qname: &str, qtype: QueryType, response: &Packet) {
+        let class = response.questions.first().map_or(QueryClass::IN, |question| question.qclass);
+        self.cache()
+            .insert_negative(qname, qtype, class, response.header.rcode, &response.authorities);
+    }
+
+    /// The closest enclosing zone we have cached name server addresses
+    /// for, along with those addresses, falling back to the root hints.
+    fn closest_servers(&self, qname: &str) -> (String, Vec<IpAddr>) {
+        let mut cache = self.cache();
+        let mut zone = qname;
+
+        while !zone.is_empty() {
+            if let Some(ns_records) = cache.lookup(zone, QueryType::NS, QueryClass::IN) {
+                let mut servers = Vec::new();
+                for record in ns_records {
+                    if let Record::NS { host, .. } = record {
+                        if let Some(glue) = cache.lookup(&host, QueryType::A, QueryClass::IN) {
+                            servers.extend(glue.iter().filter_map(|r| match r {
+                                Record::A { addr, .. } => Some(IpAddr::V4(*addr)),
+                                _ => None,
+                            }));
+                        }
+                    }
+                }
+                if !servers.is_empty() {
+                    return (zone.to_string(), servers);
+                }
+            }
+
+            zone = match zone.split_once('.') {
+                Some((_, parent)) => parent,
+                None => "",
+            };
+        }
+
+        (String::new(), self.root_hints.clone())
+    }
+
+    /// Remembers the zone cut, DS records and glue of a referral from a
+    /// server for `zone`.
+    fn cache_referral(&self, response: &Packet, qname: &str, zone: &str) {
+        let ns_records: Vec<Record> = response
+            .authorities
+            .iter()
+            .filter(|record| match record {
+                Record::NS { domain, .. } => descends(domain, qname, zone),
+                _ => false,
+            })
+            .cloned()
+            .collect();
+        let ds_records: Vec<Record> = response
+            .authorities
+            .iter()
+            .filter(|record| match record {
+                Record::DS { domain, .. } => descends(domain, qname, zone),
+                Record::RRSIG {
+                    domain,
+                    type_covered: QueryType::DS,
+                    ..
+                } => descends(domain, qname, zone),
+                _ => false,
+            })
+            .cloned()
+            .collect();
+
+        let glue: Vec<Record> = response
+            .resources
+            .iter()
+            .filter(|record| is_subdomain(record.domain(), zone))
+            .filter(|record| {
+                ns_records.iter().any(|ns| match ns {
+                    Record::NS { host, .. } => record.domain().eq_ignore_ascii_case(host),
+                    _ => false,
+                })
+            })
+            .cloned()
+            .collect();
+
+        let mut cache = self.cache();
+        cache.insert(&ns_records);
+        cache.insert(&ds_records);
+        cache.insert(&glue);
+    }
+
+    fn recursive_lookup(&self, qname: &str, qtype: QueryType, depth: usize) -> Result<Packet> {
+        if depth > MAX_DEPTH {
+            return Err(Error::other("Recursion too deep"));
+        }
+
+        if let Some(packet) = self.cached_answer(qname, qtype) {
+            return Ok(packet);
+        }
+
+        // DS records are served by the parent side of the zone cut.
+        let (mut zone, mut servers) = match qtype {
+            QueryType::DS => self.closest_servers(dnssec::parent(qname).unwrap_or(qname)),
+            _ => self.closest_servers(qname),
+        };
+
+        for _ in 0..MAX_REFERRALS {
+            let response = self.query_zone(qname, qtype, &zone, &servers)?;
+
+            // NXDOMAIN, and SERVFAIL from every server, are final.
+            if response.header.rcode != ResultCode::NOERROR {
+                self.cache_negative(qname, qtype, &response);
+                return Ok(response);
+            }
+
+            if !response.answers.is_empty() {
+                self.cache().insert(&response.answers);
+                return self.follow_cname(qname, qtype, response, depth);
+            }
+
+            // An authoritative empty answer is NODATA, never a referral.
+            if response.header.aa {
+                self.cache_negative(qname, qtype, &response);
+                return Ok(response);
+            }
+
+            self.cache_referral(&response, qname, &zone);
+
+            // Whatever survived the bailiwick check leads below `zone`.
+            let cut = response.get_ns(qname).next().map(|(cut, _)| cut.to_ascii_lowercase());
+            let cut = match cut {
+                Some(cut) => cut,
+                None => {
+                    self.cache_negative(qname, qtype, &response);
+                    return Ok(response);
+                }
+            };
+
+            let resolved = response.get_resolved_ns(qname);
+            if !resolved.is_empty() {
+                zone = cut;
+                servers = resolved.into_iter().map(IpAddr::V4).collect();
+                continue;
+            }
+
+            // Referral without glue: the NS names are out of bailiwick and
+            // have to be resolved from the root on their own.
+            let unresolved: Vec<String> = response
+                .get_unresolved_ns(qname)
+                .map(|host| host.to_string())
+                .collect();
+
+            let mut next = Vec::new();
+            for host in unresolved {
+                if let Ok(ns_response) = self.recursive_lookup(&host, QueryType::A, depth + 1) {
+                    next.extend(ns_response.get_a(&host).into_iter().map(IpAddr::V4));
+                }
+                if !next.is_empty() {
+                    break;
+                }
+            }
+            if next.is_empty() {
+                return Ok(response);
+            }
+            zone = cut;
+            servers = next;
+        }
+
+        Err(Error::other("Too many referrals"))
+    }
+
+    /// Completes an answer that stops at a CNAME pointing outside of the
+    /// data the authoritative server returned. A chain that loops, or runs
+    /// longer than `MAX_DEPTH`, is a SERVFAIL.
+    fn follow_cname(&self, qname: &str, qtype: QueryType, mut response: Packet, depth: usize) -> Result<Packet> {
+        if qtype == QueryType::CNAME {
+            return Ok(response);
+        }
+
+        let mut name = qname.to_string();
+        for _ in 0..MAX_DEPTH {
+            let has_target = response.answers.iter().any(|rec| {
+                rec.domain().eq_ignore_ascii_case(&name) && rec.qtype() == qtype
+            });
+            if has_target {
+                return Ok(response);
+            }
+
+            let cname = response.answers.iter().find_map(|rec| match rec {
+                Record::CNAME { domain, host, .. } if domain.eq_ignore_ascii_case(&name) => {
+                    Some(host.clone())
+                }
+                _ => None,
+            });
+
+            match cname {
+                Some(host) if response.answers.iter().any(|rec| rec.domain().eq_ignore_ascii_case(&host)) => {
+                    name = host;
+                }
+                Some(host) => {
+                    let chased = self.recursive_lookup(&host, qtype, depth + 1)?;
+                    response.header.rcode = chased.header.rcode;
+                    response.answers.extend(chased.answers);
+                    return Ok(response);
+                }
+                None => return Ok(response),
+            }
+        }
+
+        response.header.rcode = ResultCode::SERVFAIL;
+        Ok(response)
+    }
+
+    /// Asks the servers of `zone` in turn until one gives a usable response,
+    /// with everything outside of `zone` dropped from it. Errors, SERVFAIL,
+    /// REFUSED and referrals that lead nowhere below `zone` move on to the
+    /// next server; the last of them is returned once all have been tried.
+    fn query_zone(&self, qname: &str, qtype: QueryType, zone: &str, servers: &[IpAddr]) -> Result<Packet> {
+        let mut last = Err(Error::other("No servers to query"));
+
+        for ip in servers {
+            match self.lookup(qname, qtype, SocketAddr::new(*ip, self.port), false) {
+                Ok(mut packet) => {
+                    in_bailiwick(&mut packet, qname, zone);
+                    let failed = matches!(packet.header.rcode, ResultCode::SERVFAIL | ResultCode::REFUSED);
+                    let lame = packet.header.rcode == ResultCode::NOERROR
+                        && packet.answers.is_empty()
+                        && !packet.header.aa
+                        && packet.get_ns(qname).next().is_none();
+                    if !failed && !lame {
+                        return Ok(packet);
+                    }
+                    last = Ok(packet);
+                }
+                Err(e) => last = Err(e),
+            }
+        }
+
+        last
+    }
+
+    /// Queries `server` over UDP, retrying over TCP if the answer came back
+    /// truncated. EDNS is used unless the server rejects it with FORMERR.
+    pub fn lookup(&self, qname: &str, qtype: QueryType, server: SocketAddr, rd: bool) -> Result<Packet> {
+        let mut edns = true;
+        let mut packet = self.lookup_udp(qname, qtype, server, rd, edns)?;
+        if packet.header.rcode == ResultCode::FORMERR {
+            edns = false;
+            packet = self.lookup_udp(qname, qtype, server, rd, edns)?;
+        }
+
+        if !packet.header.tc {
+            return Ok(packet);
+        }
+
+        self.lookup_tcp(qname, qtype, server, rd, edns)
+    }
+
+    fn lookup_udp(&self, qname: &str, qtype: QueryType, server: SocketAddr, rd: bool, edns: bool) -> Result<Packet> {
+        let mut packet = build_query(qname, qtype, rd, edns, !self.trust_anchors.is_empty());
+
+        self.udp.query(&mut packet, server, self.timeout)
+    }
+
+    fn lookup_tcp(&self, qname: &str, qtype: QueryType, server: SocketAddr, rd: bool, edns: bool) -> Result<Packet> {
+        let mut stream = TcpStream::connect_timeout(&server, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut query = build_query(qname, qtype, rd, edns, !self.trust_anchors.is_empty());
+        query.header.id = random::random_u16();
+
+        let mut req_buffer = VectorPacketBuffer::new();
+        query.write(&mut req_buffer)?;
+        tcp::write_message(&mut stream, req_buffer.get_range(0, req_buffer.pos())?)?;
+
+        let mut res_buffer = VectorPacketBuffer::new();
+        if !tcp::read_message(&mut stream, &mut res_buffer)? {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Connection closed"));
+        }
+
+        let response = Packet::from_buffer(&mut res_buffer)?;
+        if response.header.id != query.header.id || response.questions != query.questions {
+            return Err(Error::new(ErrorKind::InvalidData, "Response does not match the query"));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Whether a referral from a server for `zone` to the zone `cut` leads
+/// closer to `qname`: strictly below `zone`, and enclosing `qname`.
+fn descends(cut: &str, qname: &str, zone: &str) -> bool {
+    is_subdomain(qname, cut) && is_subdomain(cut, zone) && !cut.eq_ignore_ascii_case(zone)
+}
+
+/// Drops what a server for `zone` has no authority over from its response
+/// to `qname`: answers and additional records outside of `zone`, and
+/// delegations that do not lead below it. Of several delegations only the
+/// deepest is kept.
+fn in_bailiwick(response: &mut Packet, qname: &str, zone: &str) {
+    response.answers.retain(|record| is_subdomain(record.domain(), zone));
+    response.authorities.retain(|record| match record {
+        Record::NS { domain, .. } | Record::DS { domain, .. } => descends(domain, qname, zone),
+        Record::RRSIG {
+            domain,
+            type_covered: QueryType::DS,
+            ..
+        } => descends(domain, qname, zone),
+        _ => is_subdomain(record.domain(), zone),
+    });
+    response
+        .resources
+        .retain(|record| matches!(record, Record::OPT { .. }) || is_subdomain(record.domain(), zone));
+
+    let deepest = response
+        .get_ns(qname)
+        .map(|(cut, _)| cut.to_string())
+        .max_by_key(|cut| cut.len());
+    if let Some(deepest) = deepest {
+        response.authorities.retain(|record| match record {
+            Record::NS { domain, .. } => domain.eq_ignore_ascii_case(&deepest),
+            _ => true,
+        });
+    }
+}
+
+/// With `dnssec` set the query asks for signatures (DO) and for data that
+/// failed validation upstream (CD), since we validate ourselves.
+fn build_query(qname: &str, qtype: QueryType, rd: bool, edns: bool, dnssec: bool) -> Packet {
+    let mut packet = Packet::new();
+
+    packet.header.qdcount = 1;
+    packet.header.rd = rd;
+    packet.header.cd = dnssec;
+    packet
+        .questions
+        .push(Question::new(qname.to_string(), qtype));
+
+    if edns {
+        packet.resources.push(Record::OPT {
+            packet_len: EDNS_PAYLOAD_SIZE,
+            ext_rcode: 0,
+            version: 0,
+            dnssec_ok: dnssec,
+            options: Vec::new(),
+        });
+    }
+
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{BytePacketBuffer, SlicePacketBuffer};
+    use crate::testing::{a, cname, ns, soa};
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    type Handler = Box<dyn Fn(&Question) -> Packet + Send>;
+
+    /// Starts one fake authoritative server per address, all on the same
+    /// free port, and returns that port.
+    fn start(servers: Vec<(&str, Handler)>) -> u16 {
+        let mut port = 0;
+        for (ip, handler) in servers {
+            let socket = UdpSocket::bind((ip, port)).unwrap();
+            port = socket.local_addr().unwrap().port();
+            thread::spawn(move || loop {
+                let mut buffer = BytePacketBuffer::new();
+                let (len, src) = socket.recv_from(&mut buffer.buf).unwrap();
+                let query = Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf[..len])).unwrap();
+
+                let mut response = handler(&query.questions[0]);
+                response.header.id = query.header.id;
+                response.header.qr = true;
+                response.questions = query.questions.clone();
+                let mut out = BytePacketBuffer::new();
+                response.write(&mut out).unwrap();
+                socket.send_to(&out.buf[..out.pos], src).unwrap();
+            });
+        }
+        port
+    }
+
+    fn resolver(root: &str, port: u16) -> Resolver {
+        let mut resolver = Resolver::new().unwrap();
+        resolver.root_hints = vec![root.parse().unwrap()];
+        resolver.port = port;
+        resolver.timeout = Duration::from_millis(500);
+        resolver
+    }
+
+    /// A referral to `zone`, with glue for each of its `servers`.
+    fn referral(zone: &str, servers: &[(&str, &str)]) -> Packet {
+        let mut packet = Packet::new();
+        for (host, addr) in servers {
+            packet.authorities.push(ns(zone, host));
+            packet.resources.push(a(host, addr));
+        }
+        packet
+    }
+
+    fn answer(records: Vec<Record>) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.aa = true;
+        packet.answers = records;
+        packet
+    }
+
+    fn failure(rcode: ResultCode) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.rcode = rcode;
+        packet
+    }
+
+    /// A negative answer from the example.com zone.
+    fn denial(rcode: ResultCode) -> Packet {
+        let mut packet = failure(rcode);
+        packet.header.aa = true;
+        packet.authorities.push(soa("example.com"));
+        packet
+    }
+
+    #[test]
+    fn follows_referrals_from_the_root() {
+        let port = start(vec![
+            ("127.1.1.1", Box::new(|_: &Question| referral("com", &[("a.gtld.com", "127.1.1.2")]))),
+            (
+                "127.1.1.2",
+                Box::new(|_: &Question| referral("example.com", &[("ns.example.com", "127.1.1.3")])),
+            ),
+            ("127.1.1.3", Box::new(|q: &Question| answer(vec![a(&q.name, "192.0.2.1")]))),
+        ]);
+        let resolver = resolver("127.1.1.1", port);
+
+        let response = resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(response.answers, vec![a("www.example.com", "192.0.2.1")]);
+
+        // The zone cut was cached, so the next name starts below the root.
+        let (zone, servers) = resolver.closest_servers("mail.example.com");
+        assert_eq!(zone, "example.com");
+        assert_eq!(servers, vec!["127.1.1.3".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn drops_records_out_of_bailiwick() {
+        let port = start(vec![
+            (
+                "127.1.2.1",
+                Box::new(|_: &Question| referral("com", &[("a.gtld.com", "127.1.2.2")])),
+            ),
+            (
+                "127.1.2.2",
+                Box::new(|_: &Question| {
+                    let mut packet = referral("example.com", &[("ns.example.com", "127.1.2.3")]);
+                    packet.authorities.push(ns("org", "ns.victim.org"));
+                    packet.resources.push(a("ns.victim.org", "198.51.100.6"));
+                    packet
+                }),
+            ),
+            (
+                "127.1.2.3",
+                Box::new(|q: &Question| {
+                    let mut packet = answer(vec![a(&q.name, "192.0.2.1"), a("www.victim.org", "198.51.100.6")]);
+                    packet.resources.push(a("ns.victim.org", "198.51.100.6"));
+                    packet
+                }),
+            ),
+        ]);
+        let resolver = resolver("127.1.2.1", port);
+
+        let response = resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(response.answers, vec![a("www.example.com", "192.0.2.1")]);
+        assert!(response.resources.iter().all(|record| record.domain() != "ns.victim.org"));
+
+        let mut cache = resolver.cache();
+        assert!(cache.lookup("www.victim.org", QueryType::A, QueryClass::IN).is_none());
+        assert!(cache.lookup("ns.victim.org", QueryType::A, QueryClass::IN).is_none());
+        assert!(cache.lookup("org", QueryType::NS, QueryClass::IN).is_none());
+    }
+
+    #[test]
+    fn skips_referrals_that_do_not_lead_down() {
+        let port = start(vec![
+            (
+                "127.1.3.1",
+                Box::new(|_: &Question| {
+                    referral(
+                        "com",
+                        &[("a.gtld.com", "127.1.3.2"), ("b.gtld.com", "127.1.3.3"), ("c.gtld.com", "127.1.3.4")],
+                    )
+                }),
+            ),
+            // Back up to the root.
+            (
+                "127.1.3.2",
+                Box::new(|_: &Question| referral("", &[("a.root-servers.net", "127.1.3.1")])),
+            ),
+            // Sideways, to a zone not enclosing the name.
+            (
+                "127.1.3.3",
+                Box::new(|_: &Question| referral("example.net", &[("ns.example.net", "127.1.3.1")])),
+            ),
+            ("127.1.3.4", Box::new(|q: &Question| answer(vec![a(&q.name, "192.0.2.3")]))),
+        ]);
+        let resolver = resolver("127.1.3.1", port);
+
+        let response = resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(response.answers, vec![a("www.example.com", "192.0.2.3")]);
+        assert!(resolver.cache().lookup("example.net", QueryType::NS, QueryClass::IN).is_none());
+    }
+
+    #[test]
+    fn tries_other_servers_on_servfail_and_refused() {
+        let port = start(vec![
+            (
+                "127.1.4.1",
+                Box::new(|_: &Question| {
+                    referral(
+                        "com",
+                        &[("a.gtld.com", "127.1.4.2"), ("b.gtld.com", "127.1.4.3"), ("c.gtld.com", "127.1.4.4")],
+                    )
+                }),
+            ),
+            ("127.1.4.2", Box::new(|_: &Question| failure(ResultCode::SERVFAIL))),
+            ("127.1.4.3", Box::new(|_: &Question| failure(ResultCode::REFUSED))),
+            ("127.1.4.4", Box::new(|q: &Question| answer(vec![a(&q.name, "192.0.2.4")]))),
+        ]);
+        let resolver = resolver("127.1.4.1", port);
+
+        let response = resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(response.header.rcode, ResultCode::NOERROR);
+        assert_eq!(response.answers, vec![a("www.example.com", "192.0.2.4")]);
+    }
+
+    #[test]
+    fn servfail_from_every_server_is_final() {
+        let port = start(vec![
+            (
+                "127.1.5.1",
+                Box::new(|_: &Question| referral("com", &[("a.gtld.com", "127.1.5.2"), ("b.gtld.com", "127.1.5.3")])),
+            ),
+            ("127.1.5.2", Box::new(|_: &Question| failure(ResultCode::SERVFAIL))),
+            ("127.1.5.3", Box::new(|_: &Question| failure(ResultCode::REFUSED))),
+        ]);
+        let resolver = resolver("127.1.5.1", port);
+
+        let response = resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(response.header.rcode, ResultCode::REFUSED);
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn looping_cnames_are_a_servfail() {
+        let port = start(vec![(
+            "127.1.14.1",
+            Box::new(|q: &Question| match q.name.as_str() {
+                "self.example.com" => answer(vec![cname("self.example.com", "SELF.example.com")]),
+                _ => answer(vec![cname("a.example.com", "b.example.com"), cname("b.example.com", "a.example.com")]),
+            }),
+        )]);
+        let resolver = resolver("127.1.14.1", port);
+
+        for name in ["a.example.com", "self.example.com"] {
+            let response = resolver.resolve(name, QueryType::A).unwrap();
+            assert_eq!(response.header.rcode, ResultCode::SERVFAIL, "{}", name);
+            // The loop is cached, and answering from the cache ends too.
+            assert!(resolver.resolve(name, QueryType::A).is_ok());
+        }
+    }
+
+    #[test]
+    fn negative_answers_are_cached() {
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&queries);
+        let port = start(vec![(
+            "127.1.12.1",
+            Box::new(move |q: &Question| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                match q.name.as_str() {
+                    "gone.example.com" => denial(ResultCode::NXDOMAIN),
+                    _ => denial(ResultCode::NOERROR),
+                }
+            }),
+        )]);
+        let upstream = SocketAddr::new("127.1.12.1".parse().unwrap(), port);
+        let resolver = Resolver::forwarding(vec![upstream], Selection::FastestFirst).unwrap();
+
+        let response = resolver.resolve("gone.example.com", QueryType::A).unwrap();
+        assert_eq!(response.header.rcode, ResultCode::NXDOMAIN);
+        // Answered from the cache, the SOA lives no longer than its MINIMUM.
+        let response = resolver.resolve("gone.example.com", QueryType::A).unwrap();
+        assert_eq!(response.header.rcode, ResultCode::NXDOMAIN);
+        assert!(response.authorities[0].ttl() <= 300);
+        // NXDOMAIN holds for every type of the name.
+        let response = resolver.resolve("gone.example.com", QueryType::MX).unwrap();
+        assert_eq!(response.header.rcode, ResultCode::NXDOMAIN);
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+        for _ in 0..2 {
+            let response = resolver.resolve("www.example.com", QueryType::MX).unwrap();
+            assert_eq!(response.header.rcode, ResultCode::NOERROR);
+            assert!(response.answers.is_empty());
+            assert_eq!(response.authorities.len(), 1);
+        }
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // NODATA says nothing about the name's other types.
+        resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn truncated_answers_are_retried_over_tcp() {
+        let port = start(vec![(
+            "127.1.13.1",
+            Box::new(|_: &Question| {
+                let mut packet = Packet::new();
+                packet.header.tc = true;
+                packet
+            }),
+        )]);
+        let listener = TcpListener::bind(("127.1.13.1", port)).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buffer = VectorPacketBuffer::new();
+                tcp::read_message(&mut stream, &mut buffer).unwrap();
+                let query = Packet::from_buffer(&mut buffer).unwrap();
+
+                let name = &query.questions[0].name;
+                let mut response = answer((0..100).map(|i| a(name, &format!("10.0.0.{}", i))).collect());
+                response.header.id = query.header.id;
+                response.header.qr = true;
+                response.questions = query.questions;
+                let mut out = VectorPacketBuffer::new();
+                response.write(&mut out).unwrap();
+                tcp::write_message(&mut stream, &out.buf).unwrap();
+            }
+        });
+        let upstream = SocketAddr::new("127.1.13.1".parse().unwrap(), port);
+        let resolver = Resolver::forwarding(vec![upstream], Selection::FastestFirst).unwrap();
+
+        let response = resolver.resolve("big.example.com", QueryType::A).unwrap();
+        assert!(!response.header.tc);
+        assert_eq!(response.answers.len(), 100);
+    }
+}
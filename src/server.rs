@@ -0,0 +1,678 @@
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::buffer::{
+    BytePacketBuffer, PacketBuffer, SlicePacketBuffer, VectorPacketBuffer, EDNS_PAYLOAD_SIZE,
+    MAX_MESSAGE_SIZE, UDP_MESSAGE_SIZE,
+};
+use crate::acl::Acl;
+use crate::cache::Cache;
+use crate::chaos;
+use crate::config::{Config, Listener, Protocol};
+use crate::dnssec;
+use crate::error::WireError;
+use crate::header::Header;
+use crate::packet::Packet;
+use crate::pool::ThreadPool;
+use crate::query::QueryClass;
+use crate::record::Record;
+use crate::rescode::ResultCode;
+use crate::resolver::Resolver;
+use crate::signer::SigningKey;
+use crate::tcp;
+use crate::upstream::Upstreams;
+use crate::validator::Security;
+use crate::zone::{Authority, Zone};
+use crate::zonefile;
+
+/// Everything queries are answered from: our own zones first, then the
+/// resolver for names outside of them.
+pub struct ServerContext {
+    pub authority: Authority,
+    pub resolver: Resolver,
+    /// Clients answered at all.
+    pub allow_query: Acl,
+    /// Clients the resolver works for.
+    pub allow_recursion: Acl,
+    /// Whether every query and answer is printed.
+    pub log_queries: bool,
+}
+
+impl ServerContext {
+    /// Answers queries from our zones and the resolver, with no
+    /// restrictions on clients.
+    pub fn new(authority: Authority, resolver: Resolver) -> ServerContext {
+        ServerContext {
+            authority,
+            resolver,
+            allow_query: Acl::any(),
+            allow_recursion: Acl::any(),
+            log_queries: true,
+        }
+    }
+
+    /// Loads the zones, keys and trust anchors `config` names and sets up
+    /// the resolver it describes.
+    pub fn from_config(config: &Config) -> Result<ServerContext> {
+        let group = |name: &str| {
+            config
+                .upstreams
+                .get(name)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("no upstream group `{}`", name)))
+        };
+
+        let settings = &config.resolver;
+        let mut resolver = match settings.upstream {
+            Some(ref name) => {
+                let group = group(name)?;
+                Resolver::forwarding(group.servers.clone(), group.selection)?
+            }
+            None => Resolver::new()?,
+        };
+        resolver.timeout = settings.timeout;
+        resolver.attempts = settings.attempts;
+        resolver.cache = Mutex::new(Cache::new(settings.cache_size));
+        for forward in &config.forward_zones {
+            let group = group(&forward.upstream)?;
+            let upstreams = Upstreams::new(group.servers.clone(), group.selection);
+            resolver.forward_zones.push((forward.zone.clone(), upstreams));
+        }
+
+        if settings.dnssec {
+            resolver.trust_anchors.extend(dnssec::root_trust_anchors());
+        }
+        for path in &settings.trust_anchors {
+            let anchors = zonefile::load(path, "")?;
+            resolver.trust_anchors.extend(
+                anchors
+                    .into_iter()
+                    .filter(|rec| matches!(rec, Record::DS { .. } | Record::DNSKEY { .. })),
+            );
+        }
+
+        let mut authority = Authority::new();
+        for settings in &config.zones {
+            let mut zone = Zone::load(&settings.origin, &settings.file)?;
+            for path in &settings.ksk {
+                zone.add_key(SigningKey::load(&settings.origin, path, true)?);
+            }
+            for path in &settings.zsk {
+                zone.add_key(SigningKey::load(&settings.origin, path, false)?);
+            }
+            authority.add(zone);
+        }
+
+        Ok(ServerContext {
+            authority,
+            resolver,
+            allow_query: config.allow_query.clone(),
+            allow_recursion: config.allow_recursion.clone(),
+            log_queries: config.log_queries,
+        })
+    }
+}
+
+/// How long an idle TCP connection is kept open between queries.
+pub const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many TCP connections are served at once per listener. Clients
+/// connecting beyond that are disconnected straight away.
+pub const MAX_TCP_CONNECTIONS: usize = 128;
+
+/// Highest EDNS version we implement.
+pub const EDNS_VERSION: u8 = 0;
+
+/// Largest UDP response the client accepts, capped at what we advertise.
+pub fn udp_payload_size(request: &Packet) -> usize {
+    match request.edns() {
+        Some(Record::OPT { packet_len, .. }) => {
+            (*packet_len).clamp(UDP_MESSAGE_SIZE as u16, EDNS_PAYLOAD_SIZE) as usize
+        }
+        _ => UDP_MESSAGE_SIZE,
+    }
+}
+
+fn response_opt(request_opt: &Record) -> Record {
+    let dnssec_ok = matches!(request_opt, Record::OPT { dnssec_ok: true, .. });
+
+    Record::OPT {
+        packet_len: EDNS_PAYLOAD_SIZE,
+        // Filled in from the header RCODE when the packet is written.
+        ext_rcode: 0,
+        version: EDNS_VERSION,
+        dnssec_ok,
+        options: Vec::new(),
+    }
+}
+
+/// Answers `request` from `client`.
+pub fn build_response(mut request: Packet, context: &ServerContext, client: IpAddr) -> Packet {
+    let recursion = context.allow_recursion.allows(client);
+
+    let mut packet = Packet::new();
+    packet.header.id = request.header.id;
+    packet.header.rd = true;
+    packet.header.ra = recursion;
+    packet.header.qr = true;
+    packet.header.cd = request.header.cd;
+
+    if !context.allow_query.allows(client) {
+        packet.questions = request.questions;
+        packet.header.rcode = ResultCode::REFUSED;
+        return packet;
+    }
+
+    let request_opt = request.edns().cloned();
+    let dnssec_ok = matches!(request_opt, Some(Record::OPT { dnssec_ok: true, .. }));
+    if let Some(ref opt @ Record::OPT { version, .. }) = request_opt {
+        if version > EDNS_VERSION {
+            packet.questions = request.questions;
+            packet.header.rcode = ResultCode::BADVERS;
+            packet.resources.push(response_opt(opt));
+            return packet;
+        }
+    }
+
+    if let Some(question) = request.questions.pop() {
+        if context.log_queries {
+            println!("Received query: {:?} from {}", question, client);
+        }
+
+        match question.qclass {
+            QueryClass::IN => {
+                let result = match context.authority.query(&question.name, question.qtype, dnssec_ok) {
+                    Some(answer) => {
+                        packet.header.aa = answer.header.aa;
+                        Ok(answer)
+                    }
+                    // Only our own zones are served to everybody else.
+                    None if !recursion => {
+                        let mut refused = Packet::new();
+                        refused.header.rcode = ResultCode::REFUSED;
+                        Ok(refused)
+                    }
+                    // Checking disabled means the client validates for
+                    // itself and wants the data regardless (RFC 4035 3.2.2).
+                    None if request.header.cd => context.resolver.resolve(&question.name, question.qtype),
+                    None => context.resolver.resolve(&question.name, question.qtype).and_then(|answer| {
+                        match context.resolver.validate(&question.name, question.qtype, &answer) {
+                            // Only clients that understand DNSSEC are told.
+                            Security::Secure => packet.header.ad = dnssec_ok || request.header.ad,
+                            Security::Insecure => {}
+                            Security::Bogus(reason) => {
+                                eprintln!("Bogus answer for {:?}: {}", question, reason);
+                                return Err(Error::other(reason));
+                            }
+                        }
+                        Ok(answer)
+                    }),
+                };
+
+                match result {
+                    Ok(mut result) => {
+                        // DNSSEC records only go to clients that ask for
+                        // them, unless they are what was asked for.
+                        if !dnssec_ok {
+                            for section in [&mut result.answers, &mut result.authorities, &mut result.resources] {
+                                section.retain(|rec| rec.qtype() == question.qtype || !is_dnssec(rec));
+                            }
+                        }
+
+                        packet.questions.push(question);
+                        packet.header.rcode = result.header.rcode;
+
+                        for rec in result.answers {
+                            if context.log_queries {
+                                println!("Answer: {:?}", rec);
+                            }
+                            packet.answers.push(rec);
+                        }
+                        for rec in result.authorities {
+                            if context.log_queries {
+                                println!("Authority: {:?}", rec);
+                            }
+                            packet.authorities.push(rec);
+                        }
+                        // The upstream OPT record describes the upstream hop only.
+                        for rec in result.resources {
+                            if let Record::OPT { .. } = rec {
+                                continue;
+                            }
+                            if context.log_queries {
+                                println!("Resource: {:?}", rec);
+                            }
+                            packet.resources.push(rec);
+                        }
+                    }
+                    Err(_) => packet.header.rcode = ResultCode::SERVFAIL,
+                }
+            }
+            // Built-in identification answers (version.bind and friends).
+            QueryClass::CH => {
+                match chaos::lookup(&question) {
+                    Some(answers) => {
+                        packet.header.aa = true;
+                        packet.answers = answers;
+                    }
+                    None => packet.header.rcode = ResultCode::REFUSED,
+                }
+                packet.questions.push(question);
+            }
+            // Nothing is served in other classes.
+            _ => {
+                packet.header.rcode = ResultCode::NOTIMP;
+                packet.questions.push(question);
+            }
+        }
+    }
+    else {
+        packet.header.rcode = ResultCode::FORMERR;
+    }
+
+    if let Some(ref opt) = request_opt {
+        packet.resources.push(response_opt(opt));
+    }
+
+    packet
+}
+
+/// Signatures and proofs of nonexistence, which clients that did not set
+/// the DO bit are not sent (RFC 4035 3.2.1).
+fn is_dnssec(record: &Record) -> bool {
+    matches!(record, Record::RRSIG { .. } | Record::NSEC { .. } | Record::NSEC3 { .. })
+}
+
+/// Reads one datagram and hands it to the worker pool. The query is
+/// dropped if too many are already in flight.
+fn handle_query(
+    socket: &Arc<UdpSocket>,
+    req_buffer: &mut BytePacketBuffer,
+    context: &Arc<ServerContext>,
+    pool: &ThreadPool,
+) -> Result<()> {
+    let (len, src) = socket.recv_from(&mut req_buffer.buf)?;
+    let data = req_buffer.get_range(0, len)?.to_vec();
+
+    let socket = Arc::clone(socket);
+    let context = Arc::clone(context);
+    let accepted = pool.try_execute(move || {
+        if let Err(e) = answer_udp(&socket, &data, src, &context) {
+            eprintln!("An error occurred: {}", e);
+        }
+    });
+    if !accepted {
+        eprintln!("Dropping query from {}: too many queries in flight", src);
+    }
+
+    Ok(())
+}
+
+/// Reply to a request that failed to parse: FORMERR if its header is
+/// intact and it is a query, otherwise nothing, so that garbage and stray
+/// responses never cause replies of their own.
+pub fn malformed_response(data: &[u8], err: WireError) -> Result<Packet> {
+    let mut header = Header::new();
+    if header.read(&mut SlicePacketBuffer::new(data)).is_err() || header.qr {
+        return Err(err.into());
+    }
+    eprintln!("Malformed query {}: {}", header.id, err);
+
+    let mut packet = Packet::new();
+    packet.header.id = header.id;
+    packet.header.opcode = header.opcode;
+    packet.header.rd = header.rd;
+    packet.header.qr = true;
+    packet.header.rcode = ResultCode::FORMERR;
+
+    Ok(packet)
+}
+
+fn answer_udp(socket: &UdpSocket, data: &[u8], src: SocketAddr, context: &ServerContext) -> Result<()> {
+    let (mut packet, payload_size) = match Packet::from_buffer(&mut SlicePacketBuffer::new(data)) {
+        Ok(request) => {
+            let payload_size = udp_payload_size(&request);
+            (build_response(request, context, src.ip()), payload_size)
+        }
+        Err(e) => (malformed_response(data, e)?, UDP_MESSAGE_SIZE),
+    };
+
+    let mut res_buffer = BytePacketBuffer::with_capacity(payload_size);
+    packet.write_truncated(&mut res_buffer)?;
+
+    let len = res_buffer.pos();
+    let data = res_buffer.get_range(0, len)?;
+
+    socket.send_to(data, src)?;
+
+    Ok(())
+}
+
+/// Reads queries from one TCP connection until the client closes it or
+/// stays idle for `TCP_IDLE_TIMEOUT`. Pipelined queries are resolved
+/// concurrently and answered as they complete, which RFC 7766 allows.
+fn handle_tcp_connection(mut stream: TcpStream, context: &Arc<ServerContext>, pool: &ThreadPool) -> Result<()> {
+    stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT))?;
+    let client = stream.peer_addr()?.ip();
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+
+    loop {
+        let mut req_buffer = VectorPacketBuffer::new();
+        match tcp::read_message(&mut stream, &mut req_buffer) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        let writer = Arc::clone(&writer);
+        let context = Arc::clone(context);
+        let accepted = pool.try_execute(move || {
+            if let Err(e) = answer_tcp(&writer, req_buffer, &context, client) {
+                eprintln!("An error occurred: {}", e);
+            }
+        });
+        if !accepted {
+            return Err(Error::new(ErrorKind::WouldBlock, "Too many queries in flight"));
+        }
+    }
+}
+
+fn answer_tcp(
+    writer: &Mutex<TcpStream>,
+    mut req_buffer: VectorPacketBuffer,
+    context: &ServerContext,
+    client: IpAddr,
+) -> Result<()> {
+    let mut packet = match Packet::from_buffer(&mut req_buffer) {
+        Ok(request) => build_response(request, context, client),
+        Err(e) => malformed_response(&req_buffer.buf, e)?,
+    };
+
+    let mut res_buffer = VectorPacketBuffer::new();
+    packet.write(&mut res_buffer)?;
+
+    let len = res_buffer.pos();
+    let mut stream = writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    tcp::write_message(&mut stream, res_buffer.get_range(0, len)?)
+}
+
+/// Takes one of the `max` connection slots counted by `open`, if any is
+/// free, and gives it back when dropped.
+struct ConnectionSlot {
+    open: Arc<AtomicUsize>,
+}
+
+impl ConnectionSlot {
+    fn take(open: &Arc<AtomicUsize>, max: usize) -> Option<ConnectionSlot> {
+        open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| (count < max).then_some(count + 1))
+            .ok()?;
+        Some(ConnectionSlot { open: Arc::clone(open) })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Accepts connections on `listener` until the process exits, each served
+/// by a thread of its own, with at most `max_connections` open at once.
+pub fn serve_tcp(listener: TcpListener, context: Arc<ServerContext>, pool: Arc<ThreadPool>, max_connections: usize) {
+    let open = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("An error occurred: {}", e);
+                continue;
+            }
+        };
+
+        // Dropping the stream closes the connection.
+        let Some(slot) = ConnectionSlot::take(&open, max_connections) else {
+            continue;
+        };
+
+        let context = Arc::clone(&context);
+        let pool = Arc::clone(&pool);
+        thread::spawn(move || {
+            let _slot = slot;
+            if let Err(e) = handle_tcp_connection(stream, &context, &pool) {
+                eprintln!("An error occurred: {}", e);
+            }
+        });
+    }
+}
+
+/// Answers queries arriving on `socket` until the process exits.
+pub fn serve_udp(socket: Arc<UdpSocket>, context: Arc<ServerContext>, pool: Arc<ThreadPool>) {
+    let mut req_buffer = BytePacketBuffer::with_capacity(MAX_MESSAGE_SIZE);
+    loop {
+        match handle_query(&socket, &mut req_buffer, &context, &pool) {
+            Ok(_) => {},
+            Err(e) => eprintln!("An error occurred: {}", e),
+        }
+    }
+}
+
+/// Binds every listener and answers queries on them until the process
+/// exits. TLS and HTTPS listeners can be configured but not served yet, so
+/// any of those fails the whole call before a socket is bound.
+pub fn serve(listeners: &[Listener], context: Arc<ServerContext>, pool: Arc<ThreadPool>) -> Result<()> {
+    if let Some(listener) = listeners.iter().find(|listener| listener.protocol.is_encrypted()) {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{} {:?}: encrypted listeners are not implemented yet", listener.address, listener.protocol),
+        ));
+    }
+
+    let mut threads = Vec::new();
+    for listener in listeners {
+        let context = Arc::clone(&context);
+        let pool = Arc::clone(&pool);
+        let bind_error = |e: Error| Error::new(e.kind(), format!("{} {:?}: {}", listener.address, listener.protocol, e));
+
+        let thread = match listener.protocol {
+            Protocol::UDP => {
+                let socket = Arc::new(UdpSocket::bind(listener.address).map_err(bind_error)?);
+                thread::spawn(move || serve_udp(socket, context, pool))
+            }
+            Protocol::TCP => {
+                let tcp_listener = TcpListener::bind(listener.address).map_err(bind_error)?;
+                thread::spawn(move || serve_tcp(tcp_listener, context, pool, MAX_TCP_CONNECTIONS))
+            }
+            Protocol::TLS | Protocol::HTTPS => unreachable!("refused above"),
+        };
+        threads.push(thread);
+    }
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::TlsFiles;
+    use crate::query::QueryType;
+    use crate::question::Question;
+
+    fn ask_version(stream: &mut TcpStream) -> Result<bool> {
+        let mut query = Packet::new();
+        query.header.id = 7;
+        query.questions.push(Question {
+            qclass: QueryClass::CH,
+            ..Question::new("version.bind".to_string(), QueryType::TXT)
+        });
+        let mut out = BytePacketBuffer::new();
+        query.write(&mut out)?;
+        tcp::write_message(stream, &out.buf[..out.pos])?;
+
+        let mut buffer = VectorPacketBuffer::new();
+        if !tcp::read_message(stream, &mut buffer)? {
+            return Ok(false);
+        }
+        let response = Packet::from_buffer(&mut buffer)?;
+        assert_eq!(response.header.id, 7);
+        assert_eq!(response.answers.len(), 1);
+        Ok(true)
+    }
+
+    #[test]
+    fn connections_over_the_cap_are_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let context = Arc::new(ServerContext::new(Authority::new(), Resolver::new().unwrap()));
+        let pool = Arc::new(ThreadPool::new(2, 8));
+        thread::spawn(move || serve_tcp(listener, context, pool, 1));
+
+        let mut first = TcpStream::connect(addr).unwrap();
+        assert!(ask_version(&mut first).unwrap());
+
+        let mut second = TcpStream::connect(addr).unwrap();
+        second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert!(!matches!(ask_version(&mut second), Ok(true)));
+
+        // The slot frees up once the first client goes away.
+        drop(first);
+        let served = (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(20));
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            matches!(ask_version(&mut stream), Ok(true))
+        });
+        assert!(served);
+    }
+
+    fn opt(packet_len: u16, version: u8) -> Record {
+        Record::OPT {
+            packet_len,
+            ext_rcode: 0,
+            version,
+            dnssec_ok: true,
+            options: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn unknown_edns_versions_get_badvers() {
+        let context = ServerContext::new(Authority::new(), Resolver::new().unwrap());
+        let mut request = Packet::new();
+        request.header.id = 9;
+        request.questions.push(Question::new("example.com".to_string(), QueryType::A));
+        request.resources.push(opt(4096, 1));
+
+        let response = build_response(request, &context, IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(response.header.id, 9);
+        assert_eq!(response.header.rcode, ResultCode::BADVERS);
+        assert!(response.answers.is_empty());
+        match response.edns() {
+            Some(Record::OPT {
+                packet_len,
+                version,
+                dnssec_ok,
+                ..
+            }) => {
+                assert_eq!(*packet_len, EDNS_PAYLOAD_SIZE);
+                assert_eq!(*version, 0);
+                assert!(*dnssec_ok);
+            }
+            other => panic!("expected an OPT record, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn udp_payload_size_follows_the_request() {
+        let mut request = Packet::new();
+        assert_eq!(udp_payload_size(&request), UDP_MESSAGE_SIZE);
+
+        for (advertised, used) in [(100, UDP_MESSAGE_SIZE), (1000, 1000), (65000, EDNS_PAYLOAD_SIZE as usize)] {
+            request.resources = vec![opt(advertised, 0)];
+            assert_eq!(udp_payload_size(&request), used);
+        }
+    }
+
+    #[test]
+    fn classes_other_than_in_are_answered_apart() {
+        let context = ServerContext::new(Authority::new(), Resolver::new().unwrap());
+        let ask = |name: &str, qclass: QueryClass| {
+            let mut request = Packet::new();
+            request.questions.push(Question {
+                qclass,
+                ..Question::new(name.to_string(), QueryType::TXT)
+            });
+            build_response(request, &context, IpAddr::from([127, 0, 0, 1]))
+        };
+
+        let response = ask("version.bind", QueryClass::CH);
+        assert_eq!(response.header.rcode, ResultCode::NOERROR);
+        assert!(response.header.aa);
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.questions[0].qclass, QueryClass::CH);
+
+        assert_eq!(ask("example.com", QueryClass::CH).header.rcode, ResultCode::REFUSED);
+        assert_eq!(ask("example.com", QueryClass::HS).header.rcode, ResultCode::NOTIMP);
+        assert_eq!(ask("example.com", QueryClass::UNKNOWN(42)).header.rcode, ResultCode::NOTIMP);
+    }
+
+    #[test]
+    fn malformed_queries_get_formerr() {
+        // A query for one question whose name has a reserved label type.
+        let mut data = vec![0, 7, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0x41, 0, 1, 0, 1];
+        let e = Packet::from_buffer(&mut SlicePacketBuffer::new(&data)).unwrap_err();
+        assert_eq!(e, WireError::BadLabelType { pos: 12, byte: 0x41 });
+
+        let response = malformed_response(&data, e.clone()).unwrap();
+        assert_eq!(response.header.id, 7);
+        assert!(response.header.qr);
+        assert!(response.header.rd);
+        assert_eq!(response.header.rcode, ResultCode::FORMERR);
+
+        // Responses and fragments of a header are never answered.
+        assert!(malformed_response(&data[..5], e.clone()).is_err());
+        data[2] |= 0x80;
+        assert!(malformed_response(&data, e).is_err());
+    }
+
+    #[test]
+    fn encrypted_listeners_are_refused_before_binding() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = udp.local_addr().unwrap();
+        drop(udp);
+        let listeners = [
+            Listener {
+                address,
+                protocol: Protocol::UDP,
+                tls: None,
+                path: None,
+            },
+            Listener {
+                address,
+                protocol: Protocol::TLS,
+                tls: Some(TlsFiles {
+                    certificate: "cert.pem".into(),
+                    key: "key.pem".into(),
+                }),
+                path: None,
+            },
+        ];
+        let context = Arc::new(ServerContext::new(Authority::new(), Resolver::new().unwrap()));
+        let pool = Arc::new(ThreadPool::new(1, 1));
+
+        let e = serve(&listeners, context, pool).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+        assert!(e.to_string().contains("not implemented"), "{}", e);
+        // The UDP listener was never bound.
+        UdpSocket::bind(address).unwrap();
+    }
+}
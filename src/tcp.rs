@@ -0,0 +1,91 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+
+use crate::buffer::VectorPacketBuffer;
+
+/// Reads one length-prefixed DNS message (RFC 1035 4.2.2) into `buffer`,
+/// replacing its contents. Returns `Ok(false)` if the peer closed the
+/// connection between messages.
+pub fn read_message(stream: &mut TcpStream, buffer: &mut VectorPacketBuffer) -> Result<bool> {
+    let mut len_bytes = [0; 2];
+    match stream.read_exact(&mut len_bytes) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+        Err(e) => return Err(e),
+    }
+
+    let len = u16::from_be_bytes(len_bytes) as usize;
+    buffer.buf.clear();
+    buffer.buf.resize(len, 0);
+    buffer.pos = 0;
+
+    stream.read_exact(&mut buffer.buf)?;
+
+    Ok(true)
+}
+
+/// Writes `data` prefixed with its length in a single write, so pipelined
+/// responses are never interleaved.
+pub fn write_message(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    let len = u16::try_from(data.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Message too large"))?;
+
+    let mut message = Vec::with_capacity(data.len() + 2);
+    message.extend_from_slice(&len.to_be_bytes());
+    message.extend_from_slice(data);
+
+    stream.write_all(&message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn messages_are_framed_by_their_length() {
+        let (mut client, mut server) = connected_pair();
+        write_message(&mut client, b"first").unwrap();
+        write_message(&mut client, &[7; 600]).unwrap();
+        write_message(&mut client, b"").unwrap();
+        drop(client);
+
+        let mut buffer = VectorPacketBuffer::new();
+        assert!(read_message(&mut server, &mut buffer).unwrap());
+        assert_eq!(buffer.buf, b"first");
+        assert!(read_message(&mut server, &mut buffer).unwrap());
+        assert_eq!(buffer.buf, vec![7; 600]);
+        assert_eq!(buffer.pos, 0);
+        assert!(read_message(&mut server, &mut buffer).unwrap());
+        assert!(buffer.buf.is_empty());
+
+        // Closing between messages is a clean end.
+        assert!(!read_message(&mut server, &mut buffer).unwrap());
+    }
+
+    #[test]
+    fn closing_mid_message_is_an_error() {
+        let (mut client, mut server) = connected_pair();
+        client.write_all(&[0, 10, 1, 2, 3]).unwrap();
+        drop(client);
+
+        let mut buffer = VectorPacketBuffer::new();
+        let e = read_message(&mut server, &mut buffer).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn messages_over_64k_are_refused() {
+        let (mut client, _server) = connected_pair();
+        let e = write_message(&mut client, &vec![0; 65536]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        write_message(&mut client, &vec![0; 65535]).unwrap();
+    }
+}
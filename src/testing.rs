@@ -0,0 +1,71 @@
+//! Fixtures shared by the unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+
+use crate::query::QueryClass;
+use crate::record::Record;
+use crate::signer::SigningKey;
+
+/// TTL of the records made here.
+pub const TTL: u32 = 3600;
+
+/// An empty directory of the test's own, cleared of any earlier run.
+pub fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_dns-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The SOA record of `zone`, with a negative caching TTL of 300.
+pub fn soa(zone: &str) -> Record {
+    Record::SOA {
+        domain: zone.to_string(),
+        class: QueryClass::IN,
+        mname: format!("ns.{}", zone),
+        rname: format!("hostmaster.{}", zone),
+        serial: 1,
+        refresh: 3600,
+        retry: 600,
+        expire: 86400,
+        minimum: 300,
+        ttl: TTL,
+    }
+}
+
+pub fn ns(domain: &str, host: &str) -> Record {
+    Record::NS {
+        domain: domain.to_string(),
+        class: QueryClass::IN,
+        host: host.to_string(),
+        ttl: TTL,
+    }
+}
+
+pub fn a(domain: &str, addr: &str) -> Record {
+    Record::A {
+        domain: domain.to_string(),
+        class: QueryClass::IN,
+        addr: addr.parse().unwrap(),
+        ttl: TTL,
+    }
+}
+
+pub fn cname(domain: &str, host: &str) -> Record {
+    Record::CNAME {
+        domain: domain.to_string(),
+        class: QueryClass::IN,
+        host: host.to_string(),
+        ttl: TTL,
+    }
+}
+
+/// A freshly generated Ed25519 key for `zone`.
+pub fn signing_key(zone: &str, ksk: bool) -> SigningKey {
+    let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    SigningKey::from_pkcs8(zone, document.as_ref(), ksk).unwrap()
+}
@@ -0,0 +1,486 @@
+//! Just enough of TOML (https://toml.io) for the configuration file:
+//! tables, arrays of tables, and keys holding strings, integers, booleans
+//! or arrays of those. Floats, dates, multi-line strings and inline tables
+//! are rejected.
+
+use std::collections::{BTreeMap, HashSet};
+
+pub type Table = BTreeMap<String, Item>;
+
+/// A value along with the line it was defined on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub line: usize,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    /// What kind of value this is, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Parses a document into its root table.
+pub fn parse(text: &str) -> Result<Table, Error> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut defined = HashSet::new();
+
+    loop {
+        parser.skip_blank_lines();
+        let Some(c) = parser.peek() else {
+            return Ok(root);
+        };
+        let line = parser.line;
+
+        if c == '[' {
+            parser.pos += 1;
+            let array = parser.eat('[');
+            let path = parser.key_path()?;
+            parser.expect(']')?;
+            if array {
+                parser.expect(']')?;
+            }
+            parser.end_of_line()?;
+
+            let name = path.join(".");
+            if array {
+                let (parent, last) = path.split_at(path.len() - 1);
+                let table = descend(&mut root, parent, line)?;
+                let item = table.entry(last[0].clone()).or_insert(Item {
+                    line,
+                    value: Value::Array(Vec::new()),
+                });
+                match item.value {
+                    Value::Array(ref mut elements) if elements.iter().all(|e| matches!(e, Value::Table(_))) => {
+                        elements.push(Value::Table(Table::new()))
+                    }
+                    _ => return Err(error(line, format!("`{}` is not an array of tables", name))),
+                }
+                // Each element of the array starts afresh.
+                let prefix = format!("{}.", name);
+                defined.retain(|other: &String| !other.starts_with(&prefix));
+            } else {
+                if !defined.insert(name.clone()) {
+                    return Err(error(line, format!("table [{}] is defined twice", name)));
+                }
+                descend(&mut root, &path, line)?;
+            }
+            current = path;
+            continue;
+        }
+
+        let path = parser.key_path()?;
+        parser.skip_spaces();
+        parser.expect('=')?;
+        parser.skip_spaces();
+        let value = parser.value()?;
+        parser.end_of_line()?;
+
+        let (parent, last) = path.split_at(path.len() - 1);
+        let table = descend(&mut root, &current, line)?;
+        let table = descend(table, parent, line)?;
+        if table.contains_key(&last[0]) {
+            return Err(error(line, format!("`{}` is defined twice", path.join("."))));
+        }
+        table.insert(last[0].clone(), Item { line, value });
+    }
+}
+
+fn error(line: usize, message: impl Into<String>) -> Error {
+    Error {
+        line,
+        message: message.into(),
+    }
+}
+
+/// The table at `path` below `table`, created as needed. Arrays of tables
+/// lead into their last element.
+fn descend<'a>(mut table: &'a mut Table, path: &[String], line: usize) -> Result<&'a mut Table, Error> {
+    for key in path {
+        let item = table.entry(key.clone()).or_insert(Item {
+            line,
+            value: Value::Table(Table::new()),
+        });
+        table = match item.value {
+            Value::Table(ref mut table) => table,
+            Value::Array(ref mut elements) => match elements.last_mut() {
+                Some(Value::Table(table)) => table,
+                _ => return Err(error(line, format!("`{}` is not a table", key))),
+            },
+            _ => return Err(error(line, format!("`{}` is not a table", key))),
+        };
+    }
+
+    Ok(table)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            return Ok(());
+        }
+
+        let found = match self.peek() {
+            Some('\n') | None => "end of line".to_string(),
+            Some(other) => format!("`{}`", other),
+        };
+        Err(error(self.line, format!("expected `{}`, found {}", c, found)))
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), Some('\n') | None) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skips spaces, comments and line breaks.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => {
+                    self.pos += 1;
+                    self.line += 1;
+                }
+                Some('\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        self.skip_comment();
+        self.eat('\r');
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.pos += 1;
+                self.line += 1;
+                Ok(())
+            }
+            Some(c) => Err(error(self.line, format!("unexpected `{}` after value", c))),
+        }
+    }
+
+    /// A key of one or more dotted parts, bare or quoted.
+    fn key_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.pos += 1;
+                    }
+                    if self.pos == start {
+                        return Err(error(self.line, "expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(key);
+
+            self.skip_spaces();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => Err(error(self.line, "inline tables are not supported")),
+            Some(c) if c.is_ascii_alphanumeric() || c == '+' || c == '-' => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-_.:".contains(c)) {
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| error(self.line, format!("`{}` is not a string, integer or boolean", word))),
+                }
+            }
+            _ => Err(error(self.line, "expected a value")),
+        }
+    }
+
+    /// An array, which may span lines and hold comments.
+    fn array(&mut self) -> Result<Value, Error> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.eat(']') {
+                return Ok(Value::Array(elements));
+            }
+
+            elements.push(self.value()?);
+            self.skip_blank_lines();
+            if !self.eat(',') {
+                self.skip_blank_lines();
+                self.expect(']')?;
+                return Ok(Value::Array(elements));
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        self.expect('"')?;
+        if self.peek() == Some('"') && self.chars.get(self.pos + 1) == Some(&'"') {
+            return Err(error(self.line, "multi-line strings are not supported"));
+        }
+
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(error(self.line, "unterminated string")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('b') => '\u{8}',
+                        Some('t') => '\t',
+                        Some('n') => '\n',
+                        Some('f') => '\u{c}',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c @ ('u' | 'U')) => {
+                            let len = if c == 'u' { 4 } else { 8 };
+                            let digits: String = self.chars.iter().skip(self.pos + 1).take(len).collect();
+                            let c = u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .filter(|_| digits.len() == len)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| error(self.line, format!("bad unicode escape `\\{}{}`", c, digits)))?;
+                            self.pos += len;
+                            c
+                        }
+                        _ => return Err(error(self.line, "bad escape in string")),
+                    };
+                    self.pos += 1;
+                    text.push(escaped);
+                }
+                Some(c) => {
+                    self.pos += 1;
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(error(self.line, "unterminated string")),
+                Some('\'') => {
+                    let text = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(table: &Table, key: &str) -> Value {
+        table[key].value.clone()
+    }
+
+    fn table(table: &Table, key: &str) -> Table {
+        match value(table, key) {
+            Value::Table(table) => table,
+            other => panic!("{} is {}", key, other.kind()),
+        }
+    }
+
+    fn error_line(text: &str) -> (usize, String) {
+        let e = parse(text).unwrap_err();
+        (e.line, e.message)
+    }
+
+    #[test]
+    fn strings_integers_and_booleans() {
+        let root = parse(concat!(
+            "basic = \"tab\\there \\\"quoted\\\" \\\\ \\u00e9\\U0001F600\"\n",
+            "literal = 'C:\\no\\escapes'   # comment\n",
+            "\"quoted key\" = 1_000\n",
+            "negative = -53\n",
+            "yes = true\n",
+            "no = false\n",
+        ))
+        .unwrap();
+
+        assert_eq!(value(&root, "basic"), Value::String("tab\there \"quoted\" \\ \u{e9}\u{1F600}".to_string()));
+        assert_eq!(value(&root, "literal"), Value::String("C:\\no\\escapes".to_string()));
+        assert_eq!(value(&root, "quoted key"), Value::Integer(1000));
+        assert_eq!(value(&root, "negative"), Value::Integer(-53));
+        assert_eq!(value(&root, "yes"), Value::Boolean(true));
+        assert_eq!(value(&root, "no"), Value::Boolean(false));
+        assert_eq!(root["no"].line, 6);
+    }
+
+    #[test]
+    fn arrays_span_lines_and_hold_comments() {
+        let root = parse(concat!(
+            "empty = []\n",
+            "servers = [\n",
+            "    \"8.8.8.8\",  # first\n",
+            "    '1.1.1.1',\n",
+            "\n",
+            "]\n",
+            "nested = [[1, 2], [true]]\n",
+            "after = 1\n",
+        ))
+        .unwrap();
+
+        assert_eq!(value(&root, "empty"), Value::Array(Vec::new()));
+        assert_eq!(
+            value(&root, "servers"),
+            Value::Array(vec![Value::String("8.8.8.8".to_string()), Value::String("1.1.1.1".to_string())])
+        );
+        assert_eq!(
+            value(&root, "nested"),
+            Value::Array(vec![
+                Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                Value::Array(vec![Value::Boolean(true)]),
+            ])
+        );
+        assert_eq!(root["after"].line, 8);
+    }
+
+    #[test]
+    fn tables_and_arrays_of_tables() {
+        let root = parse(concat!(
+            "top = 1\n",
+            "[server]\n",
+            "workers = 4\n",
+            "[upstreams.public]\n",
+            "servers = [\"::1\"]\n",
+            "[[zone]]\n",
+            "origin = \"a.example\"\n",
+            "keys.ksk = \"ksk.pem\"\n",
+            "[[zone]]\n",
+            "origin = \"b.example\"\n",
+            "[zone.extra]\n",
+            "note = 'b'\n",
+        ))
+        .unwrap();
+
+        assert_eq!(value(&root, "top"), Value::Integer(1));
+        assert_eq!(value(&table(&root, "server"), "workers"), Value::Integer(4));
+        let public = table(&table(&root, "upstreams"), "public");
+        assert_eq!(value(&public, "servers"), Value::Array(vec![Value::String("::1".to_string())]));
+
+        let Value::Array(zones) = value(&root, "zone") else {
+            panic!("zone is not an array");
+        };
+        let zones: Vec<Table> = zones
+            .into_iter()
+            .map(|zone| match zone {
+                Value::Table(table) => table,
+                other => panic!("zone element is {}", other.kind()),
+            })
+            .collect();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(value(&zones[0], "origin"), Value::String("a.example".to_string()));
+        assert_eq!(value(&table(&zones[0], "keys"), "ksk"), Value::String("ksk.pem".to_string()));
+        assert_eq!(value(&zones[1], "origin"), Value::String("b.example".to_string()));
+        assert_eq!(value(&table(&zones[1], "extra"), "note"), Value::String("b".to_string()));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(error_line("a = 1\na = 2\n"), (2, "`a` is defined twice".to_string()));
+        assert_eq!(error_line("[t]\n[t]\n"), (2, "table [t] is defined twice".to_string()));
+        assert_eq!(error_line("t = 1\n[[t]]\n"), (2, "`t` is not an array of tables".to_string()));
+        assert_eq!(error_line("\n\nt = {}\n"), (3, "inline tables are not supported".to_string()));
+        assert_eq!(error_line("s = \"\"\"\nx\"\"\"\n"), (1, "multi-line strings are not supported".to_string()));
+        assert_eq!(error_line("ok = 1\ns = \"open\n"), (2, "unterminated string".to_string()));
+        assert_eq!(error_line("s = 'open"), (1, "unterminated string".to_string()));
+        assert_eq!(error_line("s = \"\\q\""), (1, "bad escape in string".to_string()));
+        assert_eq!(error_line("f = 1.5\n"), (1, "`1.5` is not a string, integer or boolean".to_string()));
+        assert_eq!(error_line("a = [1,\n2\n"), (3, "expected `]`, found end of line".to_string()));
+        assert_eq!(error_line("a = 1 2\n"), (1, "unexpected `2` after value".to_string()));
+        assert_eq!(error_line("= 1\n"), (1, "expected a key".to_string()));
+    }
+}
@@ -0,0 +1,401 @@
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::buffer::{BytePacketBuffer, PacketBuffer, SlicePacketBuffer, MAX_MESSAGE_SIZE};
+use crate::packet::Packet;
+use crate::question::Question;
+use crate::query::QueryType;
+use crate::random;
+
+/// How many times binding a random port is attempted before giving up.
+const BIND_ATTEMPTS: usize = 64;
+
+/// Lowest port picked for outgoing queries, to stay clear of well-known
+/// services.
+const MIN_PORT: u16 = 1024;
+
+/// However often a server fails, its penalty stays below this many
+/// timeouts, so it gets retried within a bounded number of queries.
+const MAX_PENALTY: u32 = 10;
+
+/// Sends queries over UDP. Each query goes out from a socket of its own,
+/// bound to a port picked at random from `MIN_PORT` up, under a random
+/// transaction ID, so a blind spoofer has to guess close to 32 bits: 16
+/// of ID and just under 16 of port (RFC 5452 section 9.2). A response is
+/// only taken if the ID, the sender address and the question section all
+/// match.
+pub struct UdpClient {
+    mismatched: AtomicU64,
+}
+
+impl Default for UdpClient {
+    fn default() -> UdpClient {
+        UdpClient::new()
+    }
+}
+
+impl UdpClient {
+    pub fn new() -> UdpClient {
+        UdpClient {
+            mismatched: AtomicU64::new(0),
+        }
+    }
+
+    /// Sends `packet` to `server` under a random transaction ID and waits up
+    /// to `timeout` for a matching response.
+    pub fn query(&self, packet: &mut Packet, server: SocketAddr, timeout: Duration) -> Result<Packet> {
+        let question = packet
+            .questions
+            .first()
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Query without a question"))?;
+        let local = match server {
+            SocketAddr::V4(_) => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::from(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = bind_random_port(local)?;
+        packet.header.id = random::random_u16();
+
+        let mut req_buffer = BytePacketBuffer::new();
+        packet.write(&mut req_buffer)?;
+        socket.send_to(req_buffer.get_range(0, req_buffer.pos())?, server)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut buffer = BytePacketBuffer::with_capacity(MAX_MESSAGE_SIZE);
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(Error::new(ErrorKind::TimedOut, "Upstream query timed out"));
+            }
+            socket.set_read_timeout(Some(left))?;
+
+            let (len, src) = match socket.recv_from(&mut buffer.buf) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Err(Error::new(ErrorKind::TimedOut, "Upstream query timed out"))
+                }
+                Err(e) => return Err(e),
+            };
+
+            let data = &buffer.buf[0..len];
+            if src == server && response_id(data) == Some(packet.header.id) && matches_question(data, &question) {
+                return Ok(Packet::from_buffer(&mut SlicePacketBuffer::new(data))?);
+            }
+
+            let count = self.mismatched.fetch_add(1, Ordering::Relaxed) + 1;
+            eprintln!("Discarded unexpected response from {} ({} so far)", src, count);
+        }
+    }
+
+    /// How many responses were discarded for not matching the query they
+    /// arrived for, a sign of spoofing attempts.
+    pub fn mismatched(&self) -> u64 {
+        self.mismatched.load(Ordering::Relaxed)
+    }
+}
+
+fn bind_random_port(ip: IpAddr) -> Result<UdpSocket> {
+    let mut last_err = Error::new(ErrorKind::AddrInUse, "No free port");
+
+    for _ in 0..BIND_ATTEMPTS {
+        let port = MIN_PORT + random::random_below((u16::MAX - MIN_PORT) as u64 + 1) as u16;
+        match UdpSocket::bind((ip, port)) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => last_err = e,
+        }
+    }
+
+    Err(last_err)
+}
+
+fn response_id(data: &[u8]) -> Option<u16> {
+    match data {
+        [b1, b2, ..] => Some(u16::from_be_bytes([*b1, *b2])),
+        _ => None,
+    }
+}
+
+/// Whether `data` is a response carrying exactly `question`. A FORMERR
+/// without a question section is let through, servers that do not
+/// understand EDNS answer like that.
+fn matches_question(data: &[u8], question: &Question) -> bool {
+    // The header is checked by hand, the flags are all we need from it.
+    let (flags, qdcount) = match data {
+        [_, _, f1, f2, q1, q2, ..] if data.len() >= 12 => {
+            (u16::from_be_bytes([*f1, *f2]), u16::from_be_bytes([*q1, *q2]))
+        }
+        _ => return false,
+    };
+    if flags & 0x8000 == 0 {
+        return false;
+    }
+    if qdcount == 0 {
+        return flags & 0xF == 1;
+    }
+    if qdcount != 1 {
+        return false;
+    }
+
+    let mut buffer = SlicePacketBuffer::new(data);
+    let mut received = Question::new(String::new(), QueryType::UNKNOWN(0));
+    if buffer.seek(12).is_err() || received.read(&mut buffer).is_err() {
+        return false;
+    }
+
+    received.qtype == question.qtype
+        && received.qclass == question.qclass
+        && received.name.eq_ignore_ascii_case(&question.name)
+}
+
+/// How the forwarder orders its upstream servers for each query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Rotate the starting server on every query.
+    RoundRobin,
+    /// Try the server with the lowest smoothed round trip time first.
+    FastestFirst,
+}
+
+struct Upstream {
+    addr: SocketAddr,
+    /// Smoothed round trip time in microseconds, 0 until measured.
+    srtt: AtomicU64,
+}
+
+/// The servers a forwarder sends its queries to, with the RTT bookkeeping
+/// used to pick between them.
+pub struct Upstreams {
+    servers: Vec<Upstream>,
+    selection: Selection,
+    next: AtomicUsize,
+}
+
+impl Upstreams {
+    pub fn new(addrs: Vec<SocketAddr>, selection: Selection) -> Upstreams {
+        Upstreams {
+            servers: addrs
+                .into_iter()
+                .map(|addr| Upstream {
+                    addr,
+                    srtt: AtomicU64::new(0),
+                })
+                .collect(),
+            selection,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Every server, in the order they should be tried for one query.
+    pub fn order(&self) -> Vec<SocketAddr> {
+        let n = self.servers.len();
+        if n == 0 {
+            return Vec::new();
+        }
+
+        match self.selection {
+            Selection::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % n;
+                (0..n).map(|i| self.servers[(start + i) % n].addr).collect()
+            }
+            Selection::FastestFirst => {
+                let mut servers: Vec<&Upstream> = self.servers.iter().collect();
+                servers.sort_by_key(|server| server.srtt.load(Ordering::Relaxed));
+
+                // Servers passed over slowly look faster again, so one that
+                // was penalised once gets retried eventually.
+                for server in &servers[1..] {
+                    let srtt = server.srtt.load(Ordering::Relaxed);
+                    server.srtt.store(srtt - srtt / 50, Ordering::Relaxed);
+                }
+
+                servers.into_iter().map(|server| server.addr).collect()
+            }
+        }
+    }
+
+    pub fn record_rtt(&self, addr: SocketAddr, rtt: Duration) {
+        if let Some(server) = self.find(addr) {
+            let sample = rtt.as_micros() as u64;
+            let srtt = server.srtt.load(Ordering::Relaxed);
+            let srtt = if srtt == 0 { sample } else { (srtt * 7 + sample) / 8 };
+            server.srtt.store(srtt.max(1), Ordering::Relaxed);
+        }
+    }
+
+    /// Penalises a server that timed out or failed, as if it had answered
+    /// after at least `timeout` and at most `MAX_PENALTY` timeouts.
+    pub fn record_failure(&self, addr: SocketAddr, timeout: Duration) {
+        if let Some(server) = self.find(addr) {
+            let timeout = timeout.as_micros() as u64;
+            let srtt = server.srtt.load(Ordering::Relaxed);
+            let penalty = srtt
+                .saturating_mul(2)
+                .max(timeout)
+                .min(timeout.saturating_mul(MAX_PENALTY as u64));
+            server.srtt.store(penalty, Ordering::Relaxed);
+        }
+    }
+
+    fn find(&self, addr: SocketAddr) -> Option<&Upstream> {
+        self.servers.iter().find(|server| server.addr == addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Record;
+    use std::thread;
+
+    /// Answers each query on `socket` after sending `decoys` responses for
+    /// a different question.
+    fn answer_queries(socket: UdpSocket, decoys: usize) {
+        thread::spawn(move || loop {
+            let mut buffer = BytePacketBuffer::new();
+            let (len, src) = socket.recv_from(&mut buffer.buf).unwrap();
+            let query = Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf[..len])).unwrap();
+            let question = query.questions[0].clone();
+
+            let decoy = Question::new("decoy.example".to_string(), question.qtype);
+            for _ in 0..decoys {
+                send_response(&socket, src, query.header.id, &decoy, [198, 51, 100, 1]);
+            }
+            send_response(&socket, src, query.header.id, &question, [192, 0, 2, 1]);
+        });
+    }
+
+    fn send_response(socket: &UdpSocket, to: SocketAddr, id: u16, question: &Question, addr: [u8; 4]) {
+        let mut response = Packet::new();
+        response.header.id = id;
+        response.header.qr = true;
+        response.answers.push(Record::A {
+            domain: question.name.clone(),
+            class: question.qclass,
+            addr: addr.into(),
+            ttl: 60,
+        });
+        response.questions.push(question.clone());
+
+        let mut out = BytePacketBuffer::new();
+        response.write(&mut out).unwrap();
+        socket.send_to(&out.buf[..out.pos], to).unwrap();
+    }
+
+    fn query(name: &str) -> Packet {
+        let mut packet = Packet::new();
+        packet.questions.push(Question::new(name.to_string(), QueryType::A));
+        packet
+    }
+
+    fn srtt(upstreams: &Upstreams, addr: SocketAddr) -> u64 {
+        upstreams.find(addr).unwrap().srtt.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn repeated_failures_are_capped() {
+        let good: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let bad: SocketAddr = "192.0.2.2:53".parse().unwrap();
+        let timeout = Duration::from_secs(2);
+        let upstreams = Upstreams::new(vec![bad, good], Selection::FastestFirst);
+
+        upstreams.record_rtt(good, Duration::from_millis(20));
+        for _ in 0..1000 {
+            upstreams.record_failure(bad, timeout);
+        }
+
+        let ceiling = timeout.as_micros() as u64 * MAX_PENALTY as u64;
+        assert_eq!(srtt(&upstreams, bad), ceiling);
+        assert_eq!(upstreams.order(), vec![good, bad]);
+
+        // The penalty decays while the server is passed over, so it gets
+        // another chance after a bounded number of queries.
+        let retried = (0..1000).position(|_| upstreams.order()[0] == bad);
+        assert!(retried.is_some_and(|queries| queries > 100));
+    }
+
+    #[test]
+    fn failure_penalty_is_at_least_the_timeout() {
+        let addr: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let upstreams = Upstreams::new(vec![addr], Selection::FastestFirst);
+
+        upstreams.record_rtt(addr, Duration::from_millis(5));
+        upstreams.record_failure(addr, Duration::from_secs(1));
+        assert_eq!(srtt(&upstreams, addr), 1_000_000);
+
+        upstreams.record_failure(addr, Duration::from_secs(1));
+        assert_eq!(srtt(&upstreams, addr), 2_000_000);
+    }
+
+    #[test]
+    fn round_robin_rotates_the_first_server() {
+        let addrs: Vec<SocketAddr> = vec!["192.0.2.1:53".parse().unwrap(), "192.0.2.2:53".parse().unwrap()];
+        let upstreams = Upstreams::new(addrs.clone(), Selection::RoundRobin);
+
+        assert_eq!(upstreams.order(), addrs);
+        assert_eq!(upstreams.order(), vec![addrs[1], addrs[0]]);
+        assert_eq!(upstreams.order(), addrs);
+    }
+
+    #[test]
+    fn queries_servers_of_either_family() {
+        let client = UdpClient::new();
+        for ip in ["127.0.0.1", "::1"] {
+            let server = UdpSocket::bind((ip, 0)).unwrap();
+            let addr = server.local_addr().unwrap();
+            answer_queries(server, 0);
+
+            let response = client.query(&mut query("www.example.com"), addr, Duration::from_secs(2)).unwrap();
+            assert_eq!(response.get_a("www.example.com"), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+        }
+    }
+
+    #[test]
+    fn every_query_has_a_port_of_its_own() {
+        let client = UdpClient::new();
+        let server = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let addr = server.local_addr().unwrap();
+        let (sender, ports) = std::sync::mpsc::channel();
+        thread::spawn(move || loop {
+            let mut buffer = BytePacketBuffer::new();
+            let (len, src) = server.recv_from(&mut buffer.buf).unwrap();
+            let query = Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf[..len])).unwrap();
+            sender.send((src.port(), query.header.id)).unwrap();
+            send_response(&server, src, query.header.id, &query.questions[0], [192, 0, 2, 1]);
+        });
+
+        for _ in 0..20 {
+            client.query(&mut query("www.example.com"), addr, Duration::from_secs(2)).unwrap();
+        }
+        let seen: Vec<(u16, u16)> = ports.try_iter().collect();
+        assert_eq!(seen.len(), 20);
+        assert!(seen.iter().all(|(port, _)| *port >= MIN_PORT));
+        let distinct = |values: Vec<u16>| values.into_iter().collect::<std::collections::HashSet<u16>>().len();
+        assert!(distinct(seen.iter().map(|(port, _)| *port).collect()) > 15);
+        assert!(distinct(seen.iter().map(|(_, id)| *id).collect()) > 15);
+    }
+
+    #[test]
+    fn silent_servers_time_out() {
+        let server = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let started = Instant::now();
+        let e = UdpClient::new().query(&mut query("www.example.com"), addr, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn counts_mismatched_responses() {
+        let client = UdpClient::new();
+        let server = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let addr = server.local_addr().unwrap();
+        answer_queries(server, 2);
+
+        let response = client.query(&mut query("www.example.com"), addr, Duration::from_secs(2)).unwrap();
+        assert_eq!(response.get_a("www.example.com"), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+        assert_eq!(client.mismatched(), 2);
+    }
+}
@@ -0,0 +1,688 @@
+//! Chain of trust validation of resolver answers (RFC 4035 section 5).
+//! Keys are authenticated from the trust anchors down, one DS and DNSKEY
+//! RRset per zone cut, and then used to check the RRSIGs of the answer and
+//! of the NSEC or NSEC3 records proving a denial.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::dnssec::{self, Proof};
+use crate::packet::{is_subdomain, Packet};
+use crate::query::QueryType;
+use crate::record::Record;
+use crate::rescode::ResultCode;
+use crate::resolver::Resolver;
+
+/// The outcome of validating an answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Security {
+    /// Every RRset is signed by a key chained to a trust anchor.
+    Secure,
+    /// The answer comes from a zone proven to be unsigned.
+    Insecure,
+    /// Signatures or proofs that should be there are missing or wrong.
+    Bogus(String),
+}
+
+impl Security {
+    /// The weaker of two results, bogus being the weakest.
+    fn and(self, other: Security) -> Security {
+        match (self, other) {
+            (bogus @ Security::Bogus(_), _) | (_, bogus @ Security::Bogus(_)) => bogus,
+            (Security::Insecure, _) | (_, Security::Insecure) => Security::Insecure,
+            _ => Security::Secure,
+        }
+    }
+}
+
+impl From<Proof> for Security {
+    fn from(proof: Proof) -> Security {
+        match proof {
+            Proof::Proven => Security::Secure,
+            Proof::OptOut => Security::Insecure,
+            Proof::Missing => Security::Bogus("no proof of nonexistence".to_string()),
+        }
+    }
+}
+
+/// How long a zone whose keys could not be authenticated, or that was
+/// proven unsigned, is remembered as such.
+const FAILED_KEYS_TTL: Duration = Duration::from_secs(60);
+/// Most zones whose keys are remembered at once.
+const MAX_KEY_ZONES: usize = 10_000;
+
+/// The authenticated DNSKEY RRset of each zone, or why it has none, kept
+/// until the keys' TTL runs out so that every query does not walk the
+/// chain of trust again.
+#[derive(Default)]
+pub struct KeyCache {
+    zones: Mutex<HashMap<String, ZoneKeys>>,
+}
+
+struct ZoneKeys {
+    keys: Result<Vec<Record>, Security>,
+    expires: Instant,
+}
+
+impl KeyCache {
+    pub fn new() -> KeyCache {
+        KeyCache::default()
+    }
+
+    fn get(&self, zone: &str) -> Option<Result<Vec<Record>, Security>> {
+        let zones = self.zones();
+        let entry = zones.get(zone)?;
+        (entry.expires > Instant::now()).then(|| entry.keys.clone())
+    }
+
+    fn insert(&self, zone: &str, keys: Result<Vec<Record>, Security>) {
+        let ttl = match keys {
+            Ok(ref keys) => Duration::from_secs(keys.iter().map(Record::ttl).min().unwrap_or(0) as u64),
+            Err(_) => FAILED_KEYS_TTL,
+        };
+        let now = Instant::now();
+
+        let mut zones = self.zones();
+        if zones.len() >= MAX_KEY_ZONES {
+            zones.retain(|_, entry| entry.expires > now);
+        }
+        if zones.len() < MAX_KEY_ZONES {
+            zones.insert(
+                zone.to_string(),
+                ZoneKeys {
+                    keys,
+                    expires: now + ttl,
+                },
+            );
+        }
+    }
+
+    fn zones(&self) -> MutexGuard<'_, HashMap<String, ZoneKeys>> {
+        self.zones.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Validates answers, fetching the DS and DNSKEY records it needs through
+/// the resolver. Zone keys are remembered in the resolver's `KeyCache`.
+pub struct Validator<'a> {
+    resolver: &'a Resolver,
+    anchors: &'a [Record],
+    /// Seconds since the epoch, to check signature validity periods.
+    now: u32,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(resolver: &'a Resolver, anchors: &'a [Record]) -> Validator<'a> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32);
+
+        Validator { resolver, anchors, now }
+    }
+
+    /// Validates the answer to `qname` and `qtype`: every RRset in the
+    /// answer section, wildcard expansions included, and the proof of
+    /// denial for the name the CNAME chain ends at when there is no data.
+    pub fn validate(&mut self, qname: &str, qtype: QueryType, packet: &Packet) -> Security {
+        if !self.anchors.iter().any(|anchor| is_subdomain(qname, anchor.domain())) {
+            return Security::Insecure;
+        }
+        // Failures carry no data to vouch for.
+        if !matches!(packet.header.rcode, ResultCode::NOERROR | ResultCode::NXDOMAIN) {
+            return Security::Insecure;
+        }
+
+        let mut security = Security::Secure;
+        for (owner, rtype) in rrset_keys(&packet.answers) {
+            let rrset = rrset(&packet.answers, &owner, rtype);
+            let signatures = signatures(&packet.answers, &owner, rtype);
+            let result = if signatures.is_empty() {
+                self.prove_insecure(&owner, rtype)
+            } else {
+                match self.verify_signed(&rrset, &signatures) {
+                    Ok(rrsig) => self.check_expansion(&rrsig, &owner, &packet.authorities),
+                    Err(security) => security,
+                }
+            };
+            security = security.and(result);
+        }
+
+        let mut name = qname.to_ascii_lowercase();
+        for _ in 0..packet.answers.len() {
+            let target = packet.answers.iter().find_map(|record| match record {
+                Record::CNAME { domain, host, .. } if domain.eq_ignore_ascii_case(&name) => Some(host),
+                _ => None,
+            });
+            match target {
+                Some(host) if qtype != QueryType::CNAME => name = host.to_ascii_lowercase(),
+                _ => break,
+            }
+        }
+
+        let answered = packet
+            .answers
+            .iter()
+            .any(|record| record.domain().eq_ignore_ascii_case(&name) && record.qtype() == qtype);
+        if answered {
+            return security;
+        }
+
+        security.and(self.check_denial(&name, qtype, packet))
+    }
+
+    /// Checks the proof that `name` has no `qtype` data, or does not exist.
+    fn check_denial(&mut self, name: &str, qtype: QueryType, packet: &Packet) -> Security {
+        let soa = packet.authorities.iter().find(|record| record.qtype() == QueryType::SOA);
+        let Some(soa) = soa else {
+            return match self.prove_insecure(name, qtype) {
+                Security::Bogus(_) => Security::Bogus(format!("no SOA in the denial for {}", name)),
+                security => security,
+            };
+        };
+
+        let soa_signatures = signatures(&packet.authorities, soa.domain(), QueryType::SOA);
+        if soa_signatures.is_empty() {
+            return self.prove_insecure(name, qtype);
+        }
+        let soa_set = rrset(&packet.authorities, soa.domain(), QueryType::SOA);
+        if let Err(security) = self.verify_signed(&soa_set, &soa_signatures) {
+            return security;
+        }
+
+        let denial = match self.authenticated_denial(&packet.authorities) {
+            Ok(denial) => denial,
+            Err(security) => return security,
+        };
+        let proof = if packet.header.rcode == ResultCode::NXDOMAIN {
+            dnssec::prove_nxdomain(&denial, name)
+        } else {
+            dnssec::prove_nodata(&denial, name, qtype)
+        };
+
+        match Security::from(proof) {
+            Security::Bogus(_) => Security::Bogus(format!("no proof that {} {:?} does not exist", name, qtype)),
+            security => security,
+        }
+    }
+
+    /// An answer signed with fewer labels than its owner has was expanded
+    /// from a wildcard, which is only valid if the name itself does not
+    /// exist (RFC 4035 5.3.4).
+    fn check_expansion(&mut self, rrsig: &Record, owner: &str, authorities: &[Record]) -> Security {
+        let labels = match rrsig {
+            Record::RRSIG { labels, .. } => *labels as usize,
+            _ => return Security::Secure,
+        };
+        if labels >= dnssec::label_count(owner) {
+            return Security::Secure;
+        }
+
+        let denial = match self.authenticated_denial(authorities) {
+            Ok(denial) => denial,
+            Err(security) => return security,
+        };
+        match Security::from(dnssec::prove_wildcard_expansion(&denial, owner, labels)) {
+            Security::Bogus(_) => Security::Bogus(format!("wildcard answer for {} without proof it is needed", owner)),
+            security => security,
+        }
+    }
+
+    /// The NSEC and NSEC3 records among `records` with valid signatures.
+    /// NSEC3 records with more than `MAX_NSEC3_ITERATIONS` make the answer
+    /// insecure (RFC 9276 section 3.2).
+    fn authenticated_denial(&mut self, records: &[Record]) -> Result<Vec<Record>, Security> {
+        let mut denial = Vec::new();
+        for (owner, rtype) in rrset_keys(records) {
+            if !matches!(rtype, QueryType::NSEC | QueryType::NSEC3) {
+                continue;
+            }
+
+            // A denial expanded from a wildcard proves nothing.
+            let rrset = rrset(records, &owner, rtype);
+            match self.verify_signed(&rrset, &signatures(records, &owner, rtype)) {
+                Ok(Record::RRSIG { labels, .. }) if labels as usize == dnssec::label_count(&owner) => {
+                    let costly = rrset.iter().any(|record| {
+                        matches!(record, Record::NSEC3 { iterations, .. } if *iterations > dnssec::MAX_NSEC3_ITERATIONS)
+                    });
+                    if costly {
+                        return Err(Security::Insecure);
+                    }
+                    denial.extend(rrset)
+                }
+                Err(Security::Insecure) => return Err(Security::Insecure),
+                // Proofs that do not check out are left out, which only
+                // matters if they were needed.
+                _ => {}
+            }
+        }
+
+        Ok(denial)
+    }
+
+    /// Checks that one of `signatures` over `rrset` is current and made by
+    /// an authenticated key of the signer's zone, returning that signature.
+    fn verify_signed(&mut self, rrset: &[Record], signatures: &[Record]) -> Result<Record, Security> {
+        let Some(first) = rrset.first() else {
+            return Err(Security::Bogus("empty RRset".to_string()));
+        };
+        let (owner, rtype) = (first.domain().to_ascii_lowercase(), first.qtype());
+
+        let mut failure = Security::Bogus(format!("no valid signature over {} {:?}", owner, rtype));
+        let mut insecure = false;
+        for rrsig in signatures {
+            let Record::RRSIG { signer, .. } = rrsig else {
+                continue;
+            };
+            let signer = signer.to_ascii_lowercase();
+            // A DS record is signed by the parent, never the zone it is for.
+            if !is_subdomain(&owner, &signer) || (rtype == QueryType::DS && signer == owner) {
+                continue;
+            }
+            if !dnssec::signature_current(rrsig, self.now) {
+                failure = Security::Bogus(format!("signature over {} {:?} is expired or not yet valid", owner, rtype));
+                continue;
+            }
+
+            match self.zone_keys(&signer) {
+                Ok(keys) => {
+                    if keys.iter().any(|key| dnssec::verify(rrsig, key, rrset)) {
+                        return Ok(rrsig.clone());
+                    }
+                }
+                Err(Security::Insecure) => insecure = true,
+                Err(security) => failure = security,
+            }
+        }
+
+        Err(if insecure { Security::Insecure } else { failure })
+    }
+
+    /// The authenticated DNSKEY RRset of `zone`, or why there is none.
+    fn zone_keys(&mut self, zone: &str) -> Result<Vec<Record>, Security> {
+        if let Some(keys) = self.resolver.keys.get(zone) {
+            return keys;
+        }
+
+        let keys = self.fetch_zone_keys(zone);
+        self.resolver.keys.insert(zone, keys.clone());
+
+        keys
+    }
+
+    fn fetch_zone_keys(&mut self, zone: &str) -> Result<Vec<Record>, Security> {
+        let anchors: Vec<Record> = self
+            .anchors
+            .iter()
+            .filter(|anchor| anchor.domain().eq_ignore_ascii_case(zone))
+            .cloned()
+            .collect();
+        let entry_points = if !anchors.is_empty() {
+            anchors
+        } else if !self.anchors.iter().any(|anchor| is_subdomain(zone, anchor.domain())) {
+            return Err(Security::Insecure);
+        } else {
+            match self.fetch_ds(zone)? {
+                Some(ds) => ds,
+                None => return Err(Security::Bogus(format!("{} is not a zone cut", zone))),
+            }
+        };
+
+        // A zone whose DS records all use algorithms we lack is treated as
+        // unsigned (RFC 4035 5.2).
+        let usable = |record: &&Record| match record {
+            Record::DS { algorithm, digest_type, .. } => {
+                dnssec::supports_algorithm(*algorithm) && dnssec::supports_digest(*digest_type)
+            }
+            Record::DNSKEY { algorithm, .. } => dnssec::supports_algorithm(*algorithm),
+            _ => false,
+        };
+        let entry_points: Vec<&Record> = entry_points.iter().filter(usable).collect();
+        if entry_points.is_empty() {
+            return Err(Security::Insecure);
+        }
+
+        let packet = self.lookup(zone, QueryType::DNSKEY)?;
+        let keys = rrset(&packet.answers, zone, QueryType::DNSKEY);
+        let signatures = signatures(&packet.answers, zone, QueryType::DNSKEY);
+
+        let trusted = keys.iter().filter(|key| {
+            entry_points.iter().any(|entry| match entry {
+                Record::DS { .. } => dnssec::ds_matches(entry, key),
+                Record::DNSKEY { public_key, algorithm, .. } => matches!(
+                    key,
+                    Record::DNSKEY { public_key: k, algorithm: a, .. } if k == public_key && a == algorithm
+                ),
+                _ => false,
+            })
+        });
+        let signed = trusted.into_iter().any(|key| {
+            signatures
+                .iter()
+                .any(|rrsig| dnssec::signature_current(rrsig, self.now) && dnssec::verify(rrsig, key, &keys))
+        });
+
+        if signed {
+            Ok(keys)
+        } else {
+            Err(Security::Bogus(format!("no DNSKEY of {} matches its DS records", zone)))
+        }
+    }
+
+    /// The authenticated DS RRset for `name`, `None` if the name is proven
+    /// not to be a zone cut, or an error if it is an unsigned delegation.
+    fn fetch_ds(&mut self, name: &str) -> Result<Option<Vec<Record>>, Security> {
+        let packet = self.lookup(name, QueryType::DS)?;
+
+        let ds = rrset(&packet.answers, name, QueryType::DS);
+        if !ds.is_empty() {
+            self.verify_signed(&ds, &signatures(&packet.answers, name, QueryType::DS))?;
+            return Ok(Some(ds));
+        }
+
+        // The denial comes from the parent zone, which has to be signed.
+        let parent = packet.authorities.iter().find_map(|record| match record {
+            Record::SOA { domain, .. } if is_subdomain(name, domain) && !domain.eq_ignore_ascii_case(name) => {
+                Some(domain.to_ascii_lowercase())
+            }
+            _ => None,
+        });
+        let Some(parent) = parent else {
+            return Err(Security::Bogus(format!("no proof that {} has no DS records", name)));
+        };
+        self.zone_keys(&parent)?;
+
+        let denial = self.authenticated_denial(&packet.authorities)?;
+        if dnssec::proves_insecure_delegation(&denial, name) {
+            return Err(Security::Insecure);
+        }
+        let proof = if packet.header.rcode == ResultCode::NXDOMAIN {
+            dnssec::prove_nxdomain(&denial, name)
+        } else {
+            dnssec::prove_nodata(&denial, name, QueryType::DS)
+        };
+
+        match proof {
+            Proof::Proven => Ok(None),
+            Proof::OptOut => Err(Security::Insecure),
+            Proof::Missing => Err(Security::Bogus(format!("no proof that {} has no DS records", name))),
+        }
+    }
+
+    /// Unsigned data is only acceptable below an unsigned delegation. The
+    /// names between the trust anchor and `name` are checked for DS
+    /// records from the top down until one is proven to be missing.
+    fn prove_insecure(&mut self, name: &str, rtype: QueryType) -> Security {
+        let name = name.to_ascii_lowercase();
+        let anchor = self
+            .anchors
+            .iter()
+            .map(|anchor| anchor.domain().to_ascii_lowercase())
+            .filter(|anchor| is_subdomain(&name, anchor))
+            .max_by_key(|anchor| anchor.len());
+        let Some(anchor) = anchor else {
+            return Security::Insecure;
+        };
+
+        // A DS RRset is data of the parent zone.
+        let last = match rtype {
+            QueryType::DS => dnssec::parent(&name).unwrap_or(""),
+            _ => &name,
+        };
+        let mut cuts = Vec::new();
+        let mut cut = last;
+        while cut != anchor && is_subdomain(cut, &anchor) {
+            cuts.push(cut);
+            cut = dnssec::parent(cut).unwrap_or("");
+        }
+
+        for cut in cuts.into_iter().rev() {
+            match self.fetch_ds(cut) {
+                Ok(_) => {}
+                Err(security) => return security,
+            }
+        }
+
+        Security::Bogus(format!("{} {:?} is not signed", name, rtype))
+    }
+
+    fn lookup(&self, name: &str, qtype: QueryType) -> Result<Packet, Security> {
+        self.resolver
+            .resolve(name, qtype)
+            .map_err(|e| Security::Bogus(format!("{} {:?} lookup failed: {}", name, qtype, e)))
+    }
+}
+
+/// Owner and type of every RRset among `records`, signatures aside, in
+/// the order they first appear.
+fn rrset_keys(records: &[Record]) -> Vec<(String, QueryType)> {
+    let mut keys: Vec<(String, QueryType)> = Vec::new();
+    for record in records {
+        let key = (record.domain().to_ascii_lowercase(), record.qtype());
+        if key.1 != QueryType::RRSIG && key.1 != QueryType::OPT && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    keys
+}
+
+fn rrset(records: &[Record], owner: &str, rtype: QueryType) -> Vec<Record> {
+    records
+        .iter()
+        .filter(|record| record.qtype() == rtype && record.domain().eq_ignore_ascii_case(owner))
+        .cloned()
+        .collect()
+}
+
+fn signatures(records: &[Record], owner: &str, rtype: QueryType) -> Vec<Record> {
+    records
+        .iter()
+        .filter(|record| match record {
+            Record::RRSIG { type_covered, .. } => *type_covered == rtype && record.domain().eq_ignore_ascii_case(owner),
+            _ => false,
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{BytePacketBuffer, SlicePacketBuffer};
+    use crate::query::QueryClass;
+    use crate::signer::SigningKey;
+    use crate::testing::{a, ns, signing_key, soa};
+    use crate::zone::Zone;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    /// The signed zone `example`, served from `ip`, delegating the
+    /// unsigned `unsigned.example` to `child_ip`. With `nsec3` set its
+    /// denials use NSEC3 with that many iterations.
+    fn signed_zone(key: SigningKey, ip: &str, child_ip: &str, nsec3: Option<u16>) -> Zone {
+        let mut records = vec![
+            soa("example"),
+            ns("example", "ns.example"),
+            a("ns.example", ip),
+            a("www.example", "192.0.2.1"),
+            a("tampered.example", "192.0.2.2"),
+            a("expired.example", "192.0.2.3"),
+            ns("unsigned.example", "ns.unsigned.example"),
+            a("ns.unsigned.example", child_ip),
+        ];
+        if let Some(iterations) = nsec3 {
+            records.push(Record::NSEC3PARAM {
+                domain: "example".to_string(),
+                class: QueryClass::IN,
+                hash_algorithm: 1,
+                flags: 0,
+                iterations,
+                salt: vec![0xab, 0xcd],
+                ttl: 0,
+            });
+        }
+
+        let mut zone = Zone::new("example", records).unwrap();
+        zone.add_key(key);
+        zone
+    }
+
+    fn unsigned_zone(ip: &str) -> Zone {
+        let records = vec![
+            soa("unsigned.example"),
+            ns("unsigned.example", "ns.unsigned.example"),
+            a("ns.unsigned.example", ip),
+            a("www.unsigned.example", "192.0.2.4"),
+        ];
+        Zone::new("unsigned.example", records).unwrap()
+    }
+
+    /// Serves each zone from its address, all on one free port, which is
+    /// returned. Answers are always signed where the zone has keys, and
+    /// may be altered by `tamper` before they are sent.
+    fn serve(zones: Vec<(&str, Zone)>, tamper: fn(&mut Packet)) -> u16 {
+        let mut port = 0;
+        for (ip, zone) in zones {
+            let socket = UdpSocket::bind((ip, port)).unwrap();
+            port = socket.local_addr().unwrap().port();
+            thread::spawn(move || loop {
+                let mut buffer = BytePacketBuffer::new();
+                let (len, src) = socket.recv_from(&mut buffer.buf).unwrap();
+                let query = Packet::from_buffer(&mut SlicePacketBuffer::new(&buffer.buf[..len])).unwrap();
+                let question = &query.questions[0];
+
+                let mut response = zone.lookup(&question.name, question.qtype, true);
+                tamper(&mut response);
+                response.header.id = query.header.id;
+                response.header.qr = true;
+                response.questions = query.questions.clone();
+                let mut out = BytePacketBuffer::with_capacity(4096);
+                response.write(&mut out).unwrap();
+                socket.send_to(&out.buf[..out.pos], src).unwrap();
+            });
+        }
+        port
+    }
+
+    fn resolver(root: &str, port: u16, anchor: Record) -> Resolver {
+        let mut resolver = Resolver::new().unwrap();
+        resolver.root_hints = vec![root.parse().unwrap()];
+        resolver.port = port;
+        resolver.timeout = Duration::from_millis(500);
+        resolver.trust_anchors = vec![anchor];
+        resolver
+    }
+
+    fn validate(resolver: &Resolver, qname: &str, qtype: QueryType) -> Security {
+        let response = resolver.resolve(qname, qtype).unwrap();
+        resolver.validate(qname, qtype, &response)
+    }
+
+    #[test]
+    fn signed_answers_and_denials_are_secure() {
+        let key = signing_key("example", true);
+        let anchor = key.ds(dnssec::DIGEST_SHA256);
+        let port = serve(
+            vec![("127.1.6.1", signed_zone(key, "127.1.6.1", "127.1.6.2", None))],
+            |_| {},
+        );
+        let resolver = resolver("127.1.6.1", port, anchor);
+
+        assert_eq!(validate(&resolver, "www.example", QueryType::A), Security::Secure);
+        assert_eq!(validate(&resolver, "missing.example", QueryType::A), Security::Secure);
+        assert_eq!(validate(&resolver, "www.example", QueryType::MX), Security::Secure);
+
+        // The authenticated keys outlive the validator that fetched them.
+        assert!(matches!(resolver.keys.get("example"), Some(Ok(_))));
+    }
+
+    #[test]
+    fn tampered_and_expired_signatures_are_bogus() {
+        fn tamper(packet: &mut Packet) {
+            for record in packet.answers.iter_mut() {
+                match record {
+                    Record::RRSIG { domain, signature, .. } if domain == "tampered.example" => signature[0] ^= 1,
+                    Record::RRSIG {
+                        domain,
+                        inception,
+                        expiration,
+                        ..
+                    } if domain == "expired.example" => {
+                        // Long gone, and no longer matching the signature
+                        // either, but expiry is checked first.
+                        *inception = 1_000_000_000;
+                        *expiration = 1_100_000_000;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let key = signing_key("example", true);
+        let anchor = key.ds(dnssec::DIGEST_SHA256);
+        let port = serve(
+            vec![("127.1.7.1", signed_zone(key, "127.1.7.1", "127.1.7.2", None))],
+            tamper,
+        );
+        let resolver = resolver("127.1.7.1", port, anchor);
+
+        assert_eq!(validate(&resolver, "www.example", QueryType::A), Security::Secure);
+        assert!(matches!(
+            validate(&resolver, "tampered.example", QueryType::A),
+            Security::Bogus(_)
+        ));
+        match validate(&resolver, "expired.example", QueryType::A) {
+            Security::Bogus(reason) => assert!(reason.contains("expired"), "{}", reason),
+            security => panic!("expected an expired signature, got {:?}", security),
+        }
+    }
+
+    #[test]
+    fn unsigned_delegation_is_insecure() {
+        let key = signing_key("example", true);
+        let anchor = key.ds(dnssec::DIGEST_SHA256);
+        let port = serve(
+            vec![
+                ("127.1.8.1", signed_zone(key, "127.1.8.1", "127.1.8.2", None)),
+                ("127.1.8.2", unsigned_zone("127.1.8.2")),
+            ],
+            |_| {},
+        );
+        let resolver = resolver("127.1.8.1", port, anchor);
+
+        let response = resolver.resolve("www.unsigned.example", QueryType::A).unwrap();
+        assert_eq!(response.answers, vec![a("www.unsigned.example", "192.0.2.4")]);
+        assert_eq!(
+            resolver.validate("www.unsigned.example", QueryType::A, &response),
+            Security::Insecure
+        );
+    }
+
+    #[test]
+    fn wrong_trust_anchor_is_bogus() {
+        let key = signing_key("example", true);
+        let anchor = signing_key("example", true).ds(dnssec::DIGEST_SHA256);
+        let port = serve(
+            vec![("127.1.9.1", signed_zone(key, "127.1.9.1", "127.1.9.2", None))],
+            |_| {},
+        );
+        let resolver = resolver("127.1.9.1", port, anchor);
+
+        assert!(matches!(validate(&resolver, "www.example", QueryType::A), Security::Bogus(_)));
+    }
+
+    #[test]
+    fn nsec3_iterations_over_the_limit_are_insecure() {
+        for (ip, iterations, expected) in [
+            ("127.1.10.1", 5, Security::Secure),
+            ("127.1.11.1", dnssec::MAX_NSEC3_ITERATIONS + 1, Security::Insecure),
+        ] {
+            let key = signing_key("example", true);
+            let anchor = key.ds(dnssec::DIGEST_SHA256);
+            let port = serve(vec![(ip, signed_zone(key, ip, "127.0.0.1", Some(iterations)))], |_| {});
+            let resolver = resolver(ip, port, anchor);
+
+            assert_eq!(validate(&resolver, "missing.example", QueryType::A), expected);
+            assert_eq!(validate(&resolver, "www.example", QueryType::MX), expected);
+        }
+    }
+}
@@ -0,0 +1,1416 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dnssec::{self, canonical_cmp, label_count, wildcard_of};
+use crate::encoding::base32hex_encode;
+use crate::packet::{is_subdomain, Packet};
+use crate::query::{QueryClass, QueryType};
+use crate::record::Record;
+use crate::rescode::ResultCode;
+use crate::signer::{self, SigningKey};
+use crate::zonefile;
+
+/// How many CNAMEs are followed inside a zone before giving up.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// A zone we are authoritative for, held in memory.
+pub struct Zone {
+    pub origin: String,
+    soa: Record,
+    /// Every record, by lowercase owner name.
+    records: HashMap<String, Vec<Record>>,
+    /// Every name that exists, empty non-terminals included.
+    names: HashSet<String>,
+    /// Every name not below a zone cut in canonical order, empty
+    /// non-terminals included, where the neighbours of a name being denied
+    /// are looked up.
+    sorted: Vec<String>,
+    /// Keys answers are signed with for clients that set the DO bit.
+    keys: Vec<SigningKey>,
+}
+
+impl Zone {
+    /// Builds a zone from its records, which must all lie under `origin`
+    /// and include exactly one SOA at the apex.
+    pub fn new(origin: &str, records: Vec<Record>) -> Result<Zone> {
+        let origin = origin.trim_end_matches('.').to_ascii_lowercase();
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, format!("zone {}: {}", origin, msg));
+
+        let mut soa = None;
+        let mut by_name: HashMap<String, Vec<Record>> = HashMap::new();
+        let mut names = HashSet::new();
+
+        for record in records {
+            let name = record.domain().to_ascii_lowercase();
+            if !is_subdomain(&name, &origin) {
+                return Err(invalid(format!("{} is outside the zone", name)));
+            }
+
+            if let Record::SOA { .. } = record {
+                if name != origin {
+                    return Err(invalid(format!("SOA record at {} is not at the apex", name)));
+                }
+                if soa.replace(record.clone()).is_some() {
+                    return Err(invalid("more than one SOA record".to_string()));
+                }
+            }
+
+            // The owner and every name between it and the apex exist.
+            let mut ancestor = name.as_str();
+            while names.insert(ancestor.to_string()) && ancestor != origin {
+                ancestor = ancestor.split_once('.').map_or("", |(_, parent)| parent);
+            }
+
+            by_name.entry(name).or_default().push(record);
+        }
+
+        let soa = soa.ok_or_else(|| invalid("no SOA record".to_string()))?;
+        for (name, records) in &by_name {
+            let cnames = records.iter().filter(|rec| rec.qtype() == QueryType::CNAME).count();
+            if cnames > 0 && records.len() > 1 {
+                return Err(invalid(format!("CNAME at {} has other data", name)));
+            }
+            if records.iter().any(|rec| rec.class() != soa.class()) {
+                return Err(invalid(format!("records at {} differ in class from the SOA", name)));
+            }
+        }
+
+        let mut zone = Zone {
+            origin,
+            soa,
+            records: by_name,
+            names,
+            sorted: Vec::new(),
+            keys: Vec::new(),
+        };
+
+        // Glue below a zone cut is not part of the NSEC chain.
+        let mut sorted: Vec<String> = zone
+            .names
+            .iter()
+            .filter(|name| zone.delegation(name, QueryType::DS).is_none())
+            .cloned()
+            .collect();
+        sorted.sort_by(|a, b| canonical_cmp(a, b));
+        zone.sorted = sorted;
+
+        Ok(zone)
+    }
+
+    /// Loads the zone for `origin` from a master file.
+    pub fn load(origin: &str, path: &Path) -> Result<Zone> {
+        Zone::new(origin, zonefile::load(path, origin)?)
+    }
+
+    /// Adds a key to sign answers with and publishes it in the DNSKEY
+    /// RRset at the apex.
+    pub fn add_key(&mut self, key: SigningKey) {
+        let apex = self.records.entry(self.origin.clone()).or_default();
+        if !apex.contains(&key.dnskey) {
+            apex.push(key.dnskey.clone());
+        }
+        self.keys.push(key);
+    }
+
+    /// DS records for the parent zone to publish, made from the key
+    /// signing keys, or from every key if none is marked as one.
+    pub fn ds_records(&self) -> Vec<Record> {
+        self.signing_keys(QueryType::DNSKEY)
+            .map(|key| key.ds(dnssec::DIGEST_SHA256))
+            .collect()
+    }
+
+    /// Answers a query for a name inside the zone (RFC 1034 4.3.2). With
+    /// `dnssec` set, for a client that set the DO bit, the answer is
+    /// signed if the zone has keys.
+    pub fn lookup(&self, qname: &str, qtype: QueryType, dnssec: bool) -> Packet {
+        let mut packet = self.answer(qname, qtype);
+        if dnssec && !self.keys.is_empty() {
+            self.sign_response(&mut packet, qname, qtype);
+        }
+
+        packet
+    }
+
+    /// Every record of the zone signed for publishing, along with a full
+    /// NSEC3 chain, or NSEC chain if the apex has no NSEC3PARAM record.
+    /// Records come in canonical order so the same input and signature
+    /// times give the same output.
+    pub fn sign(&self, inception: u32, expiration: u32) -> Vec<Record> {
+        let mut records: Vec<Record> = self.records.values().flatten().cloned().collect();
+        records.extend(self.chain());
+        records.sort_by(dnssec::canonical_order);
+        records.dedup();
+
+        let mut signed = Vec::with_capacity(records.len() * 2);
+        for rrset in records.chunk_by(|a, b| a.qtype() == b.qtype() && a.domain().eq_ignore_ascii_case(b.domain())) {
+            signed.extend(rrset.iter().cloned());
+            signed.extend(self.signatures(rrset, inception, expiration));
+        }
+        signed.sort_by(dnssec::canonical_order);
+
+        signed
+    }
+
+    /// Signs the records of a master file for `origin` afresh, as the
+    /// `sign-zone` command does. Signatures and denials from an earlier
+    /// signing are dropped, the SOA serial is bumped, the NSEC3PARAM record
+    /// is replaced by one with `nsec3`, and the zone is signed with `keys`.
+    /// Signatures run from `inception`, by default an hour ago, until
+    /// `expiration`, by default `signer::OFFLINE_SIGNATURE_VALIDITY` later.
+    /// Returns the signed records and the DS records for the parent zone.
+    pub fn resign(
+        origin: &str,
+        mut records: Vec<Record>,
+        keys: Vec<SigningKey>,
+        nsec3: Nsec3Params,
+        inception: Option<u32>,
+        expiration: Option<u32>,
+    ) -> Result<(Vec<Record>, Vec<Record>)> {
+        records.retain(|record| {
+            !matches!(
+                record,
+                Record::RRSIG { .. } | Record::NSEC { .. } | Record::NSEC3 { .. } | Record::NSEC3PARAM { .. }
+            )
+        });
+
+        let mut minimum_ttl = 0;
+        for record in records.iter_mut() {
+            if let Record::SOA {
+                ref mut serial, minimum, ..
+            } = *record
+            {
+                *serial = serial.wrapping_add(1);
+                minimum_ttl = minimum;
+            }
+        }
+        records.push(Record::NSEC3PARAM {
+            domain: origin.trim_end_matches('.').to_ascii_lowercase(),
+            class: QueryClass::IN,
+            hash_algorithm: 1,
+            flags: 0,
+            iterations: nsec3.iterations,
+            salt: nsec3.salt,
+            ttl: minimum_ttl,
+        });
+
+        let mut zone = Zone::new(origin, records)?;
+        for key in keys {
+            zone.add_key(key);
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32);
+        let inception = inception.unwrap_or(now.wrapping_sub(signer::SIGNATURE_BACKDATE));
+        let expiration = expiration.unwrap_or(inception.wrapping_add(signer::OFFLINE_SIGNATURE_VALIDITY));
+
+        Ok((zone.sign(inception, expiration), zone.ds_records()))
+    }
+
+    fn answer(&self, qname: &str, qtype: QueryType) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.aa = true;
+
+        let mut name = qname.to_ascii_lowercase();
+        for _ in 0..MAX_CNAME_CHAIN {
+            if let Some(ns) = self.delegation(&name, qtype) {
+                // Only what came before the cut is ours to vouch for.
+                packet.header.aa = !packet.answers.is_empty();
+                self.add_addresses(&mut packet, &ns);
+                packet.authorities.extend(ns);
+                return packet;
+            }
+
+            let records = match self.records_at(&name) {
+                Some(records) if records.is_empty() => {
+                    packet.authorities.push(self.negative_soa());
+                    return packet;
+                }
+                Some(records) => records,
+                None => {
+                    packet.header.rcode = ResultCode::NXDOMAIN;
+                    packet.authorities.push(self.negative_soa());
+                    return packet;
+                }
+            };
+
+            let matching: Vec<Record> = records.iter().filter(|rec| rec.qtype() == qtype).cloned().collect();
+            if !matching.is_empty() {
+                self.add_addresses(&mut packet, &matching);
+                packet.answers.extend(matching);
+                return packet;
+            }
+
+            match records.iter().find(|rec| rec.qtype() == QueryType::CNAME) {
+                Some(cname @ Record::CNAME { host, .. }) => {
+                    // A loop ends once it has been listed in full.
+                    if packet.answers.contains(cname) {
+                        return packet;
+                    }
+                    packet.answers.push(cname.clone());
+                    // Targets elsewhere are left to the client to chase.
+                    if !is_subdomain(host, &self.origin) {
+                        return packet;
+                    }
+                    name = host.to_ascii_lowercase();
+                }
+                _ => {
+                    packet.authorities.push(self.negative_soa());
+                    return packet;
+                }
+            }
+        }
+
+        packet
+    }
+
+    /// The records owned by `name`, synthesised from the source of
+    /// synthesis when the name does not exist (RFC 4592 section 3.3).
+    /// `None` means neither does a matching wildcard.
+    fn records_at(&self, name: &str) -> Option<Vec<Record>> {
+        if self.names.contains(name) {
+            return Some(self.records.get(name).cloned().unwrap_or_default());
+        }
+
+        // The closest encloser always exists, as the apex does. A wildcard
+        // only matches directly below it, so an existing empty
+        // non-terminal in between blocks the match.
+        let mut encloser = name;
+        while !self.names.contains(encloser) && !encloser.is_empty() {
+            encloser = encloser.split_once('.').map_or("", |(_, parent)| parent);
+        }
+
+        let source = if encloser.is_empty() {
+            "*".to_string()
+        } else {
+            format!("*.{}", encloser)
+        };
+        if !self.names.contains(&source) {
+            return None;
+        }
+
+        let synthesised = self.records.get(&source).into_iter().flatten().map(|rec| {
+            let mut rec = rec.clone();
+            rec.set_domain(name);
+            rec
+        });
+        Some(synthesised.collect())
+    }
+
+    /// NS records of the highest zone cut between the apex and `name`.
+    /// The DS records at a cut belong to this side of it, so for DS
+    /// queries `name` itself does not count (RFC 4035 3.1.4.1).
+    fn delegation(&self, name: &str, qtype: QueryType) -> Option<Vec<Record>> {
+        let mut cuts = Vec::new();
+        let mut ancestor = name;
+        if qtype == QueryType::DS {
+            ancestor = ancestor.split_once('.').map_or("", |(_, parent)| parent);
+        }
+        while ancestor != self.origin && is_subdomain(ancestor, &self.origin) {
+            cuts.push(ancestor);
+            ancestor = ancestor.split_once('.').map_or("", |(_, parent)| parent);
+        }
+
+        cuts.iter().rev().find_map(|cut| {
+            let ns: Vec<Record> = self
+                .records
+                .get(*cut)?
+                .iter()
+                .filter(|rec| rec.qtype() == QueryType::NS)
+                .cloned()
+                .collect();
+            (!ns.is_empty()).then_some(ns)
+        })
+    }
+
+    /// Adds in-zone A and AAAA records for the hosts named by NS, MX and
+    /// SRV records to the additional section, glue included.
+    fn add_addresses(&self, packet: &mut Packet, records: &[Record]) {
+        for record in records {
+            let host = match record {
+                Record::NS { host, .. } | Record::MX { host, .. } | Record::SRV { host, .. } => host,
+                _ => continue,
+            };
+
+            let addresses = self.records.get(&host.to_ascii_lowercase()).into_iter().flatten();
+            for address in addresses {
+                if matches!(address, Record::A { .. } | Record::AAAA { .. }) && !packet.resources.contains(address) {
+                    packet.resources.push(address.clone());
+                }
+            }
+        }
+    }
+
+    /// The SOA for the authority section of a negative answer, with the
+    /// TTL negative caches should use (RFC 2308 section 3).
+    fn negative_soa(&self) -> Record {
+        let mut soa = self.soa.clone();
+        if let Record::SOA { minimum, ttl, .. } = soa {
+            soa.set_ttl(minimum.min(ttl));
+        }
+
+        soa
+    }
+
+    /// Signs the RRsets of a response from `answer` and adds the NSEC or
+    /// NSEC3 records for whatever it denies. These are made up for the
+    /// query so as to cover nothing but the name asked about, which keeps
+    /// the zone from being walked (RFC 4470, RFC 7129 appendix B).
+    fn sign_response(&self, packet: &mut Packet, qname: &str, qtype: QueryType) {
+        let mut proof = Vec::new();
+
+        // The name the CNAME chain in the answer ends at.
+        let mut name = qname.to_ascii_lowercase();
+        for record in &packet.answers {
+            match record {
+                Record::CNAME { domain, host, .. } if qtype != QueryType::CNAME && domain.eq_ignore_ascii_case(&name) => {
+                    name = host.to_ascii_lowercase();
+                }
+                _ => {}
+            }
+        }
+
+        // Answers expanded from a wildcard need proof the name is missing.
+        for rrset in rrsets(&packet.answers) {
+            let owner = rrset[0].domain().to_ascii_lowercase();
+            if !self.names.contains(&owner) {
+                proof.extend(self.covering(&self.next_closer(&owner)));
+            }
+        }
+
+        let answered = packet
+            .answers
+            .iter()
+            .any(|record| record.domain().eq_ignore_ascii_case(&name) && record.qtype() == qtype);
+        let referral = packet.authorities.iter().find(|record| record.qtype() == QueryType::NS);
+        if let Some(ns) = referral {
+            // A delegation either has signed DS records or proof that it
+            // has none, and so is unsigned.
+            let cut = ns.domain().to_ascii_lowercase();
+            let ds: Vec<Record> = self
+                .records
+                .get(&cut)
+                .into_iter()
+                .flatten()
+                .filter(|record| record.qtype() == QueryType::DS)
+                .cloned()
+                .collect();
+            if ds.is_empty() {
+                proof.extend(self.matching(&cut));
+            } else {
+                packet.authorities.extend(ds);
+            }
+        } else if !answered && is_subdomain(&name, &self.origin) {
+            if packet.header.rcode == ResultCode::NXDOMAIN {
+                proof.extend(self.deny_name(&name));
+            } else {
+                proof.extend(self.deny_type(&name));
+            }
+        }
+
+        // Two spans from the same owner are merged into the longer one,
+        // as an owner can only have one NSEC record.
+        let mut denial: Vec<Record> = Vec::new();
+        for record in proof {
+            match denial.iter_mut().find(|other| other.domain() == record.domain()) {
+                Some(other) => {
+                    if let (Record::NSEC { next, .. }, Record::NSEC { next: other_next, .. }) = (&record, &*other) {
+                        if canonical_cmp(next, other_next) == Ordering::Greater {
+                            *other = record;
+                        }
+                    }
+                }
+                None => denial.push(record),
+            }
+        }
+        packet.authorities.extend(denial);
+
+        packet.answers = self.with_signatures(&packet.answers);
+        packet.authorities = self.with_signatures(&packet.authorities);
+        packet.resources = self.with_signatures(&packet.resources);
+    }
+
+    /// `records` with each RRset followed by its signatures.
+    fn with_signatures(&self, records: &[Record]) -> Vec<Record> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32);
+        let inception = now.wrapping_sub(signer::SIGNATURE_BACKDATE);
+        let expiration = now.wrapping_add(signer::ONLINE_SIGNATURE_VALIDITY);
+
+        let mut signed = Vec::new();
+        for rrset in rrsets(records) {
+            signed.extend(rrset.iter().cloned());
+            signed.extend(self.signatures(&rrset, inception, expiration));
+        }
+
+        signed
+    }
+
+    /// Signatures over `rrset` by each key that signs its type. NS records
+    /// at a cut and glue are not signed.
+    fn signatures(&self, rrset: &[Record], inception: u32, expiration: u32) -> Vec<Record> {
+        let owner = rrset[0].domain().to_ascii_lowercase();
+        let rtype = rrset[0].qtype();
+
+        let at_cut = owner != self.origin && rtype == QueryType::NS;
+        if at_cut || self.delegation(&owner, QueryType::DS).is_some() {
+            return Vec::new();
+        }
+
+        // Records expanded from a wildcard are signed as the wildcard.
+        // Denials are made up for names that need not exist.
+        let expanded = !self.names.contains(&owner) && !matches!(rtype, QueryType::NSEC | QueryType::NSEC3);
+        let labels = if expanded {
+            label_count(self.closest_encloser(&owner))
+        } else {
+            label_count(&owner)
+        };
+
+        let mut signatures = Vec::new();
+        for key in self.signing_keys(rtype) {
+            match key.sign(rrset, labels as u8, inception, expiration) {
+                Ok(rrsig) => signatures.push(rrsig),
+                Err(e) => eprintln!("Failed to sign {} {:?}: {}", owner, rtype, e),
+            }
+        }
+
+        signatures
+    }
+
+    /// Key signing keys sign the DNSKEY RRset and zone signing keys all
+    /// the rest. A zone with keys of only one kind signs with all of them.
+    fn signing_keys(&self, rtype: QueryType) -> impl Iterator<Item = &SigningKey> {
+        let ksk = rtype == QueryType::DNSKEY;
+        let split = self.keys.iter().any(|key| key.is_ksk() == ksk);
+
+        self.keys.iter().filter(move |key| !split || key.is_ksk() == ksk)
+    }
+
+    /// Proof that `name` does not exist, nor a wildcard that could stand
+    /// in for it.
+    fn deny_name(&self, name: &str) -> Vec<Record> {
+        let encloser = self.closest_encloser(name);
+
+        let mut proof = Vec::new();
+        if self.nsec3_param().is_some() {
+            proof.extend(self.matching(encloser));
+        }
+        proof.extend(self.covering(&self.next_closer(name)));
+        proof.extend(self.covering(&wildcard_of(encloser)));
+
+        proof
+    }
+
+    /// Proof that `name`, or the wildcard it was expanded from, has no
+    /// records of the type asked for.
+    fn deny_type(&self, name: &str) -> Vec<Record> {
+        if self.names.contains(name) {
+            return self.matching(name);
+        }
+
+        let encloser = self.closest_encloser(name);
+        let mut proof = Vec::new();
+        if self.nsec3_param().is_some() {
+            proof.extend(self.matching(encloser));
+        }
+        proof.extend(self.covering(&self.next_closer(name)));
+        proof.extend(self.matching(&wildcard_of(encloser)));
+
+        proof
+    }
+
+    /// An NSEC or NSEC3 record showing the types at `name`, which exists.
+    fn matching(&self, name: &str) -> Vec<Record> {
+        let ttl = self.negative_soa().ttl();
+        let record = match self.nsec3_param() {
+            Some((iterations, salt)) => {
+                let hash = dnssec::nsec3_hash(name, &salt, iterations);
+                Record::NSEC3 {
+                    domain: self.nsec3_owner(&hash),
+                    class: self.soa.class(),
+                    hash_algorithm: 1,
+                    flags: 0,
+                    iterations,
+                    salt,
+                    next_hashed: hash_step(&hash, true),
+                    types: self.types_at(name),
+                    ttl,
+                }
+            }
+            None => Record::NSEC {
+                domain: name.to_string(),
+                class: self.soa.class(),
+                next: format!("\0.{}", name),
+                types: self.nsec_types(name),
+                ttl,
+            },
+        };
+
+        vec![record]
+    }
+
+    /// An NSEC or NSEC3 record covering `name`, which does not exist.
+    fn covering(&self, name: &str) -> Vec<Record> {
+        let ttl = self.negative_soa().ttl();
+
+        let record = match self.nsec3_param() {
+            Some((iterations, salt)) => {
+                let hash = dnssec::nsec3_hash(name, &salt, iterations);
+                Record::NSEC3 {
+                    domain: self.nsec3_owner(&hash_step(&hash, false)),
+                    class: self.soa.class(),
+                    hash_algorithm: 1,
+                    flags: 0,
+                    iterations,
+                    salt,
+                    next_hashed: hash_step(&hash, true),
+                    types: Vec::new(),
+                    ttl,
+                }
+            }
+            None => {
+                // The span runs from just before `name` to just after the
+                // last name below it, moved out to the real neighbours if
+                // either is closer.
+                let encloser = dnssec::parent(name).unwrap_or("");
+                let label = name.split('.').next().unwrap_or("");
+                let before = match label_before(label) {
+                    Some(before) => join(&before, encloser),
+                    None => encloser.to_string(),
+                };
+                let after = join(&format!("{}\0", label), encloser);
+
+                let index = self.sorted.partition_point(|other| canonical_cmp(other, name) == Ordering::Less);
+                let mut owner = before;
+                if let Some(previous) = index.checked_sub(1).map(|i| &self.sorted[i]) {
+                    if canonical_cmp(previous, &owner) == Ordering::Greater {
+                        owner = previous.clone();
+                    }
+                }
+                let mut next = after;
+                if let Some(following) = self.sorted.get(index) {
+                    if canonical_cmp(following, &next) == Ordering::Less {
+                        next = following.clone();
+                    }
+                }
+
+                let types = if self.names.contains(&owner) {
+                    self.nsec_types(&owner)
+                } else {
+                    vec![QueryType::RRSIG, QueryType::NSEC]
+                };
+
+                Record::NSEC {
+                    domain: owner,
+                    class: self.soa.class(),
+                    next,
+                    types,
+                    ttl,
+                }
+            }
+        };
+
+        vec![record]
+    }
+
+    /// The NSEC3 chain through every name with authoritative data, or the
+    /// NSEC chain if the zone is not using NSEC3.
+    fn chain(&self) -> Vec<Record> {
+        let ttl = self.negative_soa().ttl();
+        let class = self.soa.class();
+
+        let Some((iterations, salt)) = self.nsec3_param() else {
+            let nexts = self.sorted.iter().cycle().skip(1);
+            return self
+                .sorted
+                .iter()
+                .zip(nexts)
+                .map(|(name, next)| Record::NSEC {
+                    domain: name.clone(),
+                    class,
+                    next: next.clone(),
+                    types: self.nsec_types(name),
+                    ttl,
+                })
+                .collect();
+        };
+
+        // Hashes sort the same as their base32hex owner names.
+        let mut hashed: Vec<(Vec<u8>, &String)> = self
+            .sorted
+            .iter()
+            .map(|name| (dnssec::nsec3_hash(name, &salt, iterations), name))
+            .collect();
+        hashed.sort();
+
+        let nexts = hashed.iter().cycle().skip(1);
+        hashed
+            .iter()
+            .zip(nexts)
+            .map(|((hash, name), (next, _))| Record::NSEC3 {
+                domain: self.nsec3_owner(hash),
+                class,
+                hash_algorithm: 1,
+                flags: 0,
+                iterations,
+                salt: salt.clone(),
+                next_hashed: next.clone(),
+                types: self.types_at(name),
+                ttl,
+            })
+            .collect()
+    }
+
+    /// The types an NSEC record at `name` lists, itself included.
+    fn nsec_types(&self, name: &str) -> Vec<QueryType> {
+        let mut types = self.types_at(name);
+        types.extend([QueryType::RRSIG, QueryType::NSEC]);
+        types.sort_by_key(|qtype| qtype.to_num());
+        types.dedup();
+
+        types
+    }
+
+    /// The types at `name`, RRSIG included if any of them are signed.
+    fn types_at(&self, name: &str) -> Vec<QueryType> {
+        let mut types: Vec<QueryType> = self.records.get(name).into_iter().flatten().map(Record::qtype).collect();
+        let cut = name != self.origin && types.contains(&QueryType::NS);
+        if types.iter().any(|qtype| !cut || *qtype == QueryType::DS) {
+            types.push(QueryType::RRSIG);
+        }
+        types.sort_by_key(|qtype| qtype.to_num());
+        types.dedup();
+
+        types
+    }
+
+    /// NSEC3 iterations and salt from the NSEC3PARAM record at the apex.
+    /// Zones without one are denied with NSEC.
+    fn nsec3_param(&self) -> Option<(u16, Vec<u8>)> {
+        self.records.get(&self.origin)?.iter().find_map(|record| match record {
+            Record::NSEC3PARAM {
+                hash_algorithm: 1,
+                iterations,
+                salt,
+                ..
+            } => Some((*iterations, salt.clone())),
+            _ => None,
+        })
+    }
+
+    fn nsec3_owner(&self, hash: &[u8]) -> String {
+        join(&base32hex_encode(hash).to_ascii_lowercase(), &self.origin)
+    }
+
+    /// The longest existing ancestor of `name`, which lies in the zone.
+    fn closest_encloser<'a>(&self, name: &'a str) -> &'a str {
+        let mut encloser = name;
+        while !self.names.contains(encloser) && !encloser.is_empty() {
+            encloser = encloser.split_once('.').map_or("", |(_, parent)| parent);
+        }
+
+        encloser
+    }
+
+    /// The ancestor of `name`, or `name` itself, one label below its
+    /// closest encloser.
+    fn next_closer(&self, name: &str) -> String {
+        let encloser = self.closest_encloser(name);
+        let depth = label_count(encloser) + 1;
+        let labels: Vec<&str> = name.split('.').collect();
+
+        labels[labels.len().saturating_sub(depth)..].join(".")
+    }
+}
+
+/// Groups `records` into RRsets by owner and type, in order of appearance.
+fn rrsets(records: &[Record]) -> Vec<Vec<Record>> {
+    let mut sets: Vec<Vec<Record>> = Vec::new();
+    for record in records {
+        let set = sets.iter_mut().find(|set| {
+            set[0].qtype() == record.qtype() && set[0].domain().eq_ignore_ascii_case(record.domain())
+        });
+        match set {
+            Some(set) => set.push(record.clone()),
+            None => sets.push(vec![record.clone()]),
+        }
+    }
+
+    sets
+}
+
+/// The hash one above or below `hash`, wrapping around.
+fn hash_step(hash: &[u8], up: bool) -> Vec<u8> {
+    let mut hash = hash.to_vec();
+    for byte in hash.iter_mut().rev() {
+        let (value, carry) = if up {
+            byte.overflowing_add(1)
+        } else {
+            byte.overflowing_sub(1)
+        };
+        *byte = value;
+        if !carry {
+            break;
+        }
+    }
+
+    hash
+}
+
+/// A label sorting shortly before `label` among its siblings: the last
+/// character lowered by one and followed by the highest printable one.
+/// `None` if the parent name itself has to do.
+fn label_before(label: &str) -> Option<String> {
+    let (last, rest) = label.as_bytes().split_last()?;
+    let lowered = match last.checked_sub(1)? {
+        // Uppercase sorts as lowercase, and dots separate labels.
+        b'A'..=b'Z' => b'@',
+        b'.' => b'-',
+        0 => return (!rest.is_empty()).then(|| String::from_utf8_lossy(rest).into_owned()),
+        byte => byte,
+    };
+
+    let mut before = rest.to_vec();
+    before.push(lowered);
+    if before.len() < 63 {
+        before.push(b'~');
+    }
+
+    Some(String::from_utf8_lossy(&before).into_owned())
+}
+
+fn join(label: &str, name: &str) -> String {
+    if name.is_empty() {
+        label.to_string()
+    } else {
+        format!("{}.{}", label, name)
+    }
+}
+
+/// The zones served authoritatively.
+/// The hashing parameters of a zone denying names with NSEC3
+/// (RFC 5155 section 4).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Nsec3Params {
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+}
+
+impl Nsec3Params {
+    /// The parameters of the NSEC3PARAM record among `records`, or no
+    /// extra iterations and no salt if there is none.
+    pub fn of(records: &[Record]) -> Nsec3Params {
+        records
+            .iter()
+            .find_map(|record| match record {
+                Record::NSEC3PARAM {
+                    iterations, salt, ..
+                } => Some(Nsec3Params {
+                    iterations: *iterations,
+                    salt: salt.clone(),
+                }),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+pub struct Authority {
+    zones: Vec<Zone>,
+}
+
+impl Authority {
+    pub fn new() -> Authority {
+        Authority { zones: Vec::new() }
+    }
+
+    pub fn add(&mut self, zone: Zone) {
+        self.zones.push(zone);
+    }
+
+    /// The most specific zone containing `qname`.
+    pub fn find(&self, qname: &str) -> Option<&Zone> {
+        self.zones
+            .iter()
+            .filter(|zone| is_subdomain(qname, &zone.origin))
+            .max_by_key(|zone| zone.origin.len())
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    pub fn find_mut(&mut self, origin: &str) -> Option<&mut Zone> {
+        let origin = origin.trim_end_matches('.');
+        self.zones.iter_mut().find(|zone| zone.origin.eq_ignore_ascii_case(origin))
+    }
+
+    /// An authoritative answer, or `None` if `qname` is in none of our
+    /// zones. It is signed if `dnssec` is set and the zone has keys.
+    pub fn query(&self, qname: &str, qtype: QueryType, dnssec: bool) -> Option<Packet> {
+        self.find(qname).map(|zone| zone.lookup(qname, qtype, dnssec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryClass;
+    use crate::testing::{a, cname, ns, signing_key, soa, TTL};
+
+    fn txt(domain: &str, text: &str) -> Record {
+        Record::TXT {
+            domain: domain.to_string(),
+            class: QueryClass::IN,
+            data: vec![text.as_bytes().to_vec()],
+            ttl: TTL,
+        }
+    }
+
+    fn mx(domain: &str, host: &str) -> Record {
+        Record::MX {
+            domain: domain.to_string(),
+            class: QueryClass::IN,
+            priority: 10,
+            host: host.to_string(),
+            ttl: TTL,
+        }
+    }
+
+    fn srv(domain: &str, host: &str) -> Record {
+        Record::SRV {
+            domain: domain.to_string(),
+            class: QueryClass::IN,
+            priority: 0,
+            weight: 0,
+            port: 22,
+            host: host.to_string(),
+            ttl: TTL,
+        }
+    }
+
+    /// The example zone of RFC 4592 section 2.2.1.
+    fn rfc4592_zone() -> Zone {
+        let records = vec![
+            soa("example"),
+            ns("example", "ns.example.com"),
+            ns("example", "ns.example.net"),
+            txt("*.example", "this is a wildcard"),
+            mx("*.example", "host1.example"),
+            txt("sub.*.example", "this is not a wildcard"),
+            a("host1.example", "192.0.2.1"),
+            srv("_ssh._tcp.host1.example", "host1.example"),
+            srv("_ssh._tcp.host2.example", "host2.example"),
+            ns("subdel.example", "ns.example.com"),
+            ns("subdel.example", "ns.example.net"),
+        ];
+        Zone::new("example", records).unwrap()
+    }
+
+    #[test]
+    fn wildcard_synthesises_answers() {
+        let zone = rfc4592_zone();
+
+        let packet = zone.lookup("host3.example", QueryType::MX, false);
+        assert_eq!(packet.header.rcode, ResultCode::NOERROR);
+        assert!(packet.header.aa);
+        assert_eq!(packet.answers, vec![mx("host3.example", "host1.example")]);
+        assert_eq!(packet.resources, vec![a("host1.example", "192.0.2.1")]);
+
+        let packet = zone.lookup("foo.bar.example", QueryType::TXT, false);
+        assert_eq!(packet.answers, vec![txt("foo.bar.example", "this is a wildcard")]);
+    }
+
+    #[test]
+    fn wildcard_without_the_type_is_nodata() {
+        let packet = rfc4592_zone().lookup("host3.example", QueryType::A, false);
+        assert_eq!(packet.header.rcode, ResultCode::NOERROR);
+        assert!(packet.answers.is_empty());
+        assert_eq!(packet.authorities.len(), 1);
+        assert_eq!(packet.authorities[0].qtype(), QueryType::SOA);
+    }
+
+    #[test]
+    fn existing_names_block_the_wildcard() {
+        let zone = rfc4592_zone();
+
+        // host1.example exists, so the wildcard MX does not apply to it.
+        let packet = zone.lookup("host1.example", QueryType::MX, false);
+        assert_eq!(packet.header.rcode, ResultCode::NOERROR);
+        assert!(packet.answers.is_empty());
+
+        // So does sub.*.example, whatever the `*` in it.
+        let packet = zone.lookup("sub.*.example", QueryType::MX, false);
+        assert_eq!(packet.header.rcode, ResultCode::NOERROR);
+        assert!(packet.answers.is_empty());
+
+        // The wildcard owner answers for itself.
+        let packet = zone.lookup("*.example", QueryType::TXT, false);
+        assert_eq!(packet.answers, vec![txt("*.example", "this is a wildcard")]);
+    }
+
+    #[test]
+    fn empty_non_terminals_block_the_wildcard() {
+        let zone = rfc4592_zone();
+
+        // _tcp.host1.example is an empty non-terminal and the closest
+        // encloser, and there is no *._tcp.host1.example.
+        let packet = zone.lookup("_telnet._tcp.host1.example", QueryType::SRV, false);
+        assert_eq!(packet.header.rcode, ResultCode::NXDOMAIN);
+        assert!(packet.answers.is_empty());
+        assert_eq!(packet.authorities[0].qtype(), QueryType::SOA);
+
+        // The empty non-terminal itself exists.
+        let packet = zone.lookup("_tcp.host1.example", QueryType::SRV, false);
+        assert_eq!(packet.header.rcode, ResultCode::NOERROR);
+        assert!(packet.answers.is_empty());
+
+        // *.example is the closest encloser, and *.*.example does not exist.
+        let packet = zone.lookup("ghost.*.example", QueryType::MX, false);
+        assert_eq!(packet.header.rcode, ResultCode::NXDOMAIN);
+    }
+
+    #[test]
+    fn delegation_is_not_synthesised() {
+        let packet = rfc4592_zone().lookup("host.subdel.example", QueryType::A, false);
+        assert_eq!(packet.header.rcode, ResultCode::NOERROR);
+        assert!(!packet.header.aa);
+        assert!(packet.answers.is_empty());
+        assert_eq!(
+            packet.authorities,
+            vec![ns("subdel.example", "ns.example.com"), ns("subdel.example", "ns.example.net")]
+        );
+    }
+
+    fn example_zone() -> Zone {
+        let records = vec![
+            soa("example"),
+            ns("example", "ns1.example"),
+            mx("example", "mail.example"),
+            a("ns1.example", "192.0.2.1"),
+            a("mail.example", "192.0.2.2"),
+            cname("www.example", "web.example"),
+            a("web.example", "192.0.2.3"),
+            cname("ext.example", "www.example.org"),
+            cname("loop1.example", "loop2.example"),
+            cname("loop2.example", "loop1.example"),
+            cname("dangling.example", "nothere.example"),
+            a("a.b.c.example", "192.0.2.9"),
+            ns("sub.example", "ns.sub.example"),
+            a("ns.sub.example", "192.0.2.53"),
+        ];
+        Zone::new("Example.", records).unwrap()
+    }
+
+    #[test]
+    fn answers_carry_the_addresses_of_named_hosts() {
+        let zone = example_zone();
+
+        let packet = zone.lookup("EXAMPLE", QueryType::MX, false);
+        assert!(packet.header.aa);
+        assert_eq!(packet.answers, vec![mx("example", "mail.example")]);
+        assert_eq!(packet.resources, vec![a("mail.example", "192.0.2.2")]);
+
+        let packet = zone.lookup("example", QueryType::NS, false);
+        assert_eq!(packet.resources, vec![a("ns1.example", "192.0.2.1")]);
+    }
+
+    #[test]
+    fn cnames_are_followed_within_the_zone() {
+        let zone = example_zone();
+
+        let packet = zone.lookup("www.example", QueryType::A, false);
+        assert_eq!(packet.answers, vec![cname("www.example", "web.example"), a("web.example", "192.0.2.3")]);
+
+        // The rest of the chain is for the client to chase.
+        let packet = zone.lookup("ext.example", QueryType::A, false);
+        assert_eq!(packet.answers, vec![cname("ext.example", "www.example.org")]);
+
+        let packet = zone.lookup("loop1.example", QueryType::A, false);
+        assert_eq!(packet.answers, vec![cname("loop1.example", "loop2.example"), cname("loop2.example", "loop1.example")]);
+
+        let packet = zone.lookup("dangling.example", QueryType::A, false);
+        assert_eq!(packet.header.rcode, ResultCode::NXDOMAIN);
+        assert_eq!(packet.answers.len(), 1);
+
+        // Asking for the CNAME itself does not follow it.
+        let packet = zone.lookup("www.example", QueryType::CNAME, false);
+        assert_eq!(packet.answers, vec![cname("www.example", "web.example")]);
+    }
+
+    #[test]
+    fn negative_answers_carry_the_soa() {
+        let zone = example_zone();
+
+        let packet = zone.lookup("nope.example", QueryType::A, false);
+        assert!(packet.header.aa);
+        assert_eq!(packet.header.rcode, ResultCode::NXDOMAIN);
+        assert_eq!(packet.authorities.len(), 1);
+        // Negative caches keep it for the lesser of its TTL and MINIMUM.
+        assert_eq!(packet.authorities[0].ttl(), 300);
+
+        for (name, qtype) in [("web.example", QueryType::AAAA), ("b.c.example", QueryType::A)] {
+            let packet = zone.lookup(name, qtype, false);
+            assert_eq!(packet.header.rcode, ResultCode::NOERROR, "{}", name);
+            assert!(packet.answers.is_empty());
+            assert_eq!(packet.authorities.len(), 1);
+        }
+    }
+
+    #[test]
+    fn delegations_refer_with_glue() {
+        let zone = example_zone();
+
+        for name in ["sub.example", "host.sub.example", "ns.sub.example"] {
+            let packet = zone.lookup(name, QueryType::A, false);
+            assert!(!packet.header.aa, "{}", name);
+            assert!(packet.answers.is_empty());
+            assert_eq!(packet.authorities, vec![ns("sub.example", "ns.sub.example")]);
+            assert_eq!(packet.resources, vec![a("ns.sub.example", "192.0.2.53")]);
+        }
+
+        // DS records are served from the parent side of the cut.
+        let packet = zone.lookup("sub.example", QueryType::DS, false);
+        assert!(packet.header.aa);
+        assert_eq!(packet.authorities.len(), 1);
+        assert_eq!(packet.authorities[0].qtype(), QueryType::SOA);
+    }
+
+    #[test]
+    fn inconsistent_zones_are_refused() {
+        let cases = [
+            (vec![soa("example"), a("www.example.org", "192.0.2.1")], "outside the zone"),
+            (vec![a("www.example", "192.0.2.1")], "no SOA"),
+            (vec![soa("example"), soa("example")], "more than one SOA"),
+            (vec![soa("example"), cname("x.example", "y.example"), a("x.example", "192.0.2.1")], "other data"),
+        ];
+        for (records, needle) in cases {
+            let e = Zone::new("example", records).err().unwrap();
+            assert!(e.to_string().contains(needle), "{}", e);
+        }
+    }
+
+    #[test]
+    fn queries_go_to_the_closest_zone() {
+        let mut authority = Authority::new();
+        authority.add(example_zone());
+        authority.add(Zone::new("sub.example", vec![soa("sub.example")]).unwrap());
+
+        assert_eq!(authority.find("www.example").unwrap().origin, "example");
+        assert_eq!(authority.find("host.SUB.example").unwrap().origin, "sub.example");
+        assert!(authority.find("example.com").is_none());
+        assert!(authority.query("example.com", QueryType::A, false).is_none());
+    }
+
+    /// `zone` signed with a key signing key and a zone signing key, whose
+    /// DNSKEY records are returned along with it.
+    fn signed(mut zone: Zone) -> (Zone, Record, Record) {
+        let (ksk, zsk) = (signing_key("example", true), signing_key("example", false));
+        let (ksk_dnskey, zsk_dnskey) = (ksk.dnskey.clone(), zsk.dnskey.clone());
+        zone.add_key(ksk);
+        zone.add_key(zsk);
+        (zone, ksk_dnskey, zsk_dnskey)
+    }
+
+    /// Checks that every RRset in `records` but `unsigned` ones carries a
+    /// valid signature by `dnskey`, and returns the records without them.
+    fn verified(records: &[Record], dnskey: &Record, unsigned: &[QueryType]) -> Vec<Record> {
+        let data: Vec<Record> = records.iter().filter(|record| record.qtype() != QueryType::RRSIG).cloned().collect();
+        for rrset in rrsets(&data) {
+            let owner = rrset[0].domain();
+            let rtype = rrset[0].qtype();
+            let signed = records.iter().any(|rrsig| {
+                matches!(rrsig, Record::RRSIG { domain, type_covered, .. } if domain == owner && *type_covered == rtype)
+                    && dnssec::verify(rrsig, dnskey, &rrset)
+            });
+            assert_eq!(signed, !unsigned.contains(&rtype), "{} {:?} in {:?}", owner, rtype, records);
+        }
+
+        data
+    }
+
+    #[test]
+    fn answers_are_signed_only_for_dnssec_clients() {
+        let (zone, ksk, zsk) = signed(example_zone());
+
+        let packet = zone.lookup("www.example", QueryType::A, true);
+        let answers = verified(&packet.answers, &zsk, &[]);
+        assert_eq!(answers, vec![cname("www.example", "web.example"), a("web.example", "192.0.2.3")]);
+
+        let packet = zone.lookup("www.example", QueryType::A, false);
+        assert!(packet.answers.iter().all(|record| record.qtype() != QueryType::RRSIG));
+
+        // The key signing key signs the DNSKEY RRset, which holds both.
+        let packet = zone.lookup("example", QueryType::DNSKEY, true);
+        assert_eq!(verified(&packet.answers, &ksk, &[]), vec![ksk.clone(), zsk.clone()]);
+        assert_eq!(packet.answers.len(), 3);
+
+        let ds = zone.ds_records();
+        assert_eq!(ds.len(), 1);
+        assert!(dnssec::ds_matches(&ds[0], &ksk));
+
+        // Unsigned zones answer the same either way.
+        let unsigned = example_zone();
+        assert_eq!(unsigned.lookup("www.example", QueryType::A, true).answers.len(), 2);
+    }
+
+    #[test]
+    fn nsec_denials_cover_only_the_name_asked() {
+        let (zone, _, zsk) = signed(example_zone());
+
+        let packet = zone.lookup("nope.example", QueryType::A, true);
+        assert_eq!(packet.header.rcode, ResultCode::NXDOMAIN);
+        let denial = verified(&packet.authorities, &zsk, &[]);
+        assert_eq!(dnssec::prove_nxdomain(&denial, "nope.example"), dnssec::Proof::Proven);
+        // The neighbours, mail.example and ns1.example, are not given away.
+        for record in &denial {
+            if let Record::NSEC { domain, next, .. } = record {
+                assert_ne!(domain, "mail.example");
+                assert_ne!(next, "ns1.example");
+            }
+        }
+
+        let packet = zone.lookup("web.example", QueryType::AAAA, true);
+        let denial = verified(&packet.authorities, &zsk, &[]);
+        assert_eq!(dnssec::prove_nodata(&denial, "web.example", QueryType::AAAA), dnssec::Proof::Proven);
+
+        // b.c.example only exists as the parent of a.b.c.example.
+        let packet = zone.lookup("b.c.example", QueryType::A, true);
+        let denial = verified(&packet.authorities, &zsk, &[]);
+        assert_eq!(dnssec::prove_nodata(&denial, "b.c.example", QueryType::A), dnssec::Proof::Proven);
+    }
+
+    #[test]
+    fn unsigned_delegations_are_proven_so() {
+        let (zone, _, zsk) = signed(example_zone());
+
+        let packet = zone.lookup("host.sub.example", QueryType::A, true);
+        assert!(!packet.header.aa);
+        // Neither the NS records at the cut nor the glue are signed.
+        let authorities = verified(&packet.authorities, &zsk, &[QueryType::NS]);
+        assert!(dnssec::proves_insecure_delegation(&authorities, "sub.example"));
+        verified(&packet.resources, &zsk, &[QueryType::A]);
+    }
+
+    #[test]
+    fn wildcard_answers_are_signed_as_the_wildcard() {
+        let (zone, _, zsk) = signed(rfc4592_zone());
+
+        let packet = zone.lookup("host3.example", QueryType::MX, true);
+        let answers = verified(&packet.answers, &zsk, &[]);
+        assert_eq!(answers, vec![mx("host3.example", "host1.example")]);
+        let labels = packet.answers.iter().find_map(|record| match record {
+            Record::RRSIG { labels, .. } => Some(*labels as usize),
+            _ => None,
+        });
+        assert_eq!(labels, Some(1));
+
+        // Along with proof that host3.example itself does not exist.
+        let denial = verified(&packet.authorities, &zsk, &[]);
+        assert_eq!(dnssec::prove_wildcard_expansion(&denial, "host3.example", 1), dnssec::Proof::Proven);
+    }
+
+    #[test]
+    fn nsec3_denials_follow_the_zone_parameters() {
+        let mut records = vec![soa("example"), ns("example", "ns1.example"), a("ns1.example", "192.0.2.1")];
+        records.push(Record::NSEC3PARAM {
+            domain: "example".to_string(),
+            class: QueryClass::IN,
+            hash_algorithm: 1,
+            flags: 0,
+            iterations: 5,
+            salt: vec![0xAA, 0xBB, 0xCC, 0xDD],
+            ttl: 0,
+        });
+        let (zone, _, zsk) = signed(Zone::new("example", records).unwrap());
+
+        let packet = zone.lookup("nope.example", QueryType::A, true);
+        assert_eq!(packet.header.rcode, ResultCode::NXDOMAIN);
+        let denial = verified(&packet.authorities, &zsk, &[]);
+        assert!(denial.iter().any(|record| matches!(record, Record::NSEC3 { iterations: 5, .. })));
+        assert!(denial.iter().all(|record| record.qtype() != QueryType::NSEC));
+        assert_eq!(dnssec::prove_nxdomain(&denial, "nope.example"), dnssec::Proof::Proven);
+
+        let packet = zone.lookup("ns1.example", QueryType::MX, true);
+        let denial = verified(&packet.authorities, &zsk, &[]);
+        assert_eq!(dnssec::prove_nodata(&denial, "ns1.example", QueryType::MX), dnssec::Proof::Proven);
+    }
+
+    /// Checks that the NSEC3 records of a signed zone hash every name in
+    /// `names` and link into a single loop.
+    fn assert_nsec3_chain(signed: &[Record], names: &[&str]) {
+        let chain: Vec<(&str, &Vec<u8>)> = signed
+            .iter()
+            .filter_map(|record| match record {
+                Record::NSEC3 { domain, next_hashed, .. } => Some((domain.as_str(), next_hashed)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(chain.len(), names.len());
+
+        let owners: Vec<String> = chain.iter().map(|(owner, _)| owner.to_string()).collect();
+        for name in names {
+            let hash = base32hex_encode(&dnssec::nsec3_hash(name, &[0xAA, 0xBB], 2)).to_ascii_lowercase();
+            assert!(owners.contains(&format!("{}.example", hash)), "{} is not in the chain", name);
+        }
+
+        let mut owner = &owners[0];
+        for _ in 0..chain.len() {
+            let (_, next) = chain.iter().find(|(domain, _)| domain == owner).unwrap();
+            let next = format!("{}.example", base32hex_encode(next).to_ascii_lowercase());
+            owner = owners.iter().find(|other| **other == next).unwrap();
+        }
+        assert_eq!(owner, &owners[0]);
+    }
+
+    #[test]
+    fn signed_zones_are_complete_and_reproducible() {
+        let mut records = example_zone().records.into_values().flatten().collect::<Vec<Record>>();
+        records.push(Record::NSEC3PARAM {
+            domain: "example".to_string(),
+            class: QueryClass::IN,
+            hash_algorithm: 1,
+            flags: 0,
+            iterations: 2,
+            salt: vec![0xAA, 0xBB],
+            ttl: 0,
+        });
+        let (zone, ksk, zsk) = signed(Zone::new("example", records).unwrap());
+
+        // Ed25519 signatures depend on nothing but the key and the data.
+        let signed = zone.sign(1_700_000_000, 1_800_000_000);
+        assert_eq!(zone.sign(1_700_000_000, 1_800_000_000), signed);
+        assert!(signed.windows(2).all(|pair| dnssec::canonical_order(&pair[0], &pair[1]) != Ordering::Greater));
+
+        // Glue and the NS records at the cut stay unsigned, the DNSKEY
+        // RRset is signed by the key signing key and the rest by the other.
+        let (keys, rest): (Vec<Record>, Vec<Record>) = signed.iter().cloned().partition(|record| {
+            record.qtype() == QueryType::DNSKEY
+                || matches!(record, Record::RRSIG { type_covered: QueryType::DNSKEY, .. })
+        });
+        verified(&keys, &ksk, &[]);
+        let (delegated, rest): (Vec<Record>, Vec<Record>) =
+            rest.into_iter().partition(|record| is_subdomain(record.domain(), "sub.example"));
+        verified(&delegated, &zsk, &[QueryType::NS, QueryType::A]);
+        let data = verified(&rest, &zsk, &[]);
+        assert!(data.iter().all(|record| record.qtype() != QueryType::NSEC));
+
+        // Every name with authoritative data is in the chain, as are the
+        // empty non-terminals and the cut, but not the glue below it.
+        assert_nsec3_chain(
+            &data,
+            &[
+                "example",
+                "ns1.example",
+                "mail.example",
+                "www.example",
+                "web.example",
+                "ext.example",
+                "loop1.example",
+                "loop2.example",
+                "dangling.example",
+                "a.b.c.example",
+                "b.c.example",
+                "c.example",
+                "sub.example",
+            ],
+        );
+    }
+
+    #[test]
+    fn zones_without_nsec3_get_an_nsec_chain() {
+        let (zone, _, zsk) = signed(rfc4592_zone());
+        let signed = zone.sign(1_700_000_000, 1_800_000_000);
+
+        // The NS records at the cut are left unsigned, the NSEC record
+        // there is not.
+        let (cut, rest): (Vec<Record>, Vec<Record>) =
+            signed.into_iter().partition(|record| record.domain() == "subdel.example");
+        let mut data = verified(&cut, &zsk, &[QueryType::NS]);
+        // The DNSKEY RRset is signed by the key signing key alone.
+        data.extend(verified(&rest, &zsk, &[QueryType::DNSKEY]));
+        data.sort_by(dnssec::canonical_order);
+
+        let chain: Vec<(&str, &str)> = data
+            .iter()
+            .filter_map(|record| match record {
+                Record::NSEC { domain, next, .. } => Some((domain.as_str(), next.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            chain,
+            [
+                ("example", "*.example"),
+                ("*.example", "sub.*.example"),
+                ("sub.*.example", "host1.example"),
+                ("host1.example", "_tcp.host1.example"),
+                ("_tcp.host1.example", "_ssh._tcp.host1.example"),
+                ("_ssh._tcp.host1.example", "host2.example"),
+                ("host2.example", "_tcp.host2.example"),
+                ("_tcp.host2.example", "_ssh._tcp.host2.example"),
+                ("_ssh._tcp.host2.example", "subdel.example"),
+                ("subdel.example", "example"),
+            ]
+        );
+    }
+
+    #[test]
+    fn resigning_replaces_the_old_signatures() {
+        use ring::rand::SystemRandom;
+        use ring::signature::Ed25519KeyPair;
+
+        let rng = SystemRandom::new();
+        let documents = [Ed25519KeyPair::generate_pkcs8(&rng).unwrap(), Ed25519KeyPair::generate_pkcs8(&rng).unwrap()];
+        let keys = || -> Vec<SigningKey> {
+            vec![
+                SigningKey::from_pkcs8("example", documents[0].as_ref(), true).unwrap(),
+                SigningKey::from_pkcs8("example", documents[1].as_ref(), false).unwrap(),
+            ]
+        };
+        let serial = |records: &[Record]| {
+            records.iter().find_map(|record| match record {
+                Record::SOA { serial, .. } => Some(*serial),
+                _ => None,
+            })
+        };
+        let nsec3 = Nsec3Params {
+            iterations: 5,
+            salt: vec![0xAA, 0xBB],
+        };
+        let records = example_zone().records.into_values().flatten().collect::<Vec<Record>>();
+        let resign = |records: Vec<Record>, nsec3: Nsec3Params| {
+            Zone::resign("Example.", records, keys(), nsec3, Some(1_700_000_000), Some(1_800_000_000)).unwrap()
+        };
+
+        // The same records, keys and times give the same zone.
+        let (signed, ds) = resign(records.clone(), nsec3.clone());
+        assert_eq!(resign(records.clone(), nsec3.clone()), (signed.clone(), ds.clone()));
+        assert_eq!(serial(&signed), Some(2));
+        assert_eq!(Nsec3Params::of(&signed), nsec3);
+        assert!(signed.iter().any(|record| matches!(record, Record::NSEC3PARAM { domain, .. } if domain == "example")));
+        assert_eq!(ds.len(), 1);
+        assert!(dnssec::ds_matches(&ds[0], &keys()[0].dnskey));
+
+        // Signing the signed zone again replaces its signatures and chain,
+        // here with other NSEC3 parameters.
+        let (resigned, _) = resign(signed.clone(), Nsec3Params::default());
+        assert_eq!(serial(&resigned), Some(3));
+        assert_eq!(resigned.len(), signed.len());
+        assert_eq!(Nsec3Params::of(&resigned), Nsec3Params::default());
+        let (again, _) = resign(records, Nsec3Params::default());
+
+        // Only the serial tells it from signing the original records.
+        let without_soa = |records: Vec<Record>| -> Vec<Record> {
+            records
+                .into_iter()
+                .filter(|record| {
+                    !matches!(record, Record::SOA { .. } | Record::RRSIG { type_covered: QueryType::SOA, .. })
+                })
+                .collect()
+        };
+        assert_eq!(without_soa(resigned), without_soa(again));
+    }
+}
@@ -0,0 +1,826 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::buffer::{PacketBuffer, SlicePacketBuffer, VectorPacketBuffer};
+use crate::encoding::{base32hex_decode, base64_decode, hex_decode, parse_timestamp};
+use crate::error::Result as WireResult;
+use crate::query::{QueryClass, QueryType};
+use crate::record::Record;
+
+/// How deeply `$INCLUDE` directives may nest.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Reads the records of an RFC 1035 master file. Relative names are taken
+/// relative to `origin`, with or without its trailing dot, until a
+/// `$ORIGIN` directive changes it.
+pub fn load(path: &Path, origin: &str) -> Result<Vec<Record>> {
+    let mut parser = Parser {
+        records: Vec::new(),
+        default_ttl: None,
+        last_ttl: None,
+    };
+    parser.parse_file(path, &origin.trim_end_matches('.').to_ascii_lowercase(), 0)?;
+
+    Ok(parser.records)
+}
+
+/// One field of an entry. Quoted fields are flagged so that `"@"` or
+/// `"$TTL"` keep their literal meaning.
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+/// A record or directive, with lines continued by parentheses joined up.
+struct Entry {
+    line: usize,
+    /// The entry starts with blank space, so it reuses the previous owner.
+    blank_owner: bool,
+    tokens: Vec<Token>,
+}
+
+fn error(path: &Path, line: usize, msg: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), line, msg))
+}
+
+/// Splits master file text into entries, dropping comments.
+fn tokenize(text: &str, path: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut depth = 0;
+    let mut open_line = 0;
+    let mut at_line_start = true;
+
+    while let Some(c) = chars.next() {
+        if at_line_start && depth == 0 {
+            entries.extend(current.take().filter(|entry| !entry.tokens.is_empty()));
+            current = Some(Entry {
+                line,
+                blank_owner: c == ' ' || c == '\t',
+                tokens: Vec::new(),
+            });
+        }
+        at_line_start = false;
+        let tokens = &mut current.as_mut().expect("entry started above").tokens;
+
+        match c {
+            '\n' => {
+                line += 1;
+                at_line_start = true;
+            }
+            ' ' | '\t' | '\r' => {}
+            ';' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '(' => {
+                if depth == 0 {
+                    open_line = line;
+                }
+                depth += 1;
+            }
+            ')' => {
+                if depth == 0 {
+                    return Err(error(path, line, "unbalanced ')'"));
+                }
+                depth -= 1;
+            }
+            '"' => {
+                let start = line;
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            text.push('\\');
+                            text.extend(chars.next());
+                        }
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            text.push(c);
+                        }
+                        None => return Err(error(path, start, "unterminated quoted string")),
+                    }
+                }
+                tokens.push(Token { text, quoted: true });
+            }
+            _ => {
+                let mut text = String::from(c);
+                if c == '\\' {
+                    text.extend(chars.next());
+                }
+                while let Some(c) = chars.next_if(|c| !" \t\r\n;()\"".contains(*c)) {
+                    text.push(c);
+                    if c == '\\' {
+                        text.extend(chars.next());
+                    }
+                }
+                tokens.push(Token { text, quoted: false });
+            }
+        }
+    }
+
+    if depth > 0 {
+        return Err(error(path, open_line, "unbalanced '('"));
+    }
+    entries.extend(current.filter(|entry| !entry.tokens.is_empty()));
+
+    Ok(entries)
+}
+
+struct Parser {
+    records: Vec<Record>,
+    /// Set by `$TTL` (RFC 2308).
+    default_ttl: Option<u32>,
+    /// TTL of the previous record, used when neither is given.
+    last_ttl: Option<u32>,
+}
+
+impl Parser {
+    fn parse_file(&mut self, path: &Path, origin: &str, depth: usize) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+        // $ORIGIN only holds until the end of the file it appears in.
+        let mut origin = origin.to_string();
+        let mut owner: Option<String> = None;
+        let mut class: Option<QueryClass> = None;
+
+        for entry in tokenize(&text, path)? {
+            let err = |msg: String| error(path, entry.line, msg);
+            let first = &entry.tokens[0];
+
+            if !entry.blank_owner && !first.quoted && first.text.starts_with('$') {
+                let arg = |i: usize| {
+                    entry
+                        .tokens
+                        .get(i)
+                        .ok_or_else(|| err(format!("{} needs an argument", first.text)))
+                };
+
+                match first.text.to_ascii_uppercase().as_str() {
+                    "$ORIGIN" => origin = absolute_name(arg(1)?, &origin).map_err(err)?,
+                    "$TTL" => self.default_ttl = Some(parse_ttl(&arg(1)?.text).map_err(err)?),
+                    "$INCLUDE" => {
+                        if depth >= MAX_INCLUDE_DEPTH {
+                            return Err(err("$INCLUDE nested too deeply".to_string()));
+                        }
+                        let file = path.parent().unwrap_or(Path::new("")).join(&arg(1)?.text);
+                        let include_origin = match entry.tokens.get(2) {
+                            Some(token) => absolute_name(token, &origin).map_err(err)?,
+                            None => origin.clone(),
+                        };
+                        self.parse_file(&file, &include_origin, depth + 1)?;
+                    }
+                    _ => return Err(err(format!("unknown directive {}", first.text))),
+                }
+                continue;
+            }
+
+            let mut tokens = entry.tokens.iter();
+            if !entry.blank_owner {
+                let name = tokens.next().expect("entries are never empty");
+                owner = Some(absolute_name(name, &origin).map_err(err)?);
+            }
+            let domain = owner.clone().ok_or_else(|| err("record without an owner".to_string()))?;
+
+            // TTL and class are both optional and may come in either order.
+            let mut ttl = None;
+            let mut record_class = None;
+            let qtype = loop {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| err("missing record type".to_string()))?;
+                if ttl.is_none() && token.text.starts_with(|c: char| c.is_ascii_digit()) {
+                    ttl = Some(parse_ttl(&token.text).map_err(err)?);
+                    continue;
+                }
+                if record_class.is_none() {
+                    if let Some(parsed) = QueryClass::from_name(&token.text) {
+                        record_class = Some(parsed);
+                        continue;
+                    }
+                }
+                break QueryType::from_name(&token.text)
+                    .ok_or_else(|| err(format!("unknown record type {}", token.text)))?;
+            };
+
+            let record_class = record_class.or(class).unwrap_or(QueryClass::IN);
+            class = Some(record_class);
+
+            let args: Vec<&Token> = tokens.collect();
+            let mut record = parse_rdata(domain, record_class, qtype, &args, &origin).map_err(err)?;
+
+            let ttl = match ttl.or(self.default_ttl).or(self.last_ttl) {
+                Some(ttl) => ttl,
+                // Files predating $TTL take the SOA MINIMUM as the default.
+                None => match record {
+                    Record::SOA { minimum, .. } => minimum,
+                    _ => return Err(err("no TTL given and no $TTL in effect".to_string())),
+                },
+            };
+            record.set_ttl(ttl);
+            self.last_ttl = Some(ttl);
+
+            self.records.push(record);
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves `@` and relative names against `origin`. Names are stored
+/// lowercase and without the trailing dot.
+fn absolute_name(token: &Token, origin: &str) -> std::result::Result<String, String> {
+    let text = token.text.as_str();
+    if text.contains('\\') {
+        return Err(format!("escaped characters in names are not supported: {}", text));
+    }
+
+    let name = if text == "@" && !token.quoted {
+        origin.to_string()
+    } else if let Some(absolute) = text.strip_suffix('.') {
+        absolute.to_string()
+    } else if origin.is_empty() {
+        text.to_string()
+    } else {
+        format!("{}.{}", text, origin)
+    };
+
+    let mut len = 1;
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        if label.len() > 63 {
+            return Err(format!("label exceeds 63 bytes in {}", text));
+        }
+        len += label.len() + 1;
+    }
+    if len > 255 {
+        return Err(format!("name exceeds 255 bytes: {}", text));
+    }
+
+    Ok(name.to_ascii_lowercase())
+}
+
+/// Parses a TTL given in seconds or with BIND style units, e.g. `1h30m`.
+fn parse_ttl(text: &str) -> std::result::Result<u32, String> {
+    if let Ok(ttl) = text.parse() {
+        return Ok(ttl);
+    }
+
+    let invalid = || format!("invalid TTL {}", text);
+    let mut total: u32 = 0;
+    let mut value: Option<u32> = None;
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = Some(
+                value
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(digit))
+                    .ok_or_else(invalid)?,
+            );
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return Err(invalid()),
+        };
+        total = value
+            .take()
+            .and_then(|v| v.checked_mul(unit))
+            .and_then(|v| total.checked_add(v))
+            .ok_or_else(invalid)?;
+    }
+
+    // A trailing number without a unit is seconds.
+    total.checked_add(value.unwrap_or(0)).ok_or_else(invalid)
+}
+
+fn number<T: FromStr>(token: &Token) -> std::result::Result<T, String> {
+    token
+        .text
+        .parse()
+        .map_err(|_| format!("invalid number {}", token.text))
+}
+
+/// Decodes `\X` and `\DDD` escapes (RFC 1035 section 5.1).
+fn unescape(text: &str) -> std::result::Result<Vec<u8>, String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let digits = bytes.get(i + 1..i + 4).filter(|d| d.iter().all(u8::is_ascii_digit));
+        match (digits, bytes.get(i + 1)) {
+            (Some(digits), _) => {
+                let value = digits.iter().fold(0u32, |v, d| v * 10 + (d - b'0') as u32);
+                out.push(u8::try_from(value).map_err(|_| format!("invalid escape in {}", text))?);
+                i += 4;
+            }
+            (None, Some(&b)) => {
+                out.push(b);
+                i += 2;
+            }
+            (None, None) => return Err(format!("dangling backslash in {}", text)),
+        }
+    }
+
+    Ok(out)
+}
+
+fn character_string(token: &Token) -> std::result::Result<Vec<u8>, String> {
+    let data = unescape(&token.text)?;
+    if data.len() > 255 {
+        return Err(format!("character-string exceeds 255 bytes: {}", token.text));
+    }
+
+    Ok(data)
+}
+
+fn parse_rdata(
+    domain: String,
+    class: QueryClass,
+    qtype: QueryType,
+    args: &[&Token],
+    origin: &str,
+) -> std::result::Result<Record, String> {
+    if args.first().is_some_and(|token| token.text == "\\#" && !token.quoted) {
+        return parse_generic_rdata(domain, class, qtype, &args[1..]);
+    }
+
+    let arg = |i: usize| {
+        args.get(i)
+            .copied()
+            .ok_or_else(|| format!("too few fields for {} record", qtype))
+    };
+    let expected = match qtype {
+        QueryType::A
+        | QueryType::AAAA
+        | QueryType::NS
+        | QueryType::CNAME
+        | QueryType::PTR => Some(1),
+        QueryType::MX => Some(2),
+        QueryType::CAA => Some(3),
+        QueryType::SRV => Some(4),
+        QueryType::NSEC3PARAM => Some(4),
+        QueryType::SOA => Some(7),
+        // Keys, digests and signatures may be split into several fields.
+        QueryType::TXT
+        | QueryType::DS
+        | QueryType::RRSIG
+        | QueryType::NSEC
+        | QueryType::DNSKEY
+        | QueryType::NSEC3 => None,
+        QueryType::OPT => return Err("OPT records cannot appear in zone files".to_string()),
+        QueryType::UNKNOWN(_) => {
+            return Err(format!("{} records need the \\# generic format", qtype))
+        }
+    };
+    if let Some(expected) = expected {
+        if args.len() != expected {
+            return Err(format!(
+                "{} record takes {} fields, found {}",
+                qtype,
+                expected,
+                args.len()
+            ));
+        }
+    }
+
+    let ttl = 0;
+    let record = match qtype {
+        QueryType::A => Record::A {
+            domain,
+            class,
+            addr: Ipv4Addr::from_str(&arg(0)?.text)
+                .map_err(|_| format!("invalid IPv4 address {}", args[0].text))?,
+            ttl,
+        },
+        QueryType::AAAA => Record::AAAA {
+            domain,
+            class,
+            addr: Ipv6Addr::from_str(&arg(0)?.text)
+                .map_err(|_| format!("invalid IPv6 address {}", args[0].text))?,
+            ttl,
+        },
+        QueryType::NS => Record::NS {
+            domain,
+            class,
+            host: absolute_name(arg(0)?, origin)?,
+            ttl,
+        },
+        QueryType::CNAME => Record::CNAME {
+            domain,
+            class,
+            host: absolute_name(arg(0)?, origin)?,
+            ttl,
+        },
+        QueryType::PTR => Record::PTR {
+            domain,
+            class,
+            host: absolute_name(arg(0)?, origin)?,
+            ttl,
+        },
+        QueryType::MX => Record::MX {
+            domain,
+            class,
+            priority: number(arg(0)?)?,
+            host: absolute_name(arg(1)?, origin)?,
+            ttl,
+        },
+        QueryType::SOA => Record::SOA {
+            domain,
+            class,
+            mname: absolute_name(arg(0)?, origin)?,
+            rname: absolute_name(arg(1)?, origin)?,
+            serial: number(arg(2)?)?,
+            refresh: parse_ttl(&arg(3)?.text)?,
+            retry: parse_ttl(&arg(4)?.text)?,
+            expire: parse_ttl(&arg(5)?.text)?,
+            minimum: parse_ttl(&arg(6)?.text)?,
+            ttl,
+        },
+        QueryType::TXT => {
+            if args.is_empty() {
+                return Err("TXT record needs at least one string".to_string());
+            }
+            Record::TXT {
+                domain,
+                class,
+                data: args
+                    .iter()
+                    .map(|token| character_string(token))
+                    .collect::<std::result::Result<_, _>>()?,
+                ttl,
+            }
+        }
+        QueryType::SRV => Record::SRV {
+            domain,
+            class,
+            priority: number(arg(0)?)?,
+            weight: number(arg(1)?)?,
+            port: number(arg(2)?)?,
+            host: absolute_name(arg(3)?, origin)?,
+            ttl,
+        },
+        QueryType::CAA => {
+            let tag = &arg(1)?.text;
+            if tag.is_empty() || !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("invalid CAA tag {}", tag));
+            }
+            Record::CAA {
+                domain,
+                class,
+                flags: number(arg(0)?)?,
+                tag: tag.to_ascii_lowercase(),
+                value: unescape(&arg(2)?.text)?,
+                ttl,
+            }
+        }
+        QueryType::DS => {
+            arg(3)?;
+            Record::DS {
+                domain,
+                class,
+                key_tag: number(args[0])?,
+                algorithm: number(args[1])?,
+                digest_type: number(args[2])?,
+                digest: hex_decode(&joined(&args[3..])).ok_or("invalid hex in DS digest")?,
+                ttl,
+            }
+        }
+        QueryType::RRSIG => {
+            arg(8)?;
+            let time = |token: &Token| {
+                parse_timestamp(&token.text).ok_or_else(|| format!("invalid time {}", token.text))
+            };
+            Record::RRSIG {
+                domain,
+                class,
+                type_covered: QueryType::from_name(&args[0].text)
+                    .ok_or_else(|| format!("unknown type {}", args[0].text))?,
+                algorithm: number(args[1])?,
+                labels: number(args[2])?,
+                original_ttl: parse_ttl(&args[3].text)?,
+                expiration: time(args[4])?,
+                inception: time(args[5])?,
+                key_tag: number(args[6])?,
+                signer: absolute_name(args[7], origin)?,
+                signature: base64_decode(&joined(&args[8..])).ok_or("invalid base64 in RRSIG signature")?,
+                ttl,
+            }
+        }
+        QueryType::NSEC => Record::NSEC {
+            domain,
+            class,
+            next: absolute_name(arg(0)?, origin)?,
+            types: type_list(&args[1..])?,
+            ttl,
+        },
+        QueryType::DNSKEY => {
+            arg(3)?;
+            Record::DNSKEY {
+                domain,
+                class,
+                flags: number(args[0])?,
+                protocol: number(args[1])?,
+                algorithm: number(args[2])?,
+                public_key: base64_decode(&joined(&args[3..])).ok_or("invalid base64 in DNSKEY key")?,
+                ttl,
+            }
+        }
+        QueryType::NSEC3 => {
+            let next_hashed = base32hex_decode(&arg(4)?.text)
+                .filter(|hash| !hash.is_empty())
+                .ok_or_else(|| format!("invalid hashed name {}", args[4].text))?;
+            Record::NSEC3 {
+                domain,
+                class,
+                hash_algorithm: number(args[0])?,
+                flags: number(args[1])?,
+                iterations: number(args[2])?,
+                salt: salt(args[3])?,
+                next_hashed,
+                types: type_list(&args[5..])?,
+                ttl,
+            }
+        }
+        QueryType::NSEC3PARAM => Record::NSEC3PARAM {
+            domain,
+            class,
+            hash_algorithm: number(arg(0)?)?,
+            flags: number(arg(1)?)?,
+            iterations: number(arg(2)?)?,
+            salt: salt(arg(3)?)?,
+            ttl,
+        },
+        QueryType::OPT | QueryType::UNKNOWN(_) => unreachable!("rejected above"),
+    };
+
+    Ok(record)
+}
+
+/// Fields that are split for readability, such as base64 keys, rejoined.
+fn joined(args: &[&Token]) -> String {
+    args.iter().map(|token| token.text.as_str()).collect()
+}
+
+/// The type mnemonics listed in NSEC and NSEC3 records, in the numeric
+/// order the wire format's bitmap puts them in.
+fn type_list(args: &[&Token]) -> std::result::Result<Vec<QueryType>, String> {
+    let mut types = args
+        .iter()
+        .map(|token| QueryType::from_name(&token.text).ok_or_else(|| format!("unknown type {}", token.text)))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    types.sort_by_key(|qtype| qtype.to_num());
+    types.dedup();
+
+    Ok(types)
+}
+
+/// An NSEC3 salt in hex, where `-` stands for none.
+fn salt(token: &Token) -> std::result::Result<Vec<u8>, String> {
+    if token.text == "-" {
+        return Ok(Vec::new());
+    }
+
+    hex_decode(&token.text)
+        .filter(|salt| salt.len() <= 255)
+        .ok_or_else(|| format!("invalid salt {}", token.text))
+}
+
+/// Parses the `\# <length> <hex>` form of RFC 3597 section 5. Known types
+/// are decoded from the wire format so they end up as their own variant.
+fn parse_generic_rdata(
+    domain: String,
+    class: QueryClass,
+    qtype: QueryType,
+    args: &[&Token],
+) -> std::result::Result<Record, String> {
+    let (len, hex) = match args.split_first() {
+        Some((len, hex)) => (number::<usize>(len)?, hex),
+        None => return Err("\\# needs a length".to_string()),
+    };
+
+    let hex = joined(hex);
+    let data = hex_decode(&hex).ok_or_else(|| format!("invalid hex RDATA {}", hex))?;
+    if data.len() != len {
+        return Err(format!("RDATA is {} bytes but {} were declared", data.len(), len));
+    }
+
+    if let QueryType::UNKNOWN(qtype) = qtype {
+        return Ok(Record::UNKNOWN {
+            domain,
+            class,
+            qtype,
+            data,
+            ttl: 0,
+        });
+    }
+    if qtype == QueryType::OPT {
+        return Err("OPT records cannot appear in zone files".to_string());
+    }
+
+    let wire = wire_record(&domain, class, qtype, &data).map_err(|e| e.to_string())?;
+    Record::read(&mut SlicePacketBuffer::new(&wire)).map_err(|e| e.to_string())
+}
+
+/// The record in wire format, uncompressed, with a zero TTL.
+fn wire_record(domain: &str, class: QueryClass, qtype: QueryType, data: &[u8]) -> WireResult<Vec<u8>> {
+    let mut buffer = VectorPacketBuffer::new();
+    buffer.write_uncompressed_qname(domain)?;
+    buffer.write_u16(qtype.to_num())?;
+    buffer.write_u16(class.to_num())?;
+    buffer.write_u32(0)?;
+    buffer.write_u16(data.len() as u16)?;
+    for b in data {
+        buffer.write_u8(*b)?;
+    }
+
+    Ok(buffer.buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use std::path::PathBuf;
+
+    /// Writes `files` to a directory of the test's own and returns the
+    /// path of the first.
+    fn write(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = temp_dir(test);
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    fn parse(test: &str, text: &str) -> Result<Vec<Record>> {
+        load(&write(test, &[("zone", text)]), "example.com")
+    }
+
+    #[test]
+    fn reads_rfc_1035_master_files() {
+        let records = parse(
+            "master",
+            r#"
+$ORIGIN Example.COM.
+$TTL 1h
+@   IN  SOA ns1 hostmaster.example.com. (
+        2024010101 ; serial
+        2h 15M 2w
+        300 )  ; minimum
+    IN  NS  ns1
+    NS  ns2.example.net.
+    IN 60 MX 10 mail
+ns1     A   192.0.2.1
+mail    A   192.0.2.2
+        AAAA 2001:db8::2
+txt     TXT "hello world" "semi;colon" unquoted "esc\"aped\065"
+"#,
+        )
+        .unwrap();
+
+        let text: Vec<String> = records.iter().map(|record| record.to_string()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 900 1209600 300",
+                "example.com. 3600 IN NS ns1.example.com.",
+                "example.com. 3600 IN NS ns2.example.net.",
+                "example.com. 60 IN MX 10 mail.example.com.",
+                "ns1.example.com. 3600 IN A 192.0.2.1",
+                "mail.example.com. 3600 IN A 192.0.2.2",
+                "mail.example.com. 3600 IN AAAA 2001:db8::2",
+                r#"txt.example.com. 3600 IN TXT "hello world" "semi;colon" "unquoted" "esc\"apedA""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn ttls_default_to_the_previous_record_or_the_soa() {
+        let records = parse("ttl", "@ SOA ns1 hostmaster 1 2 3 4 300\nwww A 192.0.2.1\nftp 60 A 192.0.2.2\nmail A 192.0.2.3\n")
+            .unwrap();
+        let ttls: Vec<u32> = records.iter().map(|record| record.ttl()).collect();
+        assert_eq!(ttls, vec![300, 300, 60, 60]);
+    }
+
+    #[test]
+    fn origins_may_be_given_absolute() {
+        let path = write("absolute", &[("zone", "@ 60 A 192.0.2.1\nwww 60 A 192.0.2.2\n")]);
+        let records = load(&path, "Example.COM.").unwrap();
+        let names: Vec<&str> = records.iter().map(|record| record.domain()).collect();
+        assert_eq!(names, vec!["example.com", "www.example.com"]);
+    }
+
+    #[test]
+    fn includes_take_their_own_origin() {
+        let path = write(
+            "include",
+            &[
+                ("main.zone", "$TTL 3600\n$INCLUDE inc.zone inc\nafter A 192.0.2.3\n"),
+                ("inc.zone", "@ 60 A 192.0.2.1\n$ORIGIN other.example.com.\nx A 192.0.2.2\n"),
+            ],
+        );
+        let records = load(&path, "example.com").unwrap();
+        let names: Vec<&str> = records.iter().map(|record| record.domain()).collect();
+        // $ORIGIN in the included file ends with it.
+        assert_eq!(names, vec!["inc.example.com", "x.other.example.com", "after.example.com"]);
+    }
+
+    #[test]
+    fn generic_rdata_is_accepted_for_any_type() {
+        let records = parse("generic", "$TTL 60\ngeneric TYPE999 \\# 3 abcdef\ngen-a A \\# 4 C0000205\n").unwrap();
+        assert_eq!(records[0].to_string(), r"generic.example.com. 60 IN TYPE999 \# 3 abcdef");
+        assert_eq!(records[1].to_string(), "gen-a.example.com. 60 IN A 192.0.2.5");
+
+        let e = parse("generic-length", "$TTL 60\nx TYPE999 \\# 4 abcdef\n").unwrap_err();
+        assert!(e.to_string().contains(":2:"), "{}", e);
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        let cases = [
+            ("x A 192.0.2.1\n", 1, "no TTL"),
+            ("$TTL 60\n\nx A 192.0.2.400\n", 3, "IPv4"),
+            ("$TTL 60\nx FOO 1\n", 2, "unknown record type"),
+            ("$TTL 60\n@ SOA a b ( 1 2 3\n4 5\n", 2, "unbalanced '('"),
+            ("$TTL 60\nx A 192.0.2.1 )\n", 2, "unbalanced ')'"),
+            ("$TTL 60\nx TXT \"abc\n", 2, "unterminated"),
+            ("$TTL 60\n@ SOA a b 1 2 3 4\n", 2, "takes 7 fields"),
+            ("$FOO 1\n", 1, "unknown directive"),
+            ("$TTL\n", 1, "$TTL needs an argument"),
+            ("  A 192.0.2.1\n", 1, "record without an owner"),
+        ];
+        for (i, (text, line, needle)) in cases.into_iter().enumerate() {
+            let e = parse(&format!("error-{}", i), text).unwrap_err().to_string();
+            assert!(e.contains(&format!("zone:{}: ", line)) && e.contains(needle), "{}", e);
+        }
+    }
+
+    #[test]
+    fn reads_dnssec_records_in_presentation_format() {
+        // The examples of RFC 4034 and RFC 5155.
+        let text = r#"
+$ORIGIN example.com.
+$TTL 86400
+@ DNSKEY 256 3 5 ( AQPSKmynfzW4kyBv015MUG2DeIQ3
+                   Cbl+BBZH4b/0PY1kxkmvHjcZc8no
+                   kfzj31GajIQKY+5CptLr3buXA10h
+                   WqTkF7H6RfoRqXQeogmMHfpftf6z
+                   Mv1LyBUgia7za6ZEzOJBOztyvhjL
+                   742iU/TpPSEDhm2SNKLijfUppn1U
+                   aNvv4w==  )
+dskey IN DS 60485 5 1 ( 2BB183AF5F22588179A53B0A98631FAD1A292118 )
+host IN RRSIG A 5 3 86400 20030322173103 (
+                                  20030220173103 2642 example.com.
+                                  oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTr
+                                  PYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6o
+                                  B9wfuh3DTJXUAfI/M0zmO/zz8bW0Rznl8O3t
+                                  GNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkG
+                                  J5D6fwFm8nN+6pBzeDQfsS3Ap3o= )
+alfa IN NSEC host.example.com. ( A MX RRSIG NSEC TYPE1234 )
+0p9mhaveqvm6t7vbl5lop2u3t2rp3tom NSEC3 1 1 12 aabbccdd ( 2t7b4g4vsa5smi47k61mv5bv1a22bojr MX DNSKEY NS SOA NSEC3PARAM RRSIG )
+@ NSEC3PARAM 1 0 12 aabbccdd
+nosalt NSEC3PARAM 1 0 0 -
+"#;
+        let records = parse("dnssec", text).unwrap();
+        let lines: Vec<String> = records.iter().map(|record| record.to_string()).collect();
+        assert_eq!(
+            lines[1..],
+            [
+                "dskey.example.com. 86400 IN DS 60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118",
+                "host.example.com. 86400 IN RRSIG A 5 3 86400 20030322173103 20030220173103 2642 example.com. \
+                 oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTrPYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6oB9wfuh3DTJXUAfI/M0zmO/zz8bW0R\
+                 znl8O3tGNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkGJ5D6fwFm8nN+6pBzeDQfsS3Ap3o=",
+                "alfa.example.com. 86400 IN NSEC host.example.com. A MX RRSIG NSEC TYPE1234",
+                "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example.com. 86400 IN NSEC3 1 1 12 AABBCCDD \
+                 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR NS SOA MX RRSIG DNSKEY NSEC3PARAM",
+                "example.com. 86400 IN NSEC3PARAM 1 0 12 AABBCCDD",
+                "nosalt.example.com. 86400 IN NSEC3PARAM 1 0 0 -",
+            ]
+        );
+
+        // What is printed reads back the same.
+        let again = parse("dnssec-again", &(lines.join("\n") + "\n")).unwrap();
+        assert_eq!(again, records);
+    }
+}